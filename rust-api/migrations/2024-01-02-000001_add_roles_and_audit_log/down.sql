@@ -0,0 +1,10 @@
+-- Drop indexes
+DROP INDEX IF EXISTS idx_audit_log_created_at;
+DROP INDEX IF EXISTS idx_audit_log_subject_id;
+DROP INDEX IF EXISTS idx_audit_log_actor_id;
+
+-- Drop tables
+DROP TABLE IF EXISTS audit_log;
+
+-- Drop columns
+ALTER TABLE users DROP COLUMN IF EXISTS role;
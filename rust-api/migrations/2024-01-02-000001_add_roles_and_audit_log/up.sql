@@ -0,0 +1,22 @@
+-- Add role column to users
+ALTER TABLE users ADD COLUMN role VARCHAR(20) NOT NULL DEFAULT 'user';
+
+-- Create audit log table
+-- actor_id/subject_id are intentionally not foreign keys so records
+-- outlive the users they mention.
+CREATE TABLE audit_log (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    actor_id UUID NOT NULL,
+    subject_id UUID,
+    action VARCHAR(100) NOT NULL,
+    method VARCHAR(10),
+    path TEXT,
+    status_code INTEGER,
+    details JSONB,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Create indexes
+CREATE INDEX idx_audit_log_actor_id ON audit_log(actor_id);
+CREATE INDEX idx_audit_log_subject_id ON audit_log(subject_id);
+CREATE INDEX idx_audit_log_created_at ON audit_log(created_at);
@@ -0,0 +1,5 @@
+-- Drop indexes
+DROP INDEX IF EXISTS idx_registration_invites_created_at;
+
+-- Drop tables
+DROP TABLE IF EXISTS registration_invites;
@@ -0,0 +1,7 @@
+-- Add provisioning columns to users
+-- Deprovisioned users are deactivated rather than deleted.
+ALTER TABLE users ADD COLUMN active BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE users ADD COLUMN external_id VARCHAR(255) UNIQUE;
+
+-- Create indexes
+CREATE INDEX idx_users_active ON users(active);
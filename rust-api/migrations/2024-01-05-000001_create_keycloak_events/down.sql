@@ -0,0 +1,9 @@
+-- Drop indexes
+DROP INDEX IF EXISTS idx_keycloak_events_received_at;
+DROP INDEX IF EXISTS idx_keycloak_events_user_id;
+
+-- Drop tables
+DROP TABLE IF EXISTS keycloak_events;
+
+-- Drop columns
+ALTER TABLE users DROP COLUMN IF EXISTS tokens_valid_after;
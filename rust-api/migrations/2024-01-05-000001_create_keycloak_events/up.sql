@@ -0,0 +1,17 @@
+-- Tokens issued before this time are rejected (session revocation)
+ALTER TABLE users ADD COLUMN tokens_valid_after TIMESTAMP WITH TIME ZONE;
+
+-- Create keycloak events table
+-- Records every webhook delivery so retries are processed only once.
+CREATE TABLE keycloak_events (
+    id VARCHAR(255) PRIMARY KEY,
+    event_type VARCHAR(100) NOT NULL,
+    keycloak_user_id VARCHAR(255),
+    user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    outcome VARCHAR(50) NOT NULL,
+    received_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Create indexes
+CREATE INDEX idx_keycloak_events_user_id ON keycloak_events(user_id);
+CREATE INDEX idx_keycloak_events_received_at ON keycloak_events(received_at);
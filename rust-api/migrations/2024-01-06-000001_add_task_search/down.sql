@@ -0,0 +1,6 @@
+-- Drop indexes
+DROP INDEX IF EXISTS idx_tasks_title_trgm;
+DROP INDEX IF EXISTS idx_tasks_search_vector;
+
+-- Drop columns
+ALTER TABLE tasks DROP COLUMN IF EXISTS search_vector;
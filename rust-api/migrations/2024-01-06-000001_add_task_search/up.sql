@@ -0,0 +1,15 @@
+-- Enable trigram matching for fuzzy search
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- Add full-text search vector to tasks
+-- Maintained by Postgres and queried with raw SQL (see src/task_search.rs),
+-- so it is not part of the Diesel schema.
+ALTER TABLE tasks ADD COLUMN search_vector tsvector
+    GENERATED ALWAYS AS (
+        setweight(to_tsvector('english', coalesce(title, '')), 'A') ||
+        setweight(to_tsvector('english', coalesce(description, '')), 'B')
+    ) STORED;
+
+-- Create indexes
+CREATE INDEX idx_tasks_search_vector ON tasks USING GIN (search_vector);
+CREATE INDEX idx_tasks_title_trgm ON tasks USING GIN (title gin_trgm_ops);
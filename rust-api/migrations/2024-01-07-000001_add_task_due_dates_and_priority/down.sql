@@ -0,0 +1,11 @@
+-- Drop indexes
+DROP INDEX IF EXISTS idx_tasks_user_id_due_date;
+DROP INDEX IF EXISTS idx_tasks_user_id_due_at;
+
+-- Drop columns
+ALTER TABLE tasks DROP CONSTRAINT IF EXISTS tasks_priority_range;
+ALTER TABLE tasks DROP CONSTRAINT IF EXISTS tasks_single_due;
+ALTER TABLE tasks DROP COLUMN IF EXISTS priority;
+ALTER TABLE tasks DROP COLUMN IF EXISTS due_date;
+ALTER TABLE tasks DROP COLUMN IF EXISTS due_at;
+ALTER TABLE users DROP COLUMN IF EXISTS timezone;
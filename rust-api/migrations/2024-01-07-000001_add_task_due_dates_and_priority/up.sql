@@ -0,0 +1,16 @@
+-- Add time zone to users
+-- IANA name (e.g. 'Europe/Berlin') used to compute day-based task views
+ALTER TABLE users ADD COLUMN timezone VARCHAR(64) NOT NULL DEFAULT 'UTC';
+
+-- Add due dates and priority to tasks
+-- A task is due either at an instant (due_at) or on a whole day (due_date).
+-- priority: 0 = none, 1 = low, 2 = medium, 3 = high, 4 = urgent
+ALTER TABLE tasks ADD COLUMN due_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE tasks ADD COLUMN due_date DATE;
+ALTER TABLE tasks ADD COLUMN priority SMALLINT NOT NULL DEFAULT 0;
+ALTER TABLE tasks ADD CONSTRAINT tasks_single_due CHECK (due_at IS NULL OR due_date IS NULL);
+ALTER TABLE tasks ADD CONSTRAINT tasks_priority_range CHECK (priority BETWEEN 0 AND 4);
+
+-- Create indexes
+CREATE INDEX idx_tasks_user_id_due_at ON tasks(user_id, due_at) WHERE due_at IS NOT NULL;
+CREATE INDEX idx_tasks_user_id_due_date ON tasks(user_id, due_date) WHERE due_date IS NOT NULL;
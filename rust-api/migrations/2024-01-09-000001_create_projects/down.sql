@@ -0,0 +1,8 @@
+-- Drop indexes
+DROP INDEX IF EXISTS idx_tasks_project_id;
+
+-- Drop columns
+ALTER TABLE tasks DROP COLUMN IF EXISTS project_id;
+
+-- Drop tables
+DROP TABLE IF EXISTS projects;
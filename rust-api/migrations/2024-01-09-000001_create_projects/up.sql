@@ -0,0 +1,35 @@
+-- Create projects table
+-- Every user has exactly one inbox project, which holds tasks created
+-- without an explicit project.
+CREATE TABLE projects (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    description TEXT,
+    color VARCHAR(7) NOT NULL DEFAULT '#808080',
+    archived BOOLEAN NOT NULL DEFAULT FALSE,
+    is_inbox BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Add project to tasks, moving existing tasks into their owner's inbox
+ALTER TABLE tasks ADD COLUMN project_id UUID REFERENCES projects(id) ON DELETE CASCADE;
+
+INSERT INTO projects (user_id, name, is_inbox)
+SELECT id, 'Inbox', TRUE FROM users;
+
+UPDATE tasks SET project_id = projects.id
+FROM projects
+WHERE projects.user_id = tasks.user_id AND projects.is_inbox;
+
+ALTER TABLE tasks ALTER COLUMN project_id SET NOT NULL;
+
+-- Create indexes
+CREATE INDEX idx_projects_user_id ON projects(user_id);
+CREATE UNIQUE INDEX idx_projects_user_id_inbox ON projects(user_id) WHERE is_inbox;
+CREATE INDEX idx_tasks_project_id ON tasks(project_id);
+
+-- Create triggers
+CREATE TRIGGER update_projects_updated_at BEFORE UPDATE ON projects
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
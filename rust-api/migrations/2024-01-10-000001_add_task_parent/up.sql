@@ -0,0 +1,8 @@
+-- Add parent task for subtasks
+-- Deleting a parent removes its subtree unless the application re-parents
+-- the children first (see SUBTASK_ON_PARENT_DELETE).
+ALTER TABLE tasks ADD COLUMN parent_id UUID REFERENCES tasks(id) ON DELETE CASCADE;
+ALTER TABLE tasks ADD CONSTRAINT tasks_parent_not_self CHECK (parent_id <> id);
+
+-- Create indexes
+CREATE INDEX idx_tasks_parent_id ON tasks(parent_id);
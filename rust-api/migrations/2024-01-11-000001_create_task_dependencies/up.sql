@@ -0,0 +1,13 @@
+-- Create task_dependencies table
+-- A row means task_id is blocked until depends_on_id is completed. Cycles are
+-- rejected by the application inside the inserting transaction.
+CREATE TABLE task_dependencies (
+    task_id UUID NOT NULL REFERENCES tasks(id) ON DELETE CASCADE,
+    depends_on_id UUID NOT NULL REFERENCES tasks(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (task_id, depends_on_id),
+    CONSTRAINT task_dependencies_not_self CHECK (task_id <> depends_on_id)
+);
+
+-- Create indexes
+CREATE INDEX idx_task_dependencies_depends_on_id ON task_dependencies(depends_on_id);
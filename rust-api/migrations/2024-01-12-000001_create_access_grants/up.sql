@@ -0,0 +1,25 @@
+-- Create access_grants table
+-- Each row gives one user a role on exactly one task or project. Owners of a
+-- task or project need no row; they are found through tasks.user_id and
+-- projects.user_id.
+CREATE TABLE access_grants (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    task_id UUID REFERENCES tasks(id) ON DELETE CASCADE,
+    project_id UUID REFERENCES projects(id) ON DELETE CASCADE,
+    role VARCHAR(16) NOT NULL,
+    granted_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    CONSTRAINT access_grants_one_resource CHECK (num_nonnulls(task_id, project_id) = 1),
+    CONSTRAINT access_grants_role CHECK (role IN ('viewer', 'editor', 'owner')),
+    CONSTRAINT access_grants_task_user UNIQUE (task_id, user_id),
+    CONSTRAINT access_grants_project_user UNIQUE (project_id, user_id)
+);
+
+-- Create indexes
+CREATE INDEX idx_access_grants_user_id ON access_grants(user_id);
+
+-- Create triggers
+CREATE TRIGGER update_access_grants_updated_at BEFORE UPDATE ON access_grants
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,17 @@
+-- Create notification_events table
+-- An outbox for the notification layer: rows are written in the transaction
+-- that causes them and marked delivered by the consumer.
+CREATE TABLE notification_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    event_type VARCHAR(64) NOT NULL,
+    recipient_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    actor_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    payload JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMP WITH TIME ZONE
+);
+
+-- Create indexes
+CREATE INDEX idx_notification_events_undelivered ON notification_events(created_at)
+    WHERE delivered_at IS NULL;
+CREATE INDEX idx_notification_events_recipient_id ON notification_events(recipient_id);
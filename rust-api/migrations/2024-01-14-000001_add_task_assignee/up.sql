@@ -0,0 +1,6 @@
+-- Add assignee to tasks
+-- Unassigned when the assignee's account is deleted.
+ALTER TABLE tasks ADD COLUMN assignee_id UUID REFERENCES users(id) ON DELETE SET NULL;
+
+-- Create indexes
+CREATE INDEX idx_tasks_assignee_id ON tasks(assignee_id) WHERE assignee_id IS NOT NULL;
@@ -0,0 +1,21 @@
+-- Create task_comments table
+-- Deleting a comment only sets deleted_at; deleted comments are hidden from
+-- the thread and from comment counts.
+CREATE TABLE task_comments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    task_id UUID NOT NULL REFERENCES tasks(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    body TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    edited_at TIMESTAMP WITH TIME ZONE,
+    deleted_at TIMESTAMP WITH TIME ZONE
+);
+
+-- Create indexes
+CREATE INDEX idx_task_comments_task_id ON task_comments(task_id, created_at) WHERE deleted_at IS NULL;
+CREATE INDEX idx_task_comments_user_id ON task_comments(user_id);
+
+-- Create triggers
+CREATE TRIGGER update_task_comments_updated_at BEFORE UPDATE ON task_comments
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
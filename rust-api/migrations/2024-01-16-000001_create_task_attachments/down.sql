@@ -0,0 +1,6 @@
+-- Drop tables
+DROP TABLE IF EXISTS task_attachments;
+DROP TABLE IF EXISTS blob_deletions;
+
+-- Drop function
+DROP FUNCTION IF EXISTS queue_blob_deletion();
@@ -0,0 +1,40 @@
+-- Create task_attachments table
+-- The file itself lives in the configured blob store under storage_key.
+CREATE TABLE task_attachments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    task_id UUID NOT NULL REFERENCES tasks(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    filename VARCHAR(255) NOT NULL,
+    content_type VARCHAR(255) NOT NULL,
+    size_bytes BIGINT NOT NULL CHECK (size_bytes >= 0),
+    checksum_sha256 VARCHAR(64) NOT NULL,
+    storage_key VARCHAR(512) NOT NULL UNIQUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Create blob_deletions table
+-- Blobs whose attachment row is gone, however it was deleted (directly or
+-- through a task, project or user cascade); the application removes them
+-- from the blob store and then deletes the row here.
+CREATE TABLE blob_deletions (
+    storage_key VARCHAR(512) PRIMARY KEY,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Create indexes
+CREATE INDEX idx_task_attachments_task_id ON task_attachments(task_id, created_at);
+CREATE INDEX idx_task_attachments_user_id ON task_attachments(user_id);
+
+-- Create blob deletion trigger function
+CREATE OR REPLACE FUNCTION queue_blob_deletion()
+RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO blob_deletions (storage_key) VALUES (OLD.storage_key)
+        ON CONFLICT DO NOTHING;
+    RETURN OLD;
+END;
+$$ language 'plpgsql';
+
+-- Create triggers
+CREATE TRIGGER queue_task_attachment_blob_deletion AFTER DELETE ON task_attachments
+    FOR EACH ROW EXECUTE FUNCTION queue_blob_deletion();
@@ -0,0 +1,10 @@
+-- Drop indexes
+DROP INDEX IF EXISTS idx_tasks_series_occurrence;
+
+-- Drop columns
+ALTER TABLE tasks DROP COLUMN IF EXISTS occurrence_at;
+ALTER TABLE tasks DROP COLUMN IF EXISTS series_id;
+
+-- Drop tables
+DROP TABLE IF EXISTS task_series_exceptions;
+DROP TABLE IF EXISTS task_series;
@@ -0,0 +1,42 @@
+-- Create task_series table
+-- A recurring task. dtstart is the first occurrence as a local time in
+-- timezone, so occurrences keep their wall-clock time across DST changes. The
+-- remaining columns are copied into each new occurrence.
+CREATE TABLE task_series (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    rrule TEXT NOT NULL,
+    timezone VARCHAR(64) NOT NULL,
+    dtstart TIMESTAMP NOT NULL,
+    all_day BOOLEAN NOT NULL DEFAULT FALSE,
+    title VARCHAR(200) NOT NULL,
+    description TEXT,
+    priority SMALLINT NOT NULL DEFAULT 0,
+    project_id UUID NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    assignee_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Create task_series_exceptions table
+-- Occurrences that were skipped and must not be created.
+CREATE TABLE task_series_exceptions (
+    series_id UUID NOT NULL REFERENCES task_series(id) ON DELETE CASCADE,
+    occurrence_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (series_id, occurrence_at)
+);
+
+-- Add series to tasks
+-- occurrence_at is the instant the rule scheduled the task for; it stays put
+-- when that one occurrence is rescheduled.
+ALTER TABLE tasks ADD COLUMN series_id UUID REFERENCES task_series(id) ON DELETE SET NULL;
+ALTER TABLE tasks ADD COLUMN occurrence_at TIMESTAMP WITH TIME ZONE;
+
+-- Create indexes
+CREATE UNIQUE INDEX idx_tasks_series_occurrence ON tasks(series_id, occurrence_at);
+CREATE INDEX idx_task_series_user_id ON task_series(user_id);
+
+-- Create triggers
+CREATE TRIGGER update_task_series_updated_at BEFORE UPDATE ON task_series
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,16 @@
+-- Create task_events table
+-- Field-level history of tasks. changes maps each changed field to its
+-- "from" and "to" values. task_id has no foreign key so that a task's
+-- history, including its deletion, outlives the task.
+CREATE TABLE task_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    task_id UUID NOT NULL,
+    actor_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    event_type VARCHAR(20) NOT NULL,
+    changes JSONB NOT NULL DEFAULT '{}',
+    -- clock_timestamp() keeps events of one transaction in order
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT clock_timestamp()
+);
+
+-- Create indexes
+CREATE INDEX idx_task_events_task_id ON task_events(task_id, created_at);
@@ -0,0 +1,10 @@
+-- Drop triggers
+DROP TRIGGER IF EXISTS increment_tasks_version ON tasks;
+DROP TRIGGER IF EXISTS increment_users_version ON users;
+
+-- Drop function
+DROP FUNCTION IF EXISTS increment_version_column();
+
+-- Drop columns
+ALTER TABLE tasks DROP COLUMN IF EXISTS version;
+ALTER TABLE users DROP COLUMN IF EXISTS version;
@@ -0,0 +1,20 @@
+-- Add versions to users and tasks
+-- Incremented by every update; exposed as the ETag for optimistic concurrency.
+ALTER TABLE users ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE tasks ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+
+-- Create version trigger function
+CREATE OR REPLACE FUNCTION increment_version_column()
+RETURNS TRIGGER AS $$
+BEGIN
+    NEW.version = OLD.version + 1;
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+-- Create triggers
+CREATE TRIGGER increment_users_version BEFORE UPDATE ON users
+    FOR EACH ROW EXECUTE FUNCTION increment_version_column();
+
+CREATE TRIGGER increment_tasks_version BEFORE UPDATE ON tasks
+    FOR EACH ROW EXECUTE FUNCTION increment_version_column();
@@ -0,0 +1,5 @@
+-- Drop indexes
+DROP INDEX IF EXISTS idx_tasks_list_position;
+
+-- Drop columns
+ALTER TABLE tasks DROP COLUMN IF EXISTS position;
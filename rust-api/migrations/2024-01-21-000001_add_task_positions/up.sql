@@ -0,0 +1,26 @@
+-- Add manual ordering to tasks
+-- Positions are base-62 keys ordered byte by byte, hence the "C" collation.
+-- A list is a project's top-level tasks or one parent's subtasks; existing
+-- lists keep their creation order.
+ALTER TABLE tasks ADD COLUMN position VARCHAR COLLATE "C" NOT NULL DEFAULT '';
+
+-- Backfill without touching updated_at or version
+ALTER TABLE tasks DISABLE TRIGGER update_tasks_updated_at;
+ALTER TABLE tasks DISABLE TRIGGER increment_tasks_version;
+
+UPDATE tasks SET position = ranked.position
+FROM (
+    SELECT id, lpad(row_number() OVER (
+        PARTITION BY project_id, parent_id ORDER BY created_at, id
+    )::text, 10, '0') || 'V' AS position
+    FROM tasks
+) AS ranked
+WHERE tasks.id = ranked.id;
+
+ALTER TABLE tasks ENABLE TRIGGER update_tasks_updated_at;
+ALTER TABLE tasks ENABLE TRIGGER increment_tasks_version;
+
+ALTER TABLE tasks ALTER COLUMN position DROP DEFAULT;
+
+-- Create indexes
+CREATE INDEX idx_tasks_list_position ON tasks(project_id, parent_id, position);
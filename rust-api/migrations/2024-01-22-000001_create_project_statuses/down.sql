@@ -0,0 +1,16 @@
+-- Drop triggers
+DROP TRIGGER IF EXISTS update_project_statuses_updated_at ON project_statuses;
+DROP TRIGGER IF EXISTS sync_tasks_status ON tasks;
+DROP TRIGGER IF EXISTS create_projects_workflow ON projects;
+
+-- Drop functions
+DROP FUNCTION IF EXISTS sync_task_status();
+DROP FUNCTION IF EXISTS create_project_workflow();
+DROP FUNCTION IF EXISTS create_default_workflow(UUID);
+
+-- Drop columns
+ALTER TABLE tasks DROP COLUMN IF EXISTS status_id;
+
+-- Drop tables
+DROP TABLE IF EXISTS project_status_transitions;
+DROP TABLE IF EXISTS project_statuses;
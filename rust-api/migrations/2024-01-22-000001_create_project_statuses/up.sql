@@ -0,0 +1,129 @@
+-- Create project_statuses table
+-- The workflow statuses of a project, in board column order. Tasks in a done
+-- status are completed.
+CREATE TABLE project_statuses (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    project_id UUID NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    name VARCHAR(50) NOT NULL,
+    done BOOLEAN NOT NULL DEFAULT FALSE,
+    position INTEGER NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Create project_status_transitions table
+-- The moves between statuses a project allows.
+CREATE TABLE project_status_transitions (
+    from_status_id UUID NOT NULL REFERENCES project_statuses(id) ON DELETE CASCADE,
+    to_status_id UUID NOT NULL REFERENCES project_statuses(id) ON DELETE CASCADE,
+    PRIMARY KEY (from_status_id, to_status_id),
+    CONSTRAINT project_status_transitions_not_self CHECK (from_status_id <> to_status_id)
+);
+
+-- Create indexes
+CREATE UNIQUE INDEX idx_project_statuses_name ON project_statuses(project_id, lower(name));
+CREATE INDEX idx_project_status_transitions_to ON project_status_transitions(to_status_id);
+
+-- Create default workflow function
+-- todo -> in_progress -> review -> done, with the steps back and shortcuts
+-- to done that completing a task directly needs.
+CREATE OR REPLACE FUNCTION create_default_workflow(workflow_project_id UUID)
+RETURNS VOID AS $$
+BEGIN
+    INSERT INTO project_statuses (project_id, name, done, position) VALUES
+        (workflow_project_id, 'todo', FALSE, 0),
+        (workflow_project_id, 'in_progress', FALSE, 1),
+        (workflow_project_id, 'review', FALSE, 2),
+        (workflow_project_id, 'done', TRUE, 3);
+
+    INSERT INTO project_status_transitions (from_status_id, to_status_id)
+    SELECT f.id, t.id
+    FROM (VALUES
+        ('todo', 'in_progress'), ('todo', 'done'),
+        ('in_progress', 'todo'), ('in_progress', 'review'), ('in_progress', 'done'),
+        ('review', 'in_progress'), ('review', 'done'),
+        ('done', 'todo')
+    ) AS edges(from_name, to_name)
+    JOIN project_statuses f ON f.project_id = workflow_project_id AND f.name = edges.from_name
+    JOIN project_statuses t ON t.project_id = workflow_project_id AND t.name = edges.to_name;
+END;
+$$ language 'plpgsql';
+
+CREATE OR REPLACE FUNCTION create_project_workflow()
+RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM create_default_workflow(NEW.id);
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+SELECT create_default_workflow(id) FROM projects;
+
+-- Add status to tasks
+ALTER TABLE tasks ADD COLUMN status_id UUID REFERENCES project_statuses(id);
+
+-- Backfill without touching updated_at or version
+ALTER TABLE tasks DISABLE TRIGGER update_tasks_updated_at;
+ALTER TABLE tasks DISABLE TRIGGER increment_tasks_version;
+
+UPDATE tasks SET status_id = project_statuses.id
+FROM project_statuses
+WHERE project_statuses.project_id = tasks.project_id
+  AND project_statuses.name = CASE WHEN tasks.completed THEN 'done' ELSE 'todo' END;
+
+ALTER TABLE tasks ENABLE TRIGGER update_tasks_updated_at;
+ALTER TABLE tasks ENABLE TRIGGER increment_tasks_version;
+
+ALTER TABLE tasks ALTER COLUMN status_id SET NOT NULL;
+
+CREATE INDEX idx_tasks_status_id ON tasks(status_id);
+
+-- Create status sync function
+-- `completed` follows the status. Writing `completed` alone moves the task to
+-- the first status of that kind; a task moved to another project takes the
+-- status of the same name there, or else the first of the same kind.
+CREATE OR REPLACE FUNCTION sync_task_status()
+RETURNS TRIGGER AS $$
+DECLARE
+    current_status project_statuses%ROWTYPE;
+BEGIN
+    SELECT * INTO current_status FROM project_statuses WHERE id = NEW.status_id;
+
+    IF NEW.status_id IS NULL THEN
+        NEW.status_id := (
+            SELECT id FROM project_statuses
+            WHERE project_id = NEW.project_id AND done = NEW.completed
+            ORDER BY position LIMIT 1
+        );
+    ELSIF current_status.project_id <> NEW.project_id THEN
+        NEW.status_id := (
+            SELECT id FROM project_statuses
+            WHERE project_id = NEW.project_id
+            ORDER BY lower(name) = lower(current_status.name) DESC,
+                     done = current_status.done DESC,
+                     position
+            LIMIT 1
+        );
+    ELSIF TG_OP = 'UPDATE' AND NEW.status_id = OLD.status_id
+        AND NEW.completed <> current_status.done THEN
+        NEW.status_id := (
+            SELECT id FROM project_statuses
+            WHERE project_id = NEW.project_id AND done = NEW.completed
+            ORDER BY position LIMIT 1
+        );
+    END IF;
+
+    NEW.completed := (SELECT done FROM project_statuses WHERE id = NEW.status_id);
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+-- Create triggers
+CREATE TRIGGER create_projects_workflow AFTER INSERT ON projects
+    FOR EACH ROW EXECUTE FUNCTION create_project_workflow();
+
+CREATE TRIGGER sync_tasks_status BEFORE INSERT OR UPDATE OF status_id, completed, project_id ON tasks
+    FOR EACH ROW EXECUTE FUNCTION sync_task_status();
+
+CREATE TRIGGER update_project_statuses_updated_at BEFORE UPDATE ON project_statuses
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
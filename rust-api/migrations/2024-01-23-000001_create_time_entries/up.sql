@@ -0,0 +1,22 @@
+-- Create time_entries table
+-- A running timer is an entry without stopped_at; each user has at most one.
+CREATE TABLE time_entries (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    task_id UUID NOT NULL REFERENCES tasks(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    started_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    stopped_at TIMESTAMP WITH TIME ZONE,
+    note TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    CONSTRAINT time_entries_stopped_after_started CHECK (stopped_at IS NULL OR stopped_at > started_at)
+);
+
+-- Create indexes
+CREATE UNIQUE INDEX idx_time_entries_running ON time_entries(user_id) WHERE stopped_at IS NULL;
+CREATE INDEX idx_time_entries_user_started_at ON time_entries(user_id, started_at);
+CREATE INDEX idx_time_entries_task_id ON time_entries(task_id);
+
+-- Create triggers
+CREATE TRIGGER update_time_entries_updated_at BEFORE UPDATE ON time_entries
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,6 @@
+-- Restrict project deletion
+-- A deleted project's tasks go to the trash rather than disappearing with it,
+-- so the application moves them out before removing the project.
+ALTER TABLE tasks DROP CONSTRAINT tasks_project_id_fkey;
+ALTER TABLE tasks ADD CONSTRAINT tasks_project_id_fkey
+    FOREIGN KEY (project_id) REFERENCES projects(id);
@@ -0,0 +1,367 @@
+//! Who may see and change which tasks and projects. A task's creator and the
+//! owner of its project own it; other users need a grant on the task or on
+//! its project.
+
+use diesel::dsl::{exists, select};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::sql_types::Bool;
+use uuid::Uuid;
+
+use crate::{
+    models::{AccessGrant, GrantRole, NewAccessGrant, Project, Task, User},
+    schema::{access_grants, projects, tasks, users},
+};
+
+/// The thing a grant applies to.
+#[derive(Debug, Clone, Copy)]
+pub enum Resource {
+    Task(Uuid),
+    Project(Uuid),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AccessError {
+    /// Missing, or not visible to the user; maps to 404 so that other users'
+    /// tasks and projects can't be probed.
+    #[error("not found")]
+    NotFound,
+    /// Visible, but the user's role is below the one required; maps to 403.
+    #[error("requires {0} access")]
+    Forbidden(GrantRole),
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+type Visibility<T> = Box<dyn BoxableExpression<T, Pg, SqlType = Bool>>;
+
+/// Filter matching the tasks `user_id` can see; deleted tasks are left out.
+pub fn visible_tasks(user_id: Uuid) -> Visibility<tasks::table> {
+    let own_projects = projects::table
+        .filter(projects::user_id.eq(user_id))
+        .select(projects::id);
+    let shared_projects = access_grants::table
+        .filter(access_grants::user_id.eq(user_id))
+        .filter(access_grants::project_id.is_not_null())
+        .select(access_grants::project_id.assume_not_null());
+    let shared_tasks = access_grants::table
+        .filter(access_grants::user_id.eq(user_id))
+        .filter(access_grants::task_id.is_not_null())
+        .select(access_grants::task_id.assume_not_null());
+    Box::new(
+        tasks::deleted_at.is_null().and(
+            tasks::user_id
+                .eq(user_id)
+                .or(tasks::project_id.eq_any(own_projects))
+                .or(tasks::project_id.eq_any(shared_projects))
+                .or(tasks::id.eq_any(shared_tasks)),
+        ),
+    )
+}
+
+/// `visible_tasks` for raw SQL that aliases `tasks` as `t` and binds the
+/// user id as `$1`.
+pub const VISIBLE_TASKS_SQL: &str = "(t.deleted_at IS NULL AND (t.user_id = $1
+    OR t.project_id IN (SELECT id FROM projects WHERE user_id = $1)
+    OR t.project_id IN (SELECT project_id FROM access_grants WHERE user_id = $1 AND project_id IS NOT NULL)
+    OR t.id IN (SELECT task_id FROM access_grants WHERE user_id = $1 AND task_id IS NOT NULL)))";
+
+/// Filter matching the projects `user_id` owns or has been granted.
+pub fn visible_projects(user_id: Uuid) -> Visibility<projects::table> {
+    let shared_projects = access_grants::table
+        .filter(access_grants::user_id.eq(user_id))
+        .filter(access_grants::project_id.is_not_null())
+        .select(access_grants::project_id.assume_not_null());
+    Box::new(
+        projects::user_id
+            .eq(user_id)
+            .or(projects::id.eq_any(shared_projects)),
+    )
+}
+
+/// The user's effective role on `task`, if any.
+pub fn task_role(conn: &mut PgConnection, user_id: Uuid, task: &Task) -> QueryResult<Option<GrantRole>> {
+    role_for(conn, user_id, task.user_id, Some(task.id), task.project_id)
+}
+
+/// The role `user_id` would have on a task owned by `owner_id` in
+/// `project_id`; `task_id` is `None` for a task not created yet.
+pub fn role_for(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    owner_id: Uuid,
+    task_id: Option<Uuid>,
+    project_id: Uuid,
+) -> QueryResult<Option<GrantRole>> {
+    if owner_id == user_id {
+        return Ok(Some(GrantRole::Owner));
+    }
+    let owns_project = select(exists(
+        projects::table
+            .filter(projects::id.eq(project_id))
+            .filter(projects::user_id.eq(user_id)),
+    ))
+    .get_result::<bool>(conn)?;
+    if owns_project {
+        return Ok(Some(GrantRole::Owner));
+    }
+
+    let mut grants = access_grants::table
+        .filter(access_grants::user_id.eq(user_id))
+        .into_boxed();
+    grants = match task_id {
+        Some(task_id) => grants.filter(
+            access_grants::task_id
+                .eq(task_id)
+                .or(access_grants::project_id.eq(project_id)),
+        ),
+        None => grants.filter(access_grants::project_id.eq(project_id)),
+    };
+    let roles: Vec<GrantRole> = grants.select(access_grants::role).load(conn)?;
+    Ok(roles.into_iter().max())
+}
+
+/// The user's effective role on `project`, if any.
+pub fn project_role(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    project: &Project,
+) -> QueryResult<Option<GrantRole>> {
+    if project.user_id == user_id {
+        return Ok(Some(GrantRole::Owner));
+    }
+    access_grants::table
+        .filter(access_grants::user_id.eq(user_id))
+        .filter(access_grants::project_id.eq(project.id))
+        .select(access_grants::role)
+        .first(conn)
+        .optional()
+}
+
+fn require(role: Option<GrantRole>, required: GrantRole) -> Result<GrantRole, AccessError> {
+    match role {
+        None => Err(AccessError::NotFound),
+        Some(role) if role < required => Err(AccessError::Forbidden(required)),
+        Some(role) => Ok(role),
+    }
+}
+
+/// Loads a task the user holds at least `required` on; deleted tasks are not
+/// found.
+pub fn task(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    task_id: Uuid,
+    required: GrantRole,
+) -> Result<(Task, GrantRole), AccessError> {
+    let task: Task = tasks::table
+        .find(task_id)
+        .filter(tasks::deleted_at.is_null())
+        .first(conn)
+        .optional()?
+        .ok_or(AccessError::NotFound)?;
+    let role = require(task_role(conn, user_id, &task)?, required)?;
+    Ok((task, role))
+}
+
+/// Loads a project the user holds at least `required` on.
+pub fn project(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    project_id: Uuid,
+    required: GrantRole,
+) -> Result<(Project, GrantRole), AccessError> {
+    let project: Project = projects::table
+        .find(project_id)
+        .first(conn)
+        .optional()?
+        .ok_or(AccessError::NotFound)?;
+    let role = require(project_role(conn, user_id, &project)?, required)?;
+    Ok((project, role))
+}
+
+/// Gives `user_id` `role` on `resource`, replacing any earlier grant.
+pub fn grant(
+    conn: &mut PgConnection,
+    resource: Resource,
+    user_id: Uuid,
+    role: GrantRole,
+    granted_by: Uuid,
+) -> QueryResult<AccessGrant> {
+    let (task_id, project_id) = match resource {
+        Resource::Task(id) => (Some(id), None),
+        Resource::Project(id) => (None, Some(id)),
+    };
+    let new_grant = NewAccessGrant {
+        user_id,
+        task_id,
+        project_id,
+        role,
+        granted_by: Some(granted_by),
+    };
+    let insert = diesel::insert_into(access_grants::table).values(&new_grant);
+    let update = (
+        access_grants::role.eq(role),
+        access_grants::granted_by.eq(Some(granted_by)),
+    );
+    match resource {
+        Resource::Task(_) => insert
+            .on_conflict((access_grants::task_id, access_grants::user_id))
+            .do_update()
+            .set(update)
+            .get_result(conn),
+        Resource::Project(_) => insert
+            .on_conflict((access_grants::project_id, access_grants::user_id))
+            .do_update()
+            .set(update)
+            .get_result(conn),
+    }
+}
+
+/// Removes `user_id`'s grant on `resource`; returns the number of rows deleted.
+pub fn revoke(conn: &mut PgConnection, resource: Resource, user_id: Uuid) -> QueryResult<usize> {
+    let grants = access_grants::table.filter(access_grants::user_id.eq(user_id));
+    match resource {
+        Resource::Task(id) => {
+            diesel::delete(grants.filter(access_grants::task_id.eq(id))).execute(conn)
+        }
+        Resource::Project(id) => {
+            diesel::delete(grants.filter(access_grants::project_id.eq(id))).execute(conn)
+        }
+    }
+}
+
+/// Grants on `resource` with their users, oldest first; deleted users are
+/// left out.
+pub fn collaborators(
+    conn: &mut PgConnection,
+    resource: Resource,
+) -> QueryResult<Vec<(AccessGrant, User)>> {
+    let query = access_grants::table
+        .inner_join(users::table)
+        .filter(users::deleted_at.is_null())
+        .order(access_grants::created_at.asc())
+        .into_boxed();
+    let query = match resource {
+        Resource::Task(id) => query.filter(access_grants::task_id.eq(id)),
+        Resource::Project(id) => query.filter(access_grants::project_id.eq(id)),
+    };
+    query.load(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        projects,
+        test_support::{pool, task, user},
+    };
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn roles_on_a_shared_project() {
+        let Some(pool) = pool() else { return };
+        let owner = user(&pool);
+        let created = task(&pool, owner.id, json!({"title": "Shared"})).await;
+        let task_id: Uuid = serde_json::from_value(created["id"].clone()).unwrap();
+        let collaborators = [user(&pool), user(&pool), user(&pool)];
+        let conn = &mut pool.get().unwrap();
+        let inbox = projects::inbox(conn, owner.id).unwrap();
+
+        assert_eq!(
+            role_for(conn, owner.id, owner.id, Some(task_id), inbox.id).unwrap(),
+            Some(GrantRole::Owner)
+        );
+        for (role, collaborator) in [GrantRole::Viewer, GrantRole::Editor, GrantRole::Owner]
+            .into_iter()
+            .zip(&collaborators)
+        {
+            assert_eq!(
+                role_for(conn, collaborator.id, owner.id, Some(task_id), inbox.id).unwrap(),
+                None
+            );
+            grant(
+                conn,
+                Resource::Project(inbox.id),
+                collaborator.id,
+                role,
+                owner.id,
+            )
+            .unwrap();
+            assert_eq!(
+                role_for(conn, collaborator.id, owner.id, Some(task_id), inbox.id).unwrap(),
+                Some(role)
+            );
+            // Tasks not created yet are covered by the project's grant too
+            assert_eq!(
+                role_for(conn, collaborator.id, owner.id, None, inbox.id).unwrap(),
+                Some(role)
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn roles_from_a_task_grant() {
+        let Some(pool) = pool() else { return };
+        let owner = user(&pool);
+        let created = task(&pool, owner.id, json!({"title": "Shared"})).await;
+        let task_id: Uuid = serde_json::from_value(created["id"].clone()).unwrap();
+        let collaborators = [user(&pool), user(&pool), user(&pool)];
+        let conn = &mut pool.get().unwrap();
+        let inbox = projects::inbox(conn, owner.id).unwrap();
+
+        for (role, collaborator) in [GrantRole::Viewer, GrantRole::Editor, GrantRole::Owner]
+            .into_iter()
+            .zip(&collaborators)
+        {
+            grant(
+                conn,
+                Resource::Task(task_id),
+                collaborator.id,
+                role,
+                owner.id,
+            )
+            .unwrap();
+            assert_eq!(
+                role_for(conn, collaborator.id, owner.id, Some(task_id), inbox.id).unwrap(),
+                Some(role)
+            );
+            // The grant doesn't reach the rest of the project
+            assert_eq!(
+                role_for(conn, collaborator.id, owner.id, None, inbox.id).unwrap(),
+                None
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn the_higher_of_task_and_project_grants_wins() {
+        let Some(pool) = pool() else { return };
+        let (owner, collaborator) = (user(&pool), user(&pool));
+        let created = task(&pool, owner.id, json!({"title": "Shared"})).await;
+        let task_id: Uuid = serde_json::from_value(created["id"].clone()).unwrap();
+        let conn = &mut pool.get().unwrap();
+        let inbox = projects::inbox(conn, owner.id).unwrap();
+
+        grant(
+            conn,
+            Resource::Project(inbox.id),
+            collaborator.id,
+            GrantRole::Viewer,
+            owner.id,
+        )
+        .unwrap();
+        grant(
+            conn,
+            Resource::Task(task_id),
+            collaborator.id,
+            GrantRole::Editor,
+            owner.id,
+        )
+        .unwrap();
+        assert_eq!(
+            role_for(conn, collaborator.id, owner.id, Some(task_id), inbox.id).unwrap(),
+            Some(GrantRole::Editor)
+        );
+    }
+}
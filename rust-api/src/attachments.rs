@@ -0,0 +1,305 @@
+//! Files attached to tasks: size limits, per-user quotas, content sniffing
+//! and cleanup of blobs whose attachment is gone. Contents live in
+//! `blob_store::STORE`; the database only has their metadata.
+
+use actix_web::web;
+use diesel::prelude::*;
+use diesel::sql_types;
+use log::{error, warn};
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    blob_store::{BlobError, STORE},
+    models::{NewTaskAttachment, TaskAttachment},
+    schema::{blob_deletions, task_attachments, users},
+    DbPool,
+};
+
+/// Policy loaded from the environment at startup.
+pub static POLICY: Lazy<AttachmentPolicy> =
+    Lazy::new(|| AttachmentPolicy::from_env().expect("Invalid attachment policy configuration"));
+
+/// Blobs removed per sweep; whatever is left waits for the next one.
+const SWEEP_BATCH: i64 = 100;
+
+#[derive(Debug)]
+pub struct AttachmentPolicy {
+    /// Largest accepted file
+    pub max_bytes: i64,
+    /// Total size of the attachments a user may have uploaded
+    pub user_quota_bytes: i64,
+    /// How often blobs of deleted attachments are removed from the store
+    pub sweep_interval: std::time::Duration,
+}
+
+impl AttachmentPolicy {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let bytes = |name: &str, default: i64| match std::env::var(name) {
+            Ok(value) => value
+                .parse::<i64>()
+                .ok()
+                .filter(|bytes| *bytes > 0)
+                .ok_or_else(|| anyhow::anyhow!("{} must be a positive number of bytes", name)),
+            Err(_) => Ok(default),
+        };
+
+        let minutes = match std::env::var("ATTACHMENT_SWEEP_INTERVAL_MINUTES") {
+            Ok(value) => value
+                .parse::<u64>()
+                .ok()
+                .filter(|minutes| *minutes > 0)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("ATTACHMENT_SWEEP_INTERVAL_MINUTES must be a positive integer")
+                })?,
+            Err(_) => 10,
+        };
+
+        Ok(Self {
+            max_bytes: bytes("ATTACHMENT_MAX_BYTES", 10 * 1024 * 1024)?,
+            user_quota_bytes: bytes("ATTACHMENT_USER_QUOTA_BYTES", 100 * 1024 * 1024)?,
+            sweep_interval: std::time::Duration::from_secs(minutes * 60),
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AttachmentError {
+    /// Storing the file would take the user over their quota; maps to 403.
+    #[error("Storage quota exceeded: {used} of {quota} bytes used")]
+    QuotaExceeded { used: i64, quota: i64 },
+    #[error(transparent)]
+    Blob(#[from] BlobError),
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+/// A file received in an upload, before it is stored.
+pub struct Upload {
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+/// The type of `data` judged from its contents; what the client claims is
+/// not trusted.
+pub fn sniff_content_type(data: &[u8]) -> String {
+    if let Some(kind) = infer::get(data) {
+        return kind.mime_type().to_string();
+    }
+    match std::str::from_utf8(data) {
+        Ok(text) if !text.contains('\0') => "text/plain; charset=utf-8".to_string(),
+        _ => "application/octet-stream".to_string(),
+    }
+}
+
+/// The last path component of a client-supplied file name, without control
+/// characters, quotes or surrounding whitespace.
+pub fn sanitize_filename(name: Option<&str>) -> String {
+    let base = name
+        .unwrap_or_default()
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default();
+    let cleaned: String = base
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(255)
+        .collect();
+    match cleaned.trim() {
+        "" | "." | ".." => "attachment".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+pub fn checksum(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+fn storage_key(task_id: Uuid, attachment_id: Uuid) -> String {
+    format!("tasks/{}/{}", task_id, attachment_id)
+}
+
+#[derive(QueryableByName)]
+struct UsageRow {
+    #[diesel(sql_type = sql_types::BigInt)]
+    used: i64,
+}
+
+/// Bytes of attachments uploaded by the user.
+pub fn usage(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<i64> {
+    // SUM over BIGINT is NUMERIC in Postgres; the cast keeps it an i64
+    let row: UsageRow = diesel::sql_query(
+        "SELECT COALESCE(SUM(size_bytes), 0)::BIGINT AS used
+         FROM task_attachments WHERE user_id = $1",
+    )
+    .bind::<sql_types::Uuid, _>(user_id)
+    .get_result(conn)?;
+    Ok(row.used)
+}
+
+/// Stores the upload and records it on the task, refusing it if it would
+/// take the uploader over their quota. The blob is written first and removed
+/// again if the row can't be inserted.
+pub async fn create(
+    pool: DbPool,
+    task_id: Uuid,
+    user_id: Uuid,
+    upload: Upload,
+) -> Result<TaskAttachment, AttachmentError> {
+    let id = Uuid::new_v4();
+    let new_attachment = NewTaskAttachment {
+        id,
+        task_id,
+        user_id,
+        content_type: sniff_content_type(&upload.data),
+        size_bytes: upload.data.len() as i64,
+        checksum_sha256: checksum(&upload.data),
+        storage_key: storage_key(task_id, id),
+        filename: upload.filename,
+    };
+
+    blocking(move || {
+        let conn = &mut pool.get().expect("Failed to get DB connection");
+
+        // Cheap check before sending the file to the store
+        let used = usage(conn, user_id)?;
+        check_quota(used, new_attachment.size_bytes)?;
+
+        STORE.put(
+            &new_attachment.storage_key,
+            &upload.data,
+            &new_attachment.content_type,
+        )?;
+
+        let result = conn.transaction::<_, AttachmentError, _>(|conn| {
+            // Serializes concurrent uploads by the same user
+            users::table
+                .find(user_id)
+                .select(users::id)
+                .for_update()
+                .first::<Uuid>(conn)?;
+            let used = usage(conn, user_id)?;
+            check_quota(used, new_attachment.size_bytes)?;
+            Ok(diesel::insert_into(task_attachments::table)
+                .values(&new_attachment)
+                .get_result(conn)?)
+        });
+        if result.is_err() {
+            if let Err(e) = STORE.delete(&new_attachment.storage_key) {
+                warn!(
+                    "Failed to remove blob {}: {}",
+                    new_attachment.storage_key, e
+                );
+            }
+        }
+        result
+    })
+    .await
+}
+
+fn check_quota(used: i64, size: i64) -> Result<(), AttachmentError> {
+    let quota = POLICY.user_quota_bytes;
+    if used + size > quota {
+        return Err(AttachmentError::QuotaExceeded { used, quota });
+    }
+    Ok(())
+}
+
+/// Reads the attachment's contents, checking them against the stored
+/// checksum.
+pub async fn read(attachment: &TaskAttachment) -> Result<Vec<u8>, BlobError> {
+    let key = attachment.storage_key.clone();
+    let expected = attachment.checksum_sha256.clone();
+    blocking(move || verify(&key, STORE.get(&key)?, &expected)).await
+}
+
+fn verify(key: &str, data: Vec<u8>, expected: &str) -> Result<Vec<u8>, BlobError> {
+    if checksum(&data) != expected {
+        return Err(BlobError::Backend(format!("checksum mismatch for {}", key)));
+    }
+    Ok(data)
+}
+
+/// Removes blobs queued by the deletion trigger in the background, without
+/// waiting for the next scheduled sweep.
+pub fn spawn_sweep(pool: DbPool) {
+    actix_web::rt::spawn(run_sweep(pool));
+}
+
+/// Runs `sweep` every `POLICY.sweep_interval` in the background, so blobs
+/// queued by cascades or failed sweeps are removed too.
+pub fn spawn_sweep_job(pool: DbPool) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(POLICY.sweep_interval);
+        loop {
+            interval.tick().await;
+            run_sweep(pool.clone()).await;
+        }
+    });
+}
+
+async fn run_sweep(pool: DbPool) {
+    let result = blocking(move || {
+        let conn = &mut pool.get().expect("Failed to get DB connection");
+        sweep(conn)
+    })
+    .await;
+    if let Err(e) = result {
+        error!("Failed to remove deleted attachment blobs: {}", e);
+    }
+}
+
+fn sweep(conn: &mut PgConnection) -> Result<(), AttachmentError> {
+    let keys: Vec<String> = blob_deletions::table
+        .order(blob_deletions::created_at.asc())
+        .select(blob_deletions::storage_key)
+        .limit(SWEEP_BATCH)
+        .load(conn)?;
+    for key in keys {
+        STORE.delete(&key)?;
+        diesel::delete(blob_deletions::table.find(&key)).execute(conn)?;
+    }
+    Ok(())
+}
+
+// Runs blocking store and database work off the async workers
+async fn blocking<T, E, F>(f: F) -> Result<T, E>
+where
+    F: FnOnce() -> Result<T, E> + Send + 'static,
+    T: Send + 'static,
+    E: From<BlobError> + Send + 'static,
+{
+    web::block(f)
+        .await
+        .unwrap_or_else(|e| Err(BlobError::Backend(e.to_string()).into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_accepts_matching_checksum() {
+        let data = b"hello".to_vec();
+        let expected = checksum(&data);
+        assert_eq!(verify("tasks/a/b", data.clone(), &expected).unwrap(), data);
+    }
+
+    #[test]
+    fn verify_rejects_changed_contents() {
+        let expected = checksum(b"hello");
+        let err = verify("tasks/a/b", b"hellO".to_vec(), &expected).unwrap_err();
+        assert!(
+            matches!(err, BlobError::Backend(message) if message.contains("checksum mismatch"))
+        );
+    }
+
+    #[test]
+    fn checksum_is_hex_sha256() {
+        assert_eq!(
+            checksum(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue},
+    middleware::Next,
+    web, Error, HttpMessage,
+};
+use diesel::prelude::*;
+use log::error;
+use serde_json::json;
+
+use crate::{
+    auth::AuthContext,
+    models::NewAuditLogEntry,
+    schema::audit_log,
+    DbPool,
+};
+
+pub const ACTION_IMPERSONATION_STARTED: &str = "impersonation.started";
+pub const ACTION_IMPERSONATED_REQUEST: &str = "impersonation.request";
+pub const ACTION_INVITE_CREATED: &str = "invite.created";
+pub const ACTION_INVITE_REVOKED: &str = "invite.revoked";
+
+/// Writes an audit record. Failures are logged rather than surfaced so
+/// auditing never turns a successful request into an error.
+pub fn record(conn: &mut PgConnection, entry: NewAuditLogEntry) {
+    if let Err(e) = diesel::insert_into(audit_log::table)
+        .values(&entry)
+        .execute(conn)
+    {
+        error!("Failed to write audit record '{}': {}", entry.action, e);
+    }
+}
+
+/// Flags responses to impersonated requests and records them in the audit log.
+///
+/// Handlers resolve the caller via `handlers::get_auth_context`, which stores
+/// the `AuthContext` in request extensions; this runs after the handler and
+/// picks it up from there.
+pub async fn impersonation_audit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let pool = req.app_data::<web::Data<DbPool>>().cloned();
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+
+    let mut res = next.call(req).await?;
+
+    let context = res.request().extensions().get::<AuthContext>().cloned();
+    let Some(context) = context else {
+        return Ok(res);
+    };
+    let Some(admin_id) = context.impersonator_id else {
+        return Ok(res);
+    };
+
+    if let Ok(value) = HeaderValue::from_str(&admin_id.to_string()) {
+        res.headers_mut()
+            .insert(HeaderName::from_static("x-impersonated-by"), value);
+    }
+
+    if let Some(pool) = pool {
+        match pool.get() {
+            Ok(mut conn) => record(
+                &mut conn,
+                NewAuditLogEntry {
+                    actor_id: admin_id,
+                    subject_id: Some(context.user_id),
+                    action: ACTION_IMPERSONATED_REQUEST.to_string(),
+                    method: Some(method),
+                    path: Some(path),
+                    status_code: Some(i32::from(res.status().as_u16())),
+                    details: Some(json!({ "jti": context.token_id })),
+                },
+            ),
+            Err(e) => error!("Failed to get DB connection for audit record: {}", e),
+        }
+    }
+
+    Ok(res)
+}
@@ -5,11 +5,47 @@ use std::sync::RwLock;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+/// Policy loaded from the environment at startup.
+pub static IMPERSONATION_POLICY: Lazy<ImpersonationPolicy> = Lazy::new(|| {
+    ImpersonationPolicy::from_env().expect("Invalid impersonation policy configuration")
+});
+
+#[derive(Debug)]
+pub struct ImpersonationPolicy {
+    /// Longest lifetime an impersonation token can be given, in minutes
+    pub max_ttl_minutes: i64,
+}
+
+impl ImpersonationPolicy {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let max_ttl_minutes = match std::env::var("IMPERSONATION_MAX_TTL_MINUTES") {
+            Ok(value) => value
+                .parse::<i64>()
+                .ok()
+                .filter(|minutes| *minutes >= 1)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("IMPERSONATION_MAX_TTL_MINUTES must be a positive integer")
+                })?,
+            Err(_) => 60,
+        };
+        Ok(Self { max_ttl_minutes })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, // User ID
     pub exp: i64,    // Expiration time
     pub iat: i64,    // Issued at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>, // Token ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<ActorClaim>, // Acting party (RFC 8693), set on impersonation tokens
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActorClaim {
+    pub sub: String,
 }
 
 impl Claims {
@@ -19,17 +55,83 @@ impl Claims {
             sub: user_id.to_string(),
             exp: (now + Duration::hours(24)).timestamp(),
             iat: now.timestamp(),
+            jti: None,
+            act: None,
+        }
+    }
+
+    pub fn impersonation(user_id: Uuid, admin_id: Uuid, ttl: Duration) -> Self {
+        let now = Utc::now();
+        Self {
+            sub: user_id.to_string(),
+            exp: (now + ttl).timestamp(),
+            iat: now.timestamp(),
+            jti: Some(Uuid::new_v4().to_string()),
+            act: Some(ActorClaim {
+                sub: admin_id.to_string(),
+            }),
         }
     }
 }
 
+/// A bearer token whose signature and expiry have been checked. Its subject
+/// is a local user id or, for OIDC tokens, possibly the IdP's id for a user
+/// provisioned with `external_id`.
+#[derive(Debug, Clone)]
+pub struct VerifiedToken {
+    pub subject: String,
+    pub impersonator_id: Option<Uuid>,
+    pub token_id: Option<String>,
+    pub issued_at: i64,
+}
+
+impl VerifiedToken {
+    /// The context of a request made with this token by the local user it
+    /// resolved to.
+    pub fn for_user(self, user_id: Uuid) -> AuthContext {
+        AuthContext {
+            user_id,
+            impersonator_id: self.impersonator_id,
+            token_id: self.token_id,
+        }
+    }
+}
+
+/// Identity resolved from a bearer token.
+#[derive(Debug, Clone)]
+pub struct AuthContext {
+    pub user_id: Uuid,
+    /// Admin acting on behalf of `user_id`, if this is an impersonation token.
+    pub impersonator_id: Option<Uuid>,
+    pub token_id: Option<String>,
+}
+
+impl AuthContext {
+    pub fn is_impersonated(&self) -> bool {
+        self.impersonator_id.is_some()
+    }
+}
+
 pub fn create_token(user_id: Uuid) -> Result<String, jsonwebtoken::errors::Error> {
-    let claims = Claims::new(user_id);
+    encode_claims(&Claims::new(user_id))
+}
+
+pub fn create_impersonation_token(
+    user_id: Uuid,
+    admin_id: Uuid,
+    ttl: Duration,
+) -> Result<(String, Claims), jsonwebtoken::errors::Error> {
+    let claims = Claims::impersonation(user_id, admin_id, ttl);
+    let token = encode_claims(&claims)?;
+    Ok((token, claims))
+}
+
+fn encode_claims(claims: &Claims) -> Result<String, jsonwebtoken::errors::Error> {
     let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
     
     encode(
         &Header::default(),
-        &claims,
+        claims,
         &EncodingKey::from_secret(secret.as_ref()),
     )
 }
@@ -98,10 +200,83 @@ pub fn verify_token_oidc_blocking(token: &str) -> anyhow::Result<Claims> {
     Ok(data.claims)
 }
 
-pub fn extract_user_id_from_token(token: &str) -> Result<Uuid, Box<dyn std::error::Error>> {
-    let claims = verify_token_oidc_blocking(token).map_err(|e| -> Box<dyn std::error::Error> { Box::from(e.to_string()) })?;
-    let user_id = Uuid::parse_str(&claims.sub)?;
-    Ok(user_id)
+/// Verifies a bearer token and reads who it acts for.
+///
+/// Tokens signed with our own `JWT_SECRET` (HS256) are only accepted as
+/// impersonation tokens, i.e. with an `act` and a `jti` claim; everything else
+/// is checked against the OIDC provider's JWKS.
+pub fn authenticate_token(token: &str) -> Result<VerifiedToken, Box<dyn std::error::Error>> {
+    let header = jsonwebtoken::decode_header(token)?;
+    let claims = if header.alg == Algorithm::HS256 {
+        let claims = verify_token(token)?;
+        if claims.act.is_none() || claims.jti.is_none() {
+            return Err("locally signed tokens must be impersonation tokens".into());
+        }
+        claims
+    } else {
+        verify_token_oidc_blocking(token).map_err(|e| -> Box<dyn std::error::Error> { Box::from(e.to_string()) })?
+    };
+    let impersonator_id = match &claims.act {
+        Some(act) => Some(Uuid::parse_str(&act.sub)?),
+        None => None,
+    };
+    Ok(VerifiedToken {
+        subject: claims.sub,
+        impersonator_id,
+        token_id: claims.jti,
+        issued_at: claims.iat,
+    })
 }
 
+/// Makes the OIDC provider's keys known without fetching them.
+#[cfg(test)]
+pub(crate) fn cache_jwks(jwks: Jwks) {
+    *JWKS_CACHE.write().unwrap() = Some(jwks);
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_secret() {
+        std::env::set_var("JWT_SECRET", "test-secret");
+    }
+
+    #[test]
+    fn accepts_impersonation_tokens() {
+        with_secret();
+        let (user_id, admin_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let (token, claims) =
+            create_impersonation_token(user_id, admin_id, Duration::minutes(5)).unwrap();
+        let verified = authenticate_token(&token).unwrap();
+        assert_eq!(verified.subject, user_id.to_string());
+        assert_eq!(verified.impersonator_id, Some(admin_id));
+        assert_eq!(verified.token_id, claims.jti);
+    }
+
+    #[test]
+    fn rejects_other_locally_signed_tokens() {
+        with_secret();
+        let token = create_token(Uuid::new_v4()).unwrap();
+        assert!(authenticate_token(&token).is_err());
+
+        // An `act` claim alone is not enough
+        let mut claims =
+            Claims::impersonation(Uuid::new_v4(), Uuid::new_v4(), Duration::minutes(5));
+        claims.jti = None;
+        assert!(authenticate_token(&encode_claims(&claims).unwrap()).is_err());
+    }
+
+    #[test]
+    fn rejects_tokens_signed_with_another_secret() {
+        with_secret();
+        let claims = Claims::impersonation(Uuid::new_v4(), Uuid::new_v4(), Duration::minutes(5));
+        let token = encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(b"other"),
+        )
+        .unwrap();
+        assert!(authenticate_token(&token).is_err());
+    }
+}
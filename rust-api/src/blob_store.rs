@@ -0,0 +1,338 @@
+//! Storage for attachment contents. The backend is chosen at startup with
+//! `BLOB_STORE`: `local` keeps files under a directory, `s3` talks to any
+//! S3-compatible service (AWS, MinIO, ...) with path-style requests.
+//!
+//! Stores block on I/O, so call them from `web::block` rather than directly
+//! from a handler.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use once_cell::sync::{Lazy, OnceCell};
+use sha2::{Digest, Sha256};
+
+/// Store loaded from the environment at startup.
+pub static STORE: Lazy<Box<dyn BlobStore>> =
+    Lazy::new(|| from_env().expect("Invalid blob store configuration"));
+
+#[derive(Debug, thiserror::Error)]
+pub enum BlobError {
+    #[error("blob not found")]
+    NotFound,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("blob store request failed: {0}")]
+    Backend(String),
+}
+
+pub trait BlobStore: Send + Sync {
+    fn put(&self, key: &str, data: &[u8], content_type: &str) -> Result<(), BlobError>;
+    fn get(&self, key: &str) -> Result<Vec<u8>, BlobError>;
+    /// Removing a missing blob is not an error.
+    fn delete(&self, key: &str) -> Result<(), BlobError>;
+}
+
+fn from_env() -> anyhow::Result<Box<dyn BlobStore>> {
+    let backend = std::env::var("BLOB_STORE").unwrap_or_else(|_| "local".to_string());
+    match backend.to_lowercase().as_str() {
+        "local" => {
+            let root =
+                std::env::var("BLOB_STORE_PATH").unwrap_or_else(|_| "data/blobs".to_string());
+            Ok(Box::new(LocalBlobStore::new(root)?))
+        }
+        "s3" => Ok(Box::new(S3BlobStore::from_env()?)),
+        other => anyhow::bail!("Unknown BLOB_STORE '{}'", other),
+    }
+}
+
+/// Keys are generated by the application and only contain `[a-z0-9/-]`, so
+/// they map directly onto relative paths and URL paths.
+fn check_key(key: &str) -> Result<(), BlobError> {
+    let valid = !key.is_empty()
+        && key
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != "..")
+        && key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '/' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(BlobError::Backend(format!("invalid blob key '{}'", key)))
+    }
+}
+
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+}
+
+impl BlobStore for LocalBlobStore {
+    fn put(&self, key: &str, data: &[u8], _content_type: &str) -> Result<(), BlobError> {
+        check_key(key)?;
+        let path = self.root.join(key);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Write then rename so readers never see a partial file
+        let partial = path.with_extension("partial");
+        fs::write(&partial, data)?;
+        fs::rename(&partial, &path)?;
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, BlobError> {
+        check_key(key)?;
+        fs::read(self.root.join(key)).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => BlobError::NotFound,
+            _ => BlobError::Io(e),
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), BlobError> {
+        check_key(key)?;
+        match fs::remove_file(self.root.join(key)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(BlobError::Io(e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// S3-compatible store using path-style URLs and SigV4-signed requests.
+pub struct S3BlobStore {
+    endpoint: reqwest::Url,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    // Built on first use, from a blocking thread
+    client: OnceCell<reqwest::blocking::Client>,
+}
+
+impl S3BlobStore {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let var = |name: &str| {
+            std::env::var(name)
+                .map_err(|_| anyhow::anyhow!("{} must be set when BLOB_STORE=s3", name))
+        };
+        let endpoint = var("S3_ENDPOINT")?
+            .parse::<reqwest::Url>()
+            .map_err(|e| anyhow::anyhow!("S3_ENDPOINT is not a valid URL: {}", e))?;
+        Ok(Self {
+            endpoint,
+            bucket: var("S3_BUCKET")?,
+            region: std::env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+            access_key: var("S3_ACCESS_KEY_ID")?,
+            secret_key: var("S3_SECRET_ACCESS_KEY")?,
+            client: OnceCell::new(),
+        })
+    }
+
+    fn client(&self) -> Result<&reqwest::blocking::Client, BlobError> {
+        self.client
+            .get_or_try_init(|| reqwest::blocking::Client::builder().build())
+            .map_err(|e| BlobError::Backend(e.to_string()))
+    }
+
+    /// Sends a signed request for `key` and returns the response if it was
+    /// successful; 404 becomes `NotFound`.
+    fn send(
+        &self,
+        method: reqwest::Method,
+        key: &str,
+        body: Option<(&[u8], &str)>,
+    ) -> Result<reqwest::blocking::Response, BlobError> {
+        check_key(key)?;
+        let mut url = self.endpoint.clone();
+        let path = format!(
+            "{}/{}/{}",
+            url.path().trim_end_matches('/'),
+            self.bucket,
+            key
+        );
+        url.set_path(&path);
+
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex::encode(Sha256::digest(body.map_or(&[][..], |(data, _)| data)));
+
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method,
+            url.path(),
+            host,
+            payload_hash,
+            amz_date,
+            signed_headers,
+            payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = [date.as_str(), self.region.as_str(), "s3", "aws4_request"]
+            .iter()
+            .fold(
+                format!("AWS4{}", self.secret_key).into_bytes(),
+                |key, part| hmac_sha256(&key, part.as_bytes()),
+            );
+        let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, signature
+        );
+
+        let mut request = self
+            .client()?
+            .request(method, url)
+            .header("x-amz-content-sha256", &payload_hash)
+            .header("x-amz-date", &amz_date)
+            .header("authorization", authorization);
+        if let Some((data, content_type)) = body {
+            request = request
+                .header("content-type", content_type)
+                .body(data.to_vec());
+        }
+
+        let response = request
+            .send()
+            .map_err(|e| BlobError::Backend(e.to_string()))?;
+        match response.status() {
+            status if status.is_success() => Ok(response),
+            reqwest::StatusCode::NOT_FOUND => Err(BlobError::NotFound),
+            status => Err(BlobError::Backend(format!("{} returned {}", key, status))),
+        }
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+impl BlobStore for S3BlobStore {
+    fn put(&self, key: &str, data: &[u8], content_type: &str) -> Result<(), BlobError> {
+        self.send(reqwest::Method::PUT, key, Some((data, content_type)))?;
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, BlobError> {
+        let response = self.send(reqwest::Method::GET, key, None)?;
+        response
+            .bytes()
+            .map(|bytes| bytes.to_vec())
+            .map_err(|e| BlobError::Backend(e.to_string()))
+    }
+
+    fn delete(&self, key: &str) -> Result<(), BlobError> {
+        match self.send(reqwest::Method::DELETE, key, None) {
+            Ok(_) | Err(BlobError::NotFound) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store() -> (LocalBlobStore, PathBuf) {
+        let root = std::env::temp_dir().join(format!("blob-store-test-{}", uuid::Uuid::new_v4()));
+        (LocalBlobStore::new(&root).unwrap(), root)
+    }
+
+    #[test]
+    fn check_key_accepts_generated_keys() {
+        assert!(check_key(
+            "tasks/0b8e5a52-1f0c-4f7e-9d51-3a4c2f1e9b7d/a1b2c3d4-0000-4000-8000-000000000000"
+        )
+        .is_ok());
+        assert!(check_key("a").is_ok());
+    }
+
+    #[test]
+    fn check_key_rejects_traversal_and_odd_characters() {
+        for key in [
+            "",
+            "/abs",
+            "trailing/",
+            "a//b",
+            "../etc/passwd",
+            "a/./b",
+            "a/../b",
+            "Upper",
+            "a b",
+            "a\\b",
+            "a.txt",
+            "a%2fb",
+        ] {
+            assert!(check_key(key).is_err(), "{:?} should be rejected", key);
+        }
+    }
+
+    #[test]
+    fn local_store_round_trips_and_deletes() {
+        let (store, root) = temp_store();
+        store.put("tasks/t1/a1", b"contents", "text/plain").unwrap();
+        assert_eq!(store.get("tasks/t1/a1").unwrap(), b"contents");
+
+        store.delete("tasks/t1/a1").unwrap();
+        assert!(matches!(store.get("tasks/t1/a1"), Err(BlobError::NotFound)));
+        // Deleting again is not an error
+        store.delete("tasks/t1/a1").unwrap();
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn local_store_replaces_whole_file_without_leaving_partial() {
+        let (store, root) = temp_store();
+        store
+            .put("tasks/t1/a1", b"a much longer first version", "text/plain")
+            .unwrap();
+        store.put("tasks/t1/a1", b"short", "text/plain").unwrap();
+        assert_eq!(store.get("tasks/t1/a1").unwrap(), b"short");
+
+        let names: Vec<_> = fs::read_dir(root.join("tasks/t1"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec![std::ffi::OsString::from("a1")]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn local_store_checks_keys_before_touching_files() {
+        let (store, root) = temp_store();
+        assert!(matches!(
+            store.put("../escape", b"x", "text/plain"),
+            Err(BlobError::Backend(_))
+        ));
+        assert!(!root.parent().unwrap().join("escape").exists());
+        assert!(matches!(store.get("../escape"), Err(BlobError::Backend(_))));
+        assert!(matches!(
+            store.delete("../escape"),
+            Err(BlobError::Backend(_))
+        ));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+//! Comment threads on tasks: listing, the edit window and comment counts.
+//! Deleted comments keep their row but are left out everywhere.
+
+use std::collections::HashMap;
+
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use once_cell::sync::Lazy;
+use uuid::Uuid;
+
+use crate::{
+    models::{CommentListQuery, CommentResponse, TaskComment, User},
+    schema::{task_comments, users},
+    task_query::{DEFAULT_LIMIT, MAX_LIMIT},
+};
+
+/// Policy loaded from the environment at startup.
+pub static POLICY: Lazy<CommentPolicy> =
+    Lazy::new(|| CommentPolicy::from_env().expect("Invalid comment policy configuration"));
+
+#[derive(Debug)]
+pub struct CommentPolicy {
+    /// How long after posting the author may still edit a comment
+    pub edit_window: Duration,
+}
+
+impl CommentPolicy {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let minutes = match std::env::var("COMMENT_EDIT_WINDOW_MINUTES") {
+            Ok(value) => value
+                .parse::<i64>()
+                .ok()
+                .filter(|minutes| *minutes >= 0)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("COMMENT_EDIT_WINDOW_MINUTES must be a non-negative integer")
+                })?,
+            Err(_) => 15,
+        };
+
+        Ok(Self {
+            edit_window: Duration::minutes(minutes),
+        })
+    }
+}
+
+pub struct CommentPage {
+    pub comments: Vec<CommentResponse>,
+    pub total: i64,
+    pub next_offset: Option<i64>,
+}
+
+/// The task's comments, oldest first.
+pub fn list(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    params: &CommentListQuery,
+) -> QueryResult<CommentPage> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let thread = task_comments::table
+        .filter(task_comments::task_id.eq(task_id))
+        .filter(task_comments::deleted_at.is_null());
+    let total: i64 = thread.count().get_result(conn)?;
+    let rows: Vec<(TaskComment, User)> = thread
+        .inner_join(users::table)
+        .order((task_comments::created_at.asc(), task_comments::id.asc()))
+        .limit(limit)
+        .offset(offset)
+        .load(conn)?;
+
+    let next_offset = Some(offset + limit).filter(|next| *next < total);
+    Ok(CommentPage {
+        comments: rows
+            .into_iter()
+            .map(|(comment, author)| CommentResponse::new(comment, author))
+            .collect(),
+        total,
+        next_offset,
+    })
+}
+
+/// A comment on `task_id` that has not been deleted.
+pub fn find(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    comment_id: Uuid,
+) -> QueryResult<Option<TaskComment>> {
+    task_comments::table
+        .find(comment_id)
+        .filter(task_comments::task_id.eq(task_id))
+        .filter(task_comments::deleted_at.is_null())
+        .first(conn)
+        .optional()
+}
+
+/// Whether the edit window for `comment` is still open.
+pub fn editable(comment: &TaskComment) -> bool {
+    Utc::now() - comment.created_at <= POLICY.edit_window
+}
+
+/// Number of comments on each of `task_ids`; tasks without any are absent.
+pub fn counts_for(conn: &mut PgConnection, task_ids: &[Uuid]) -> QueryResult<HashMap<Uuid, i64>> {
+    let counts: Vec<(Uuid, i64)> = task_comments::table
+        .filter(task_comments::task_id.eq_any(task_ids))
+        .filter(task_comments::deleted_at.is_null())
+        .group_by(task_comments::task_id)
+        .select((task_comments::task_id, diesel::dsl::count_star()))
+        .load(conn)?;
+    Ok(counts.into_iter().collect())
+}
@@ -8,6 +8,7 @@ pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
+#[allow(dead_code)]
 pub fn establish_connection(database_url: &str) -> PgConnection {
     PgConnection::establish(database_url)
         .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
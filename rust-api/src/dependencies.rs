@@ -0,0 +1,315 @@
+//! "Blocked by" edges between tasks: adding them without creating cycles,
+//! the computed `blocked` flag and a work order that respects them.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::sql_types;
+use uuid::Uuid;
+
+use crate::{
+    access,
+    models::{Task, TaskDependency},
+    schema::{task_dependencies, tasks},
+    task_schedule,
+};
+
+/// Serializes changes to the dependency graph so that two concurrent inserts
+/// cannot close a cycle that neither of them sees on its own.
+const GRAPH_LOCK_KEY: i64 = 0x7461_736b_6465_7073;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DependencyError {
+    /// A task cannot depend on itself; maps to 400.
+    #[error("{0}")]
+    Invalid(String),
+    /// The edge already exists or would close a cycle; maps to 409.
+    #[error("{0}")]
+    Conflict(String),
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+#[derive(QueryableByName)]
+struct ReachRow {
+    #[diesel(sql_type = sql_types::Bool)]
+    found: bool,
+}
+
+/// Whether `to` is among the tasks `from` depends on, directly or transitively.
+fn reaches(conn: &mut PgConnection, from: Uuid, to: Uuid) -> QueryResult<bool> {
+    let row: ReachRow = diesel::sql_query(
+        "WITH RECURSIVE reachable AS (
+             SELECT depends_on_id AS id FROM task_dependencies WHERE task_id = $1
+             UNION
+             SELECT d.depends_on_id FROM task_dependencies d JOIN reachable r ON d.task_id = r.id
+         )
+         SELECT EXISTS (SELECT 1 FROM reachable WHERE id = $2) AS found",
+    )
+    .bind::<sql_types::Uuid, _>(from)
+    .bind::<sql_types::Uuid, _>(to)
+    .get_result(conn)?;
+    Ok(row.found)
+}
+
+/// Records that `task_id` is blocked by `depends_on_id`. Both tasks must
+/// already be known to be accessible to the caller.
+pub fn add(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    depends_on_id: Uuid,
+) -> Result<TaskDependency, DependencyError> {
+    if task_id == depends_on_id {
+        return Err(DependencyError::Invalid(
+            "A task cannot depend on itself".to_string(),
+        ));
+    }
+
+    conn.transaction(|conn| {
+        diesel::sql_query("SELECT pg_advisory_xact_lock($1)")
+            .bind::<sql_types::BigInt, _>(GRAPH_LOCK_KEY)
+            .execute(conn)?;
+
+        if reaches(conn, depends_on_id, task_id)? {
+            return Err(DependencyError::Conflict(
+                "Dependency would create a cycle".to_string(),
+            ));
+        }
+
+        diesel::insert_into(task_dependencies::table)
+            .values(&TaskDependency {
+                task_id,
+                depends_on_id,
+                created_at: Utc::now(),
+            })
+            .on_conflict_do_nothing()
+            .get_result(conn)
+            .optional()?
+            .ok_or_else(|| DependencyError::Conflict("Dependency already exists".to_string()))
+    })
+}
+
+/// Removes an edge; returns the number of rows deleted.
+pub fn remove(conn: &mut PgConnection, task_id: Uuid, depends_on_id: Uuid) -> QueryResult<usize> {
+    diesel::delete(
+        task_dependencies::table
+            .filter(task_dependencies::task_id.eq(task_id))
+            .filter(task_dependencies::depends_on_id.eq(depends_on_id)),
+    )
+    .execute(conn)
+}
+
+/// The tasks among `task_ids` that depend on at least one open task; tasks in
+/// the trash block nothing.
+pub fn blocked_ids(conn: &mut PgConnection, task_ids: &[Uuid]) -> QueryResult<HashSet<Uuid>> {
+    let ids: Vec<Uuid> = task_dependencies::table
+        .inner_join(tasks::table)
+        .filter(task_dependencies::task_id.eq_any(task_ids))
+        .filter(tasks::completed.eq(false))
+        .filter(tasks::deleted_at.is_null())
+        .select(task_dependencies::task_id)
+        .distinct()
+        .load(conn)?;
+    Ok(ids.into_iter().collect())
+}
+
+/// What `task_id` depends on and what depends on it, oldest edge first,
+/// limited to tasks visible to `user_id`.
+pub fn edges(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    task_id: Uuid,
+) -> QueryResult<(Vec<Task>, Vec<Task>)> {
+    let depends_on_ids: Vec<Uuid> = task_dependencies::table
+        .filter(task_dependencies::task_id.eq(task_id))
+        .order(task_dependencies::created_at.asc())
+        .select(task_dependencies::depends_on_id)
+        .load(conn)?;
+    let dependent_ids: Vec<Uuid> = task_dependencies::table
+        .filter(task_dependencies::depends_on_id.eq(task_id))
+        .order(task_dependencies::created_at.asc())
+        .select(task_dependencies::task_id)
+        .load(conn)?;
+    Ok((
+        load_in_order(conn, user_id, &depends_on_ids)?,
+        load_in_order(conn, user_id, &dependent_ids)?,
+    ))
+}
+
+fn load_in_order(conn: &mut PgConnection, user_id: Uuid, ids: &[Uuid]) -> QueryResult<Vec<Task>> {
+    let mut by_id: HashMap<Uuid, Task> = tasks::table
+        .filter(tasks::id.eq_any(ids))
+        .filter(access::visible_tasks(user_id))
+        .load::<Task>(conn)?
+        .into_iter()
+        .map(|task| (task.id, task))
+        .collect();
+    Ok(ids.iter().filter_map(|id| by_id.remove(id)).collect())
+}
+
+/// Open dependents of `task_id` visible to `user_id` with no open dependency
+/// left; call after `task_id` has been completed.
+pub fn unblocked_by(conn: &mut PgConnection, user_id: Uuid, task_id: Uuid) -> QueryResult<Vec<Task>> {
+    let dependent_ids: Vec<Uuid> = task_dependencies::table
+        .filter(task_dependencies::depends_on_id.eq(task_id))
+        .select(task_dependencies::task_id)
+        .load(conn)?;
+    let still_blocked = blocked_ids(conn, &dependent_ids)?;
+    let ready: Vec<Uuid> = dependent_ids
+        .into_iter()
+        .filter(|id| !still_blocked.contains(id))
+        .collect();
+    tasks::table
+        .filter(tasks::id.eq_any(&ready))
+        .filter(access::visible_tasks(user_id))
+        .filter(tasks::completed.eq(false))
+        .order((tasks::created_at.asc(), tasks::id.asc()))
+        .load(conn)
+}
+
+/// The open tasks visible to the user, each placed after the tasks it
+/// depends on. Among the tasks available at each step the most urgent goes
+/// first: highest priority, then earliest deadline, then oldest. Tasks waiting
+/// on something that never becomes available (e.g. a cycle) are left out.
+pub fn next(conn: &mut PgConnection, user_id: Uuid, limit: usize) -> QueryResult<Vec<Task>> {
+    let tz = task_schedule::user_timezone(conn, user_id)?;
+    let open: Vec<Task> = tasks::table
+        .filter(access::visible_tasks(user_id))
+        .filter(tasks::completed.eq(false))
+        .load(conn)?;
+    let ids: Vec<Uuid> = open.iter().map(|task| task.id).collect();
+
+    // Only open dependencies hold a task back
+    let edges: Vec<(Uuid, Uuid)> = task_dependencies::table
+        .inner_join(tasks::table)
+        .filter(task_dependencies::task_id.eq_any(&ids))
+        .filter(tasks::completed.eq(false))
+        .filter(tasks::deleted_at.is_null())
+        .select((task_dependencies::task_id, task_dependencies::depends_on_id))
+        .load(conn)?;
+
+    let urgency = |task: &Task| {
+        let due = task
+            .due_at
+            .or_else(|| task.due_date.map(|date| task_schedule::start_of_day(tz, date)));
+        // Tasks without a deadline sort after those with one
+        (Reverse(task.priority), due.is_none(), due, task.created_at)
+    };
+
+    let keys: HashMap<Uuid, _> = open.iter().map(|task| (task.id, urgency(task))).collect();
+    let mut by_id: HashMap<Uuid, Task> = open.into_iter().map(|task| (task.id, task)).collect();
+    Ok(work_order(keys, &edges, limit)
+        .into_iter()
+        .filter_map(|id| by_id.remove(&id))
+        .collect())
+}
+
+// Orders the tasks in `keys` so each comes after everything it depends on
+// (`edges` are `(task_id, depends_on_id)`), taking the smallest key among the
+// available tasks at each step. Tasks waiting on a task outside `keys`, or on
+// a cycle, never become available and are left out.
+fn work_order<K: Ord>(
+    mut keys: HashMap<Uuid, K>,
+    edges: &[(Uuid, Uuid)],
+    limit: usize,
+) -> Vec<Uuid> {
+    let mut waiting_on: HashMap<Uuid, usize> = HashMap::new();
+    let mut dependents: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+    for (task_id, depends_on_id) in edges {
+        *waiting_on.entry(*task_id).or_default() += 1;
+        dependents.entry(*depends_on_id).or_default().push(*task_id);
+    }
+
+    let available: Vec<Uuid> = keys
+        .keys()
+        .filter(|id| !waiting_on.contains_key(id))
+        .copied()
+        .collect();
+    let mut ready: BinaryHeap<_> = available
+        .into_iter()
+        .filter_map(|id| keys.remove(&id).map(|key| Reverse((key, id))))
+        .collect();
+
+    let mut ordered = Vec::new();
+    while ordered.len() < limit {
+        let Some(Reverse((_, task_id))) = ready.pop() else {
+            break;
+        };
+        for dependent_id in dependents.remove(&task_id).unwrap_or_default() {
+            let remaining = waiting_on.get_mut(&dependent_id).expect("counted above");
+            *remaining -= 1;
+            if *remaining == 0 {
+                if let Some(key) = keys.remove(&dependent_id) {
+                    ready.push(Reverse((key, dependent_id)));
+                }
+            }
+        }
+        ordered.push(task_id);
+    }
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(count: u128) -> Vec<Uuid> {
+        (1..=count).map(Uuid::from_u128).collect()
+    }
+
+    fn keys(ids: &[Uuid], urgency: &[u8]) -> HashMap<Uuid, u8> {
+        ids.iter().copied().zip(urgency.iter().copied()).collect()
+    }
+
+    #[test]
+    fn orders_by_urgency_without_dependencies() {
+        let t = ids(3);
+        assert_eq!(
+            work_order(keys(&t, &[2, 0, 1]), &[], 10),
+            [t[1], t[2], t[0]]
+        );
+    }
+
+    #[test]
+    fn places_tasks_after_their_dependencies() {
+        let t = ids(4);
+        // t0 is the most urgent but waits on t3, which waits on t2
+        let edges = [(t[0], t[3]), (t[3], t[2])];
+        assert_eq!(
+            work_order(keys(&t, &[0, 1, 3, 2]), &edges, 10),
+            [t[1], t[2], t[3], t[0]]
+        );
+    }
+
+    #[test]
+    fn waits_for_every_dependency() {
+        let t = ids(3);
+        let edges = [(t[0], t[1]), (t[0], t[2])];
+        assert_eq!(
+            work_order(keys(&t, &[0, 2, 1]), &edges, 10),
+            [t[2], t[1], t[0]]
+        );
+    }
+
+    #[test]
+    fn leaves_out_cycles_and_what_waits_on_them() {
+        let t = ids(4);
+        let edges = [(t[0], t[1]), (t[1], t[0]), (t[2], t[1])];
+        assert_eq!(work_order(keys(&t, &[0, 0, 0, 1]), &edges, 10), [t[3]]);
+    }
+
+    #[test]
+    fn leaves_out_tasks_waiting_on_unknown_tasks() {
+        let t = ids(2);
+        let edges = [(t[0], Uuid::from_u128(99))];
+        assert_eq!(work_order(keys(&t, &[0, 1]), &edges, 10), [t[1]]);
+    }
+
+    #[test]
+    fn stops_at_the_limit() {
+        let t = ids(3);
+        assert_eq!(work_order(keys(&t, &[0, 1, 2]), &[], 2), [t[0], t[1]]);
+    }
+}
@@ -0,0 +1,347 @@
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use log::error;
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+use super::{forbid_impersonation, get_auth_context, require_admin};
+use crate::{
+    audit::{self, ACTION_IMPERSONATION_STARTED, ACTION_INVITE_CREATED, ACTION_INVITE_REVOKED},
+    auth::{create_impersonation_token, IMPERSONATION_POLICY},
+    models::{
+        AuditLogEntry, AuditLogQuery, CreateInviteRequest, ImpersonateRequest, NewAuditLogEntry,
+        NewRegistrationInvite, RegistrationInvite, User, UserResponse,
+    },
+    registration,
+    schema::{audit_log, registration_invites, users},
+    DbPool,
+};
+
+const DEFAULT_IMPERSONATION_TTL_MINUTES: i64 = 15;
+const DEFAULT_AUDIT_LOG_LIMIT: i64 = 100;
+const MAX_AUDIT_LOG_LIMIT: i64 = 500;
+const DEFAULT_INVITE_TTL_HOURS: i64 = 7 * 24;
+
+#[post("/impersonate")]
+pub async fn impersonate(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    request_data: web::Json<ImpersonateRequest>,
+) -> impl Responder {
+    let context = match get_auth_context(&req) {
+        Ok(context) => context,
+        Err(response) => return response,
+    };
+
+    // Impersonation tokens cannot be used to mint further impersonation tokens
+    if let Err(response) = forbid_impersonation(&context) {
+        return response;
+    }
+
+    // Validate input
+    if let Err(validation_errors) = request_data.validate() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "Validation failed",
+            "details": validation_errors
+        }));
+    }
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let admin = match require_admin(conn, context.user_id) {
+        Ok(admin) => admin,
+        Err(response) => return response,
+    };
+
+    let target: User = match users::table
+        .filter(users::id.eq(request_data.user_id))
+        .filter(users::deleted_at.is_null())
+        .first(conn)
+    {
+        Ok(user) => user,
+        Err(diesel::result::Error::NotFound) => {
+            return HttpResponse::NotFound().json(json!({
+                "error": "User not found"
+            }));
+        }
+        Err(e) => {
+            error!("Failed to fetch user: {}", e);
+            return HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to start impersonation"
+            }));
+        }
+    };
+
+    if target.id == admin.id {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "You cannot impersonate yourself"
+        }));
+    }
+
+    if target.is_admin() {
+        return HttpResponse::Forbidden().json(json!({
+            "error": "Administrators cannot be impersonated"
+        }));
+    }
+
+    let ttl_minutes = request_data
+        .ttl_minutes
+        .unwrap_or(DEFAULT_IMPERSONATION_TTL_MINUTES)
+        .min(IMPERSONATION_POLICY.max_ttl_minutes);
+
+    let (token, claims) =
+        match create_impersonation_token(target.id, admin.id, Duration::minutes(ttl_minutes)) {
+            Ok(result) => result,
+            Err(e) => {
+                error!("Failed to create impersonation token: {}", e);
+                return HttpResponse::InternalServerError().json(json!({
+                    "error": "Failed to create authentication token"
+                }));
+            }
+        };
+    let expires_at = DateTime::<Utc>::from_timestamp(claims.exp, 0);
+
+    audit::record(
+        conn,
+        NewAuditLogEntry {
+            actor_id: admin.id,
+            subject_id: Some(target.id),
+            action: ACTION_IMPERSONATION_STARTED.to_string(),
+            method: Some(req.method().to_string()),
+            path: Some(req.path().to_string()),
+            status_code: Some(201),
+            details: Some(json!({
+                "jti": claims.jti,
+                "reason": request_data.reason,
+                "expires_at": expires_at,
+            })),
+        },
+    );
+
+    let user_response: UserResponse = target.into();
+    HttpResponse::Created().json(json!({
+        "message": "Impersonation token issued",
+        "token": token,
+        "expires_at": expires_at,
+        "impersonation": {
+            "impersonator_id": admin.id,
+            "user_id": user_response.id
+        },
+        "user": user_response
+    }))
+}
+
+#[get("/audit-log")]
+pub async fn get_audit_log(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    query: web::Query<AuditLogQuery>,
+) -> impl Responder {
+    let context = match get_auth_context(&req) {
+        Ok(context) => context,
+        Err(response) => return response,
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = require_admin(conn, context.user_id) {
+        return response;
+    }
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_AUDIT_LOG_LIMIT)
+        .clamp(1, MAX_AUDIT_LOG_LIMIT);
+
+    let mut audit_query = audit_log::table.into_boxed();
+    if let Some(actor_id) = query.actor_id {
+        audit_query = audit_query.filter(audit_log::actor_id.eq(actor_id));
+    }
+    if let Some(subject_id) = query.subject_id {
+        audit_query = audit_query.filter(audit_log::subject_id.eq(subject_id));
+    }
+
+    let entries: Vec<AuditLogEntry> = match audit_query
+        .order(audit_log::created_at.desc())
+        .limit(limit)
+        .load(conn)
+    {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Failed to fetch audit log: {}", e);
+            return HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch audit log"
+            }));
+        }
+    };
+
+    HttpResponse::Ok().json(json!({
+        "entries": entries
+    }))
+}
+
+#[post("/invites")]
+pub async fn create_invite(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    invite_data: web::Json<CreateInviteRequest>,
+) -> impl Responder {
+    let context = match get_auth_context(&req) {
+        Ok(context) => context,
+        Err(response) => return response,
+    };
+
+    if let Err(response) = forbid_impersonation(&context) {
+        return response;
+    }
+
+    // Validate input
+    if let Err(validation_errors) = invite_data.validate() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "Validation failed",
+            "details": validation_errors
+        }));
+    }
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let admin = match require_admin(conn, context.user_id) {
+        Ok(admin) => admin,
+        Err(response) => return response,
+    };
+
+    let code = registration::generate_invite_code();
+    let expires_in_hours = invite_data.expires_in_hours.unwrap_or(DEFAULT_INVITE_TTL_HOURS);
+    let new_invite = NewRegistrationInvite {
+        code_hash: registration::hash_invite_code(&code),
+        email: invite_data.email.as_ref().map(|e| e.to_lowercase()),
+        created_by: Some(admin.id),
+        expires_at: Some(Utc::now() + Duration::hours(expires_in_hours)),
+    };
+
+    let invite: RegistrationInvite = match diesel::insert_into(registration_invites::table)
+        .values(&new_invite)
+        .get_result(conn)
+    {
+        Ok(invite) => invite,
+        Err(e) => {
+            error!("Failed to create invite: {}", e);
+            return HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to create invite"
+            }));
+        }
+    };
+
+    audit::record(
+        conn,
+        NewAuditLogEntry {
+            actor_id: admin.id,
+            subject_id: None,
+            action: ACTION_INVITE_CREATED.to_string(),
+            method: Some(req.method().to_string()),
+            path: Some(req.path().to_string()),
+            status_code: Some(201),
+            details: Some(json!({ "invite_id": invite.id, "email": invite.email })),
+        },
+    );
+
+    // The plain code is only ever returned here
+    HttpResponse::Created().json(json!({
+        "message": "Invite created successfully",
+        "code": code,
+        "invite": invite
+    }))
+}
+
+#[get("/invites")]
+pub async fn get_invites(pool: web::Data<DbPool>, req: HttpRequest) -> impl Responder {
+    let context = match get_auth_context(&req) {
+        Ok(context) => context,
+        Err(response) => return response,
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = require_admin(conn, context.user_id) {
+        return response;
+    }
+
+    let invites: Vec<RegistrationInvite> = match registration_invites::table
+        .order(registration_invites::created_at.desc())
+        .load(conn)
+    {
+        Ok(invites) => invites,
+        Err(e) => {
+            error!("Failed to fetch invites: {}", e);
+            return HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch invites"
+            }));
+        }
+    };
+
+    HttpResponse::Ok().json(json!({
+        "invites": invites
+    }))
+}
+
+#[delete("/invites/{id}")]
+pub async fn delete_invite(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let context = match get_auth_context(&req) {
+        Ok(context) => context,
+        Err(response) => return response,
+    };
+    let invite_id = path.into_inner();
+
+    if let Err(response) = forbid_impersonation(&context) {
+        return response;
+    }
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let admin = match require_admin(conn, context.user_id) {
+        Ok(admin) => admin,
+        Err(response) => return response,
+    };
+
+    // Used invites are kept as a record of who joined with them
+    match diesel::delete(
+        registration_invites::table
+            .filter(registration_invites::id.eq(invite_id))
+            .filter(registration_invites::used_at.is_null()),
+    )
+    .execute(conn)
+    {
+        Ok(0) => HttpResponse::NotFound().json(json!({
+            "error": "Invite not found or already used"
+        })),
+        Ok(_) => {
+            audit::record(
+                conn,
+                NewAuditLogEntry {
+                    actor_id: admin.id,
+                    subject_id: None,
+                    action: ACTION_INVITE_REVOKED.to_string(),
+                    method: Some(req.method().to_string()),
+                    path: Some(req.path().to_string()),
+                    status_code: Some(200),
+                    details: Some(json!({ "invite_id": invite_id })),
+                },
+            );
+            HttpResponse::Ok().json(json!({
+                "message": "Invite revoked successfully"
+            }))
+        }
+        Err(e) => {
+            error!("Failed to delete invite: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to revoke invite"
+            }))
+        }
+    }
+}
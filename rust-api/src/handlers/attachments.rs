@@ -0,0 +1,295 @@
+use actix_multipart::Multipart;
+use actix_web::http::header::{
+    Charset, ContentDisposition, DispositionParam, DispositionType, ExtendedValue,
+};
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use diesel::prelude::*;
+use futures_util::StreamExt;
+use log::error;
+use serde_json::json;
+use uuid::Uuid;
+
+use super::{get_current_user_id, tasks::fetch_task};
+use crate::{
+    attachments::{self, AttachmentError, Upload},
+    blob_store::BlobError,
+    models::{GrantRole, TaskAttachment},
+    schema::task_attachments,
+    DbPool,
+};
+
+// Loads an attachment of the task, or the response to send
+#[allow(clippy::result_large_err)]
+fn fetch_attachment(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    attachment_id: Uuid,
+    action: &str,
+) -> Result<TaskAttachment, HttpResponse> {
+    let result = task_attachments::table
+        .find(attachment_id)
+        .filter(task_attachments::task_id.eq(task_id))
+        .first::<TaskAttachment>(conn)
+        .optional();
+    match result {
+        Ok(Some(attachment)) => Ok(attachment),
+        Ok(None) => Err(HttpResponse::NotFound().json(json!({
+            "error": "Attachment not found"
+        }))),
+        Err(e) => {
+            error!("Failed to {}: {}", action, e);
+            Err(HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to {}", action)
+            })))
+        }
+    }
+}
+
+// Reads the `file` field of the form, refusing files over the size limit
+async fn read_upload(mut payload: Multipart) -> Result<Upload, HttpResponse> {
+    let invalid = |message: &str| {
+        HttpResponse::BadRequest().json(json!({
+            "error": message
+        }))
+    };
+    let max_bytes = attachments::POLICY.max_bytes as usize;
+
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(|_| invalid("Invalid multipart body"))?;
+        if field.name() != Some("file") {
+            continue;
+        }
+        let filename = attachments::sanitize_filename(
+            field.content_disposition().and_then(|cd| cd.get_filename()),
+        );
+
+        let mut data = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|_| invalid("Invalid multipart body"))?;
+            if data.len() + chunk.len() > max_bytes {
+                return Err(HttpResponse::PayloadTooLarge().json(json!({
+                    "error": format!("Attachments are limited to {} bytes", max_bytes)
+                })));
+            }
+            data.extend_from_slice(&chunk);
+        }
+        return Ok(Upload { filename, data });
+    }
+    Err(invalid("Missing file field"))
+}
+
+#[get("/{id}/attachments")]
+pub async fn get_attachments(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let task_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = fetch_task(
+        conn,
+        current_user_id,
+        task_id,
+        GrantRole::Viewer,
+        "fetch attachments",
+    ) {
+        return response;
+    }
+
+    let result = task_attachments::table
+        .filter(task_attachments::task_id.eq(task_id))
+        .order((
+            task_attachments::created_at.asc(),
+            task_attachments::id.asc(),
+        ))
+        .load::<TaskAttachment>(conn);
+
+    match result {
+        Ok(attachments) => HttpResponse::Ok().json(json!({
+            "attachments": attachments
+        })),
+        Err(e) => {
+            error!("Failed to fetch attachments: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch attachments"
+            }))
+        }
+    }
+}
+
+#[post("/{id}/attachments")]
+pub async fn upload_attachment(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: Multipart,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let task_id = path.into_inner();
+
+    {
+        let conn = &mut pool.get().expect("Failed to get DB connection");
+        if let Err(response) = fetch_task(
+            conn,
+            current_user_id,
+            task_id,
+            GrantRole::Editor,
+            "upload attachment",
+        ) {
+            return response;
+        }
+    }
+
+    let upload = match read_upload(payload).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+
+    match attachments::create(pool.get_ref().clone(), task_id, current_user_id, upload).await {
+        Ok(attachment) => HttpResponse::Created().json(json!({
+            "message": "Attachment uploaded successfully",
+            "attachment": attachment
+        })),
+        Err(e @ AttachmentError::QuotaExceeded { .. }) => HttpResponse::Forbidden().json(json!({
+            "error": e.to_string()
+        })),
+        Err(e) => {
+            error!("Failed to upload attachment: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to upload attachment"
+            }))
+        }
+    }
+}
+
+#[get("/{id}/attachments/{attachment_id}")]
+pub async fn download_attachment(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let (task_id, attachment_id) = path.into_inner();
+
+    let attachment = {
+        let conn = &mut pool.get().expect("Failed to get DB connection");
+        if let Err(response) = fetch_task(
+            conn,
+            current_user_id,
+            task_id,
+            GrantRole::Viewer,
+            "download attachment",
+        ) {
+            return response;
+        }
+        match fetch_attachment(conn, task_id, attachment_id, "download attachment") {
+            Ok(attachment) => attachment,
+            Err(response) => return response,
+        }
+    };
+
+    let data = match attachments::read(&attachment).await {
+        Ok(data) => data,
+        Err(BlobError::NotFound) => {
+            error!("Blob missing for attachment {}", attachment.id);
+            return HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to download attachment"
+            }));
+        }
+        Err(e) => {
+            error!("Failed to download attachment: {}", e);
+            return HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to download attachment"
+            }));
+        }
+    };
+
+    // Always a download, so stored HTML or SVG never renders in the API's origin
+    let ascii_name: String = attachment
+        .filename
+        .chars()
+        .map(|c| if c.is_ascii() { c } else { '_' })
+        .collect();
+    let disposition = ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![
+            DispositionParam::Filename(ascii_name),
+            DispositionParam::FilenameExt(ExtendedValue {
+                charset: Charset::Ext("UTF-8".to_string()),
+                language_tag: None,
+                value: attachment.filename.clone().into_bytes(),
+            }),
+        ],
+    };
+
+    HttpResponse::Ok()
+        .content_type(attachment.content_type.as_str())
+        .insert_header(disposition)
+        .insert_header(("X-Content-Type-Options", "nosniff"))
+        .insert_header(("X-Checksum-Sha256", attachment.checksum_sha256.as_str()))
+        .body(data)
+}
+
+#[delete("/{id}/attachments/{attachment_id}")]
+pub async fn delete_attachment(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let (task_id, attachment_id) = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let role = match fetch_task(
+        conn,
+        current_user_id,
+        task_id,
+        GrantRole::Editor,
+        "delete attachment",
+    ) {
+        Ok((_, role)) => role,
+        Err(response) => return response,
+    };
+    let attachment = match fetch_attachment(conn, task_id, attachment_id, "delete attachment") {
+        Ok(attachment) => attachment,
+        Err(response) => return response,
+    };
+
+    // Task owners may remove any attachment; others only their own
+    if attachment.user_id != current_user_id && role < GrantRole::Owner {
+        return HttpResponse::Forbidden().json(json!({
+            "error": "Only the uploader or a task owner can delete an attachment"
+        }));
+    }
+
+    match diesel::delete(task_attachments::table.find(attachment_id)).execute(conn) {
+        Ok(_) => {
+            attachments::spawn_sweep(pool.get_ref().clone());
+            HttpResponse::Ok().json(json!({
+                "message": "Attachment deleted successfully"
+            }))
+        }
+        Err(e) => {
+            error!("Failed to delete attachment: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to delete attachment"
+            }))
+        }
+    }
+}
@@ -1,14 +1,18 @@
 use actix_web::{post, web, HttpResponse, Responder};
 use bcrypt::{hash, verify, DEFAULT_COST};
+use chrono::Utc;
 use diesel::prelude::*;
 use log::error;
 use serde_json::json;
 use validator::Validate;
 
+use super::validate_new_user;
 use crate::{
     auth::create_token,
-    models::{CreateUserRequest, LoginRequest, NewUser, User, AuthResponse, UserResponse},
-    schema::users,
+    models::{CreateUserRequest, LoginRequest, NewUser, User, AuthResponse},
+    projects,
+    registration::{self, RegistrationMode},
+    schema::{registration_invites, users},
     DbPool,
 };
 
@@ -17,12 +21,39 @@ pub async fn register(
     pool: web::Data<DbPool>,
     user_data: web::Json<CreateUserRequest>,
 ) -> impl Responder {
-    // Validate input
-    if let Err(validation_errors) = user_data.validate() {
-        return HttpResponse::BadRequest().json(json!({
-            "error": "Validation failed",
-            "details": validation_errors
-        }));
+    // Enforce the registration policy
+    let policy = &*registration::POLICY;
+    let invite_code = user_data
+        .invite_code
+        .as_deref()
+        .map(str::trim)
+        .filter(|code| !code.is_empty());
+    match policy.mode {
+        RegistrationMode::Open => {}
+        RegistrationMode::Closed => {
+            return HttpResponse::Forbidden().json(json!({
+                "error": "Registration is closed"
+            }));
+        }
+        RegistrationMode::InviteOnly => {
+            if invite_code.is_none() {
+                return HttpResponse::Forbidden().json(json!({
+                    "error": "An invite code is required to register"
+                }));
+            }
+        }
+        RegistrationMode::DomainAllowlist => {
+            if !policy.is_domain_allowed(&user_data.email) {
+                return HttpResponse::Forbidden().json(json!({
+                    "error": "Registration is not open for this email domain"
+                }));
+            }
+        }
+    }
+
+    // Validate input, including the password policy
+    if let Err(response) = validate_new_user(&user_data).await {
+        return response;
     }
 
     let conn = &mut pool.get().expect("Failed to get DB connection");
@@ -56,11 +87,54 @@ pub async fn register(
         password_hash,
     };
 
-    let user: User = match diesel::insert_into(users::table)
-        .values(&new_user)
-        .get_result(conn)
-    {
+    // Create the user and consume the invite together, so a code can only be used once
+    let invite_code_hash = match policy.mode {
+        RegistrationMode::InviteOnly => invite_code.map(registration::hash_invite_code),
+        _ => None,
+    };
+    let email = user_data.email.to_lowercase();
+    let user: User = match conn.transaction::<User, diesel::result::Error, _>(|conn| {
+        let user: User = diesel::insert_into(users::table)
+            .values(&new_user)
+            .get_result(conn)?;
+        projects::inbox(conn, user.id)?;
+
+        if let Some(code_hash) = &invite_code_hash {
+            let now = Utc::now();
+            let consumed = diesel::update(
+                registration_invites::table
+                    .filter(registration_invites::code_hash.eq(code_hash))
+                    .filter(registration_invites::used_at.is_null())
+                    .filter(
+                        registration_invites::expires_at
+                            .is_null()
+                            .or(registration_invites::expires_at.gt(now)),
+                    )
+                    .filter(
+                        registration_invites::email
+                            .is_null()
+                            .or(registration_invites::email.eq(&email)),
+                    ),
+            )
+            .set((
+                registration_invites::used_at.eq(now),
+                registration_invites::used_by.eq(user.id),
+            ))
+            .execute(conn)?;
+
+            if consumed == 0 {
+                return Err(diesel::result::Error::RollbackTransaction);
+            }
+        }
+
+        Ok(user)
+    }) {
         Ok(user) => user,
+        Err(diesel::result::Error::RollbackTransaction) => {
+            return HttpResponse::Forbidden().json(json!({
+                "error": "Invite code is invalid, expired or already used"
+            }));
+        }
         Err(e) => {
             error!("Failed to create user: {}", e);
             return HttpResponse::InternalServerError().json(json!({
@@ -110,6 +184,7 @@ pub async fn login(
     // Find user by email
     let user: User = match users::table
         .filter(users::email.eq(&login_data.email))
+        .filter(users::deleted_at.is_null())
         .first(conn)
     {
         Ok(user) => user,
@@ -133,6 +208,13 @@ pub async fn login(
         }));
     }
 
+    // Deactivated (e.g. deprovisioned) accounts cannot sign in
+    if !user.active {
+        return HttpResponse::Forbidden().json(json!({
+            "error": "Account is deactivated"
+        }));
+    }
+
     // Generate JWT token
     let token = match create_token(user.id) {
         Ok(token) => token,
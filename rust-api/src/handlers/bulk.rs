@@ -0,0 +1,314 @@
+use actix_web::{
+    body::MessageBody, http::header::IfMatch, post, web, HttpRequest, HttpResponse, Responder,
+};
+use diesel::prelude::*;
+use log::error;
+use serde_json::{json, Value};
+use uuid::Uuid;
+use validator::Validate;
+
+use super::{
+    get_current_user_id,
+    tasks::{create_task_response, delete_task_response, update_task_response},
+};
+use crate::{
+    models::{BulkMode, BulkOperation, BulkTaskRequest, OccurrenceScope, UpdateTaskRequest},
+    DbPool,
+};
+
+// Why an operation's savepoint was rolled back
+enum OperationRollback {
+    Failed(HttpResponse),
+    Database(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for OperationRollback {
+    fn from(e: diesel::result::Error) -> Self {
+        OperationRollback::Database(e)
+    }
+}
+
+// Why the whole bulk transaction was rolled back
+enum BulkRollback {
+    /// The operation at this index failed in atomic mode
+    Failed(usize, HttpResponse),
+    Database(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for BulkRollback {
+    fn from(e: diesel::result::Error) -> Self {
+        BulkRollback::Database(e)
+    }
+}
+
+// Runs an operation through the same code as its single-item endpoint
+fn apply(conn: &mut PgConnection, user_id: Uuid, operation: &BulkOperation) -> HttpResponse {
+    match operation {
+        BulkOperation::Create { task } => create_task_response(conn, user_id, task),
+        BulkOperation::Update { id, task, scope } => update_task_response(
+            conn,
+            user_id,
+            *id,
+            scope.unwrap_or_default(),
+            task,
+            &IfMatch::Any,
+        ),
+        BulkOperation::Complete { id } => {
+            let task = UpdateTaskRequest {
+                completed: Some(true),
+                ..Default::default()
+            };
+            update_task_response(
+                conn,
+                user_id,
+                *id,
+                OccurrenceScope::This,
+                &task,
+                &IfMatch::Any,
+            )
+        }
+        BulkOperation::Delete { id, scope } => {
+            delete_task_response(conn, user_id, *id, scope.unwrap_or_default(), &IfMatch::Any)
+        }
+    }
+}
+
+// Runs an operation in a savepoint, which is rolled back unless it succeeds
+#[allow(clippy::result_large_err)]
+fn apply_in_savepoint(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    operation: &BulkOperation,
+) -> QueryResult<HttpResponse> {
+    let result = conn.transaction(|conn| {
+        let response = apply(conn, user_id, operation);
+        if response.status().is_success() {
+            Ok(response)
+        } else {
+            Err(OperationRollback::Failed(response))
+        }
+    });
+    match result {
+        Ok(response) | Err(OperationRollback::Failed(response)) => Ok(response),
+        Err(OperationRollback::Database(e)) => Err(e),
+    }
+}
+
+// The status and JSON body an operation would have returned on its own
+fn operation_result(index: usize, response: HttpResponse) -> Value {
+    let status = response.status().as_u16();
+    let body = response
+        .into_body()
+        .try_into_bytes()
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+        .unwrap_or(Value::Null);
+    json!({
+        "index": index,
+        "status": status,
+        "body": body
+    })
+}
+
+#[post("/bulk")]
+#[allow(clippy::result_large_err)]
+pub async fn bulk_tasks(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    bulk_data: web::Json<BulkTaskRequest>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Validate input
+    if let Err(validation_errors) = bulk_data.validate() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "Validation failed",
+            "details": validation_errors
+        }));
+    }
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let result = conn.transaction(|conn| {
+        let mut responses = Vec::with_capacity(bulk_data.operations.len());
+        for (index, operation) in bulk_data.operations.iter().enumerate() {
+            let response = apply_in_savepoint(conn, current_user_id, operation)?;
+            if bulk_data.mode == BulkMode::Atomic && !response.status().is_success() {
+                return Err(BulkRollback::Failed(index, response));
+            }
+            responses.push(response);
+        }
+        Ok(responses)
+    });
+
+    match result {
+        Ok(responses) => {
+            let results: Vec<Value> = responses
+                .into_iter()
+                .enumerate()
+                .map(|(index, response)| operation_result(index, response))
+                .collect();
+            match bulk_data.mode {
+                BulkMode::Atomic => HttpResponse::Ok().json(json!({
+                    "message": "Bulk operations applied successfully",
+                    "results": results
+                })),
+                BulkMode::Partial => HttpResponse::MultiStatus().json(json!({
+                    "results": results
+                })),
+            }
+        }
+        // Reported with the failed operation's own status
+        Err(BulkRollback::Failed(index, response)) => {
+            HttpResponse::build(response.status()).json(json!({
+                "error": format!("Operation {} failed; no changes were made", index),
+                "result": operation_result(index, response)
+            }))
+        }
+        Err(BulkRollback::Database(e)) => {
+            error!("Failed to apply bulk operations: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to apply bulk operations"
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{bearer, pool, send, task, user};
+    use actix_web::{http::StatusCode, test::TestRequest};
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    async fn bulk(pool: &crate::DbPool, user_id: Uuid, body: Value) -> (StatusCode, Value) {
+        send(
+            pool,
+            TestRequest::post()
+                .uri("/api/tasks/bulk")
+                .insert_header(bearer(user_id))
+                .set_json(body),
+        )
+        .await
+    }
+
+    async fn titles(pool: &crate::DbPool, user_id: Uuid) -> Vec<Value> {
+        let (_, body) = send(
+            pool,
+            TestRequest::get()
+                .uri("/api/tasks/?sort=title")
+                .insert_header(bearer(user_id)),
+        )
+        .await;
+        body["tasks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|task| task["title"].clone())
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn atomic_mode_rolls_back_every_operation() {
+        let Some(pool) = pool() else { return };
+        let user = user(&pool);
+
+        let (status, body) = bulk(
+            &pool,
+            user.id,
+            json!({"operations": [
+                {"op": "create", "task": {"title": "Applied first"}},
+                {"op": "complete", "id": Uuid::new_v4()},
+            ]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["result"]["index"], 1);
+        assert!(titles(&pool, user.id).await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn partial_mode_rolls_back_only_the_failed_operation() {
+        let Some(pool) = pool() else { return };
+        let user = user(&pool);
+        let parent = task(&pool, user.id, json!({"title": "Parent"})).await;
+        let reviewed = task(
+            &pool,
+            user.id,
+            json!({"title": "Reviewed", "parent_id": parent["id"]}),
+        )
+        .await;
+        task(
+            &pool,
+            user.id,
+            json!({"title": "Unreviewed", "parent_id": parent["id"]}),
+        )
+        .await;
+        // Tasks must pass review to be done, so completing the parent
+        // completes one subtask before failing on the other
+        let (_, workflow) = send(
+            &pool,
+            TestRequest::put()
+                .uri(&format!("/api/projects/{}/workflow", parent["project_id"].as_str().unwrap()))
+                .insert_header(bearer(user.id))
+                .set_json(json!({
+                    "statuses": [{"name": "todo"}, {"name": "review"}, {"name": "done", "done": true}],
+                    "transitions": [{"from": "todo", "to": "review"}, {"from": "review", "to": "done"}],
+                })),
+        )
+        .await;
+        let review = workflow["statuses"][1]["id"].clone();
+        let (status, _) = bulk(
+            &pool,
+            user.id,
+            json!({"operations": [
+                {"op": "update", "id": parent["id"], "task": {"status_id": review}},
+                {"op": "update", "id": reviewed["id"], "task": {"status_id": review}},
+            ]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = bulk(
+            &pool,
+            user.id,
+            json!({"mode": "partial", "operations": [
+                {"op": "create", "task": {"title": "Before"}},
+                {"op": "complete", "id": parent["id"]},
+                {"op": "create", "task": {"title": "After"}},
+            ]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::MULTI_STATUS);
+        let statuses: Vec<_> = body["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["status"].clone())
+            .collect();
+        assert_eq!(statuses, vec![json!(201), json!(409), json!(201)]);
+
+        let (_, body) = send(
+            &pool,
+            TestRequest::get()
+                .uri(&format!("/api/tasks/{}", reviewed["id"].as_str().unwrap()))
+                .insert_header(bearer(user.id)),
+        )
+        .await;
+        assert_eq!(body["task"]["completed"], json!(false));
+        assert_eq!(body["task"]["status_id"], review);
+        assert_eq!(
+            titles(&pool, user.id).await,
+            vec![
+                json!("After"),
+                json!("Before"),
+                json!("Parent"),
+                json!("Reviewed"),
+                json!("Unreviewed")
+            ]
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! Collaborator management, shared by tasks (`/api/tasks/{id}/collaborators`)
+//! and projects (`/api/projects/{id}/collaborators`).
+
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use diesel::prelude::*;
+use log::error;
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+use super::{get_current_user_id, projects::fetch_project, tasks::fetch_task};
+use crate::{
+    access::{self, Resource},
+    models::{CollaboratorResponse, GrantRole, InviteCollaboratorRequest, User, UserSummary},
+    schema::users,
+    DbPool,
+};
+
+fn resource_noun(resource: Resource) -> &'static str {
+    match resource {
+        Resource::Task(_) => "task",
+        Resource::Project(_) => "project",
+    }
+}
+
+// Loads the resource with at least `required` access and returns its owner
+#[allow(clippy::result_large_err)]
+fn resource_owner(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    resource: Resource,
+    required: GrantRole,
+    action: &str,
+) -> Result<(Uuid, GrantRole), HttpResponse> {
+    match resource {
+        Resource::Task(id) => {
+            fetch_task(conn, user_id, id, required, action).map(|(task, role)| (task.user_id, role))
+        }
+        Resource::Project(id) => fetch_project(conn, user_id, id, required, action)
+            .map(|(project, role)| (project.user_id, role)),
+    }
+}
+
+fn list_response(conn: &mut PgConnection, user_id: Uuid, resource: Resource) -> HttpResponse {
+    let owner_id = match resource_owner(
+        conn,
+        user_id,
+        resource,
+        GrantRole::Viewer,
+        "fetch collaborators",
+    ) {
+        Ok((owner_id, _)) => owner_id,
+        Err(response) => return response,
+    };
+
+    let result = users::table.find(owner_id).first::<User>(conn).and_then(|owner| {
+        let collaborators = access::collaborators(conn, resource)?;
+        Ok((owner, collaborators))
+    });
+
+    match result {
+        Ok((owner, collaborators)) => {
+            let collaborators: Vec<CollaboratorResponse> = collaborators
+                .into_iter()
+                .map(|(grant, user)| CollaboratorResponse::new(grant, user))
+                .collect();
+            HttpResponse::Ok().json(json!({
+                "owner": UserSummary::from(owner),
+                "collaborators": collaborators
+            }))
+        }
+        Err(e) => {
+            error!("Failed to fetch collaborators: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch collaborators"
+            }))
+        }
+    }
+}
+
+fn invite_response(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    resource: Resource,
+    invite: &InviteCollaboratorRequest,
+) -> HttpResponse {
+    // Validate input
+    if let Err(validation_errors) = invite.validate() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "Validation failed",
+            "details": validation_errors
+        }));
+    }
+
+    let owner_id =
+        match resource_owner(conn, user_id, resource, GrantRole::Owner, "add collaborator") {
+            Ok((owner_id, _)) => owner_id,
+            Err(response) => return response,
+        };
+
+    let mut invitee_query = users::table
+        .filter(users::active.eq(true))
+        .filter(users::deleted_at.is_null())
+        .into_boxed();
+    invitee_query = match (&invite.email, &invite.username) {
+        (Some(email), _) => invitee_query.filter(users::email.eq(email.trim())),
+        (None, Some(username)) => invitee_query.filter(users::username.eq(username.trim())),
+        (None, None) => unreachable!("validated above"),
+    };
+    let invitee: User = match invitee_query.first(conn).optional() {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": "User not found"
+            }));
+        }
+        Err(e) => {
+            error!("Failed to fetch user: {}", e);
+            return HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to add collaborator"
+            }));
+        }
+    };
+
+    if invitee.id == owner_id {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!("User already owns this {}", resource_noun(resource))
+        }));
+    }
+
+    match access::grant(conn, resource, invitee.id, invite.role, user_id) {
+        Ok(grant) => HttpResponse::Created().json(json!({
+            "message": "Collaborator added successfully",
+            "collaborator": CollaboratorResponse::new(grant, invitee)
+        })),
+        Err(e) => {
+            error!("Failed to add collaborator: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to add collaborator"
+            }))
+        }
+    }
+}
+
+fn revoke_response(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    resource: Resource,
+    collaborator_id: Uuid,
+) -> HttpResponse {
+    // Collaborators may always remove themselves; anyone else needs ownership
+    let required = if collaborator_id == user_id {
+        GrantRole::Viewer
+    } else {
+        GrantRole::Owner
+    };
+    if let Err(response) = resource_owner(conn, user_id, resource, required, "remove collaborator")
+    {
+        return response;
+    }
+
+    match access::revoke(conn, resource, collaborator_id) {
+        Ok(0) => HttpResponse::NotFound().json(json!({
+            "error": "Collaborator not found"
+        })),
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "message": "Collaborator removed successfully"
+        })),
+        Err(e) => {
+            error!("Failed to remove collaborator: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to remove collaborator"
+            }))
+        }
+    }
+}
+
+#[get("/{id}/collaborators")]
+pub async fn get_task_collaborators(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    list_response(conn, current_user_id, Resource::Task(path.into_inner()))
+}
+
+#[post("/{id}/collaborators")]
+pub async fn add_task_collaborator(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    invite: web::Json<InviteCollaboratorRequest>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    invite_response(conn, current_user_id, Resource::Task(path.into_inner()), &invite)
+}
+
+#[delete("/{id}/collaborators/{user_id}")]
+pub async fn remove_task_collaborator(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let (task_id, collaborator_id) = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    revoke_response(conn, current_user_id, Resource::Task(task_id), collaborator_id)
+}
+
+#[get("/{id}/collaborators")]
+pub async fn get_project_collaborators(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    list_response(conn, current_user_id, Resource::Project(path.into_inner()))
+}
+
+#[post("/{id}/collaborators")]
+pub async fn add_project_collaborator(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    invite: web::Json<InviteCollaboratorRequest>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    invite_response(conn, current_user_id, Resource::Project(path.into_inner()), &invite)
+}
+
+#[delete("/{id}/collaborators/{user_id}")]
+pub async fn remove_project_collaborator(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let (project_id, collaborator_id) = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    revoke_response(conn, current_user_id, Resource::Project(project_id), collaborator_id)
+}
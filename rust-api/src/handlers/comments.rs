@@ -0,0 +1,270 @@
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use diesel::prelude::*;
+use log::error;
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+use super::{get_current_user_id, tasks::fetch_task};
+use crate::{
+    comments,
+    models::{
+        CommentListQuery, CommentRequest, CommentResponse, GrantRole, NewTaskComment, TaskComment,
+        User,
+    },
+    schema::{task_comments, users},
+    DbPool,
+};
+
+// Loads a comment that has not been deleted, or the response to send
+#[allow(clippy::result_large_err)]
+fn fetch_comment(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    comment_id: Uuid,
+    action: &str,
+) -> Result<TaskComment, HttpResponse> {
+    match comments::find(conn, task_id, comment_id) {
+        Ok(Some(comment)) => Ok(comment),
+        Ok(None) => Err(HttpResponse::NotFound().json(json!({
+            "error": "Comment not found"
+        }))),
+        Err(e) => {
+            error!("Failed to {}: {}", action, e);
+            Err(HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to {}", action)
+            })))
+        }
+    }
+}
+
+#[get("/{id}/comments")]
+pub async fn get_comments(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<CommentListQuery>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let task_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = fetch_task(
+        conn,
+        current_user_id,
+        task_id,
+        GrantRole::Viewer,
+        "fetch comments",
+    ) {
+        return response;
+    }
+
+    match comments::list(conn, task_id, &query) {
+        Ok(page) => HttpResponse::Ok().json(json!({
+            "comments": page.comments,
+            "total": page.total,
+            "next_offset": page.next_offset
+        })),
+        Err(e) => {
+            error!("Failed to fetch comments: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch comments"
+            }))
+        }
+    }
+}
+
+#[post("/{id}/comments")]
+pub async fn create_comment(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    comment_data: web::Json<CommentRequest>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let task_id = path.into_inner();
+
+    // Validate input
+    if let Err(validation_errors) = comment_data.validate() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "Validation failed",
+            "details": validation_errors
+        }));
+    }
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = fetch_task(
+        conn,
+        current_user_id,
+        task_id,
+        GrantRole::Editor,
+        "create comment",
+    ) {
+        return response;
+    }
+
+    let new_comment = NewTaskComment {
+        task_id,
+        user_id: current_user_id,
+        body: comment_data.body.trim().to_string(),
+    };
+
+    let result = diesel::insert_into(task_comments::table)
+        .values(&new_comment)
+        .get_result::<TaskComment>(conn)
+        .and_then(|comment| {
+            let author: User = users::table.find(current_user_id).first(conn)?;
+            Ok(CommentResponse::new(comment, author))
+        });
+
+    match result {
+        Ok(comment) => HttpResponse::Created().json(json!({
+            "message": "Comment created successfully",
+            "comment": comment
+        })),
+        Err(e) => {
+            error!("Failed to create comment: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to create comment"
+            }))
+        }
+    }
+}
+
+#[put("/{id}/comments/{comment_id}")]
+pub async fn update_comment(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    comment_data: web::Json<CommentRequest>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let (task_id, comment_id) = path.into_inner();
+
+    // Validate input
+    if let Err(validation_errors) = comment_data.validate() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "Validation failed",
+            "details": validation_errors
+        }));
+    }
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = fetch_task(
+        conn,
+        current_user_id,
+        task_id,
+        GrantRole::Editor,
+        "update comment",
+    ) {
+        return response;
+    }
+    let comment = match fetch_comment(conn, task_id, comment_id, "update comment") {
+        Ok(comment) => comment,
+        Err(response) => return response,
+    };
+
+    if comment.user_id != current_user_id {
+        return HttpResponse::Forbidden().json(json!({
+            "error": "Only the author can edit a comment"
+        }));
+    }
+    if !comments::editable(&comment) {
+        return HttpResponse::Conflict().json(json!({
+            "error": format!(
+                "Comments can only be edited within {} minutes of posting",
+                comments::POLICY.edit_window.num_minutes()
+            )
+        }));
+    }
+
+    let result = diesel::update(task_comments::table.find(comment_id))
+        .set((
+            task_comments::body.eq(comment_data.body.trim()),
+            task_comments::edited_at.eq(Some(Utc::now())),
+        ))
+        .get_result::<TaskComment>(conn)
+        .and_then(|comment| {
+            let author: User = users::table.find(current_user_id).first(conn)?;
+            Ok(CommentResponse::new(comment, author))
+        });
+
+    match result {
+        Ok(comment) => HttpResponse::Ok().json(json!({
+            "message": "Comment updated successfully",
+            "comment": comment
+        })),
+        Err(e) => {
+            error!("Failed to update comment: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to update comment"
+            }))
+        }
+    }
+}
+
+#[delete("/{id}/comments/{comment_id}")]
+pub async fn delete_comment(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let (task_id, comment_id) = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let role = match fetch_task(
+        conn,
+        current_user_id,
+        task_id,
+        GrantRole::Editor,
+        "delete comment",
+    ) {
+        Ok((_, role)) => role,
+        Err(response) => return response,
+    };
+    let comment = match fetch_comment(conn, task_id, comment_id, "delete comment") {
+        Ok(comment) => comment,
+        Err(response) => return response,
+    };
+
+    // Task owners may remove any comment; others only their own
+    if comment.user_id != current_user_id && role < GrantRole::Owner {
+        return HttpResponse::Forbidden().json(json!({
+            "error": "Only the author or a task owner can delete a comment"
+        }));
+    }
+
+    let result = diesel::update(task_comments::table.find(comment_id))
+        .set(task_comments::deleted_at.eq(Some(Utc::now())))
+        .execute(conn);
+
+    match result {
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "message": "Comment deleted successfully"
+        })),
+        Err(e) => {
+            error!("Failed to delete comment: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to delete comment"
+            }))
+        }
+    }
+}
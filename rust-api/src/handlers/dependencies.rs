@@ -0,0 +1,165 @@
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use log::error;
+use serde_json::json;
+use uuid::Uuid;
+
+use super::{get_current_user_id, tasks::fetch_task};
+use crate::{
+    access::{self, AccessError},
+    dependencies::{self, DependencyError},
+    models::{CreateDependencyRequest, GrantRole},
+    task_responses, DbPool,
+};
+
+#[get("/{id}/dependencies")]
+pub async fn get_dependencies(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let task_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = fetch_task(
+        conn,
+        current_user_id,
+        task_id,
+        GrantRole::Viewer,
+        "fetch task dependencies",
+    ) {
+        return response;
+    }
+
+    // Only linked tasks the user can see are listed
+    let result = dependencies::edges(conn, current_user_id, task_id).and_then(
+        |(depends_on, dependents)| {
+            Ok((
+                task_responses::build(conn, depends_on)?,
+                task_responses::build(conn, dependents)?,
+            ))
+        },
+    );
+
+    match result {
+        Ok((depends_on, dependents)) => HttpResponse::Ok().json(json!({
+            "depends_on": depends_on,
+            "dependents": dependents
+        })),
+        Err(e) => {
+            error!("Failed to fetch task dependencies: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch task dependencies"
+            }))
+        }
+    }
+}
+
+#[post("/{id}/dependencies")]
+pub async fn add_dependency(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    dependency_data: web::Json<CreateDependencyRequest>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let task_id = path.into_inner();
+    let depends_on_id = dependency_data.depends_on_id;
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = fetch_task(
+        conn,
+        current_user_id,
+        task_id,
+        GrantRole::Editor,
+        "add task dependency",
+    ) {
+        return response;
+    }
+    let depends_on = match access::task(conn, current_user_id, depends_on_id, GrantRole::Viewer) {
+        Ok((depends_on, _)) => depends_on,
+        Err(AccessError::NotFound | AccessError::Forbidden(_)) => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": "Dependency task not found"
+            }));
+        }
+        Err(AccessError::Database(e)) => {
+            error!("Failed to fetch task: {}", e);
+            return HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to add task dependency"
+            }));
+        }
+    };
+
+    let result = dependencies::add(conn, task_id, depends_on_id).and_then(|dependency| {
+        Ok((dependency, task_responses::build_one(conn, depends_on)?))
+    });
+
+    match result {
+        Ok((dependency, depends_on)) => HttpResponse::Created().json(json!({
+            "message": "Task dependency added successfully",
+            "dependency": dependency,
+            "depends_on": depends_on
+        })),
+        Err(DependencyError::Invalid(message)) => HttpResponse::BadRequest().json(json!({
+            "error": message
+        })),
+        Err(DependencyError::Conflict(message)) => HttpResponse::Conflict().json(json!({
+            "error": message
+        })),
+        Err(DependencyError::Database(e)) => {
+            error!("Failed to add task dependency: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to add task dependency"
+            }))
+        }
+    }
+}
+
+#[delete("/{id}/dependencies/{depends_on_id}")]
+pub async fn remove_dependency(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let (task_id, depends_on_id) = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = fetch_task(
+        conn,
+        current_user_id,
+        task_id,
+        GrantRole::Editor,
+        "remove task dependency",
+    ) {
+        return response;
+    }
+
+    match dependencies::remove(conn, task_id, depends_on_id) {
+        Ok(0) => HttpResponse::NotFound().json(json!({
+            "error": "Task dependency not found"
+        })),
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "message": "Task dependency removed successfully"
+        })),
+        Err(e) => {
+            error!("Failed to remove task dependency: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to remove task dependency"
+            }))
+        }
+    }
+}
@@ -1,5 +1,307 @@
+pub mod admin;
+pub mod attachments;
 pub mod auth;
+pub mod bulk;
+pub mod collaborators;
+pub mod comments;
+pub mod dependencies;
 pub mod health;
+pub mod projects;
+pub mod tags;
 pub mod tasks;
+pub mod time_entries;
+pub mod trash;
 pub mod users;
+pub mod workflows;
 pub mod oidc;
+pub mod scim;
+pub mod webhooks;
+
+use actix_web::{
+    http::header::{self, EntityTag, Header, IfMatch, IfNoneMatch, ETag},
+    web, HttpMessage, HttpRequest, HttpResponse,
+};
+use diesel::prelude::*;
+use log::error;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    auth::{authenticate_token, AuthContext, VerifiedToken},
+    models::{CreateUserRequest, User},
+    schema,
+    DbPool,
+};
+
+// Helper function to resolve the caller from the Authorization header.
+// The context is stored in request extensions so middleware can see who
+// made the request (e.g. for impersonation auditing).
+#[allow(clippy::result_large_err)]
+pub fn get_auth_context(req: &HttpRequest) -> Result<AuthContext, HttpResponse> {
+    let auth_header = req
+        .headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+
+    match auth_header {
+        Some(token) => {
+            let verified = authenticate_token(token).map_err(|_| {
+                HttpResponse::Unauthorized().json(json!({
+                    "error": "Invalid or expired token"
+                }))
+            })?;
+            let context = resolve_session(req, verified)?;
+            req.extensions_mut().insert(context.clone());
+            Ok(context)
+        }
+        None => Err(HttpResponse::Unauthorized().json(json!({
+            "error": "Authorization header required"
+        }))),
+    }
+}
+
+// Helper function to find the local user a token subject or Keycloak user id
+// names: the user with that id or, for users provisioned through SCIM, that
+// `external_id`. An id match wins.
+pub fn find_user_by_subject(conn: &mut PgConnection, subject: &str) -> QueryResult<Option<User>> {
+    let id = Uuid::parse_str(subject).ok();
+    let mut query = schema::users::table
+        .filter(schema::users::external_id.eq(subject))
+        .into_boxed();
+    if let Some(id) = id {
+        query = query.or_filter(schema::users::id.eq(id));
+    }
+    let mut users: Vec<User> = query.load(conn)?;
+    users.sort_by_key(|user| Some(user.id) != id);
+    Ok(users.into_iter().next())
+}
+
+// Helper function to resolve a token to its local user, rejecting tokens of
+// deactivated or deleted users and tokens issued before the user's sessions
+// were revoked (see `users.tokens_valid_after`)
+#[allow(clippy::result_large_err)]
+fn resolve_session(req: &HttpRequest, verified: VerifiedToken) -> Result<AuthContext, HttpResponse> {
+    let Some(pool) = req.app_data::<web::Data<DbPool>>() else {
+        error!("No database pool to resolve the token's user");
+        return Err(HttpResponse::InternalServerError().json(json!({
+            "error": "Failed to verify session"
+        })));
+    };
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let user = match find_user_by_subject(conn, &verified.subject) {
+        Ok(user) => user,
+        Err(e) => {
+            error!("Failed to check session: {}", e);
+            return Err(HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to verify session"
+            })));
+        }
+    };
+    // Tokens are only good for users known here, e.g. not for users the IdP
+    // has but SCIM never provisioned
+    let Some(user) = user else {
+        return Err(HttpResponse::Unauthorized().json(json!({
+            "error": "Unknown user"
+        })));
+    };
+
+    if user.deleted_at.is_some() {
+        return Err(HttpResponse::Unauthorized().json(json!({
+            "error": "Account has been deleted"
+        })));
+    }
+    if !user.active {
+        return Err(HttpResponse::Unauthorized().json(json!({
+            "error": "Account is deactivated"
+        })));
+    }
+    // `iat` only has whole seconds, so a token from the same second as the
+    // revocation is treated as issued before it
+    if let Some(valid_after) = user.tokens_valid_after {
+        if verified.issued_at <= valid_after.timestamp() {
+            return Err(HttpResponse::Unauthorized().json(json!({
+                "error": "Token has been revoked"
+            })));
+        }
+    }
+    Ok(verified.for_user(user.id))
+}
+
+// Helper function to extract user ID from Authorization header
+#[allow(clippy::result_large_err)]
+pub fn get_current_user_id(req: &HttpRequest) -> Result<Uuid, HttpResponse> {
+    get_auth_context(req).map(|context| context.user_id)
+}
+
+// Helper function to reject operations that must not run under an impersonation token
+#[allow(clippy::result_large_err)]
+pub fn forbid_impersonation(context: &AuthContext) -> Result<(), HttpResponse> {
+    if context.is_impersonated() {
+        return Err(HttpResponse::Forbidden().json(json!({
+            "error": "This operation is not allowed while impersonating a user"
+        })));
+    }
+    Ok(())
+}
+
+// Helper function to load the caller and ensure they have the admin role
+#[allow(clippy::result_large_err)]
+pub fn require_admin(conn: &mut PgConnection, user_id: Uuid) -> Result<User, HttpResponse> {
+    match schema::users::table.filter(schema::users::id.eq(user_id)).first::<User>(conn) {
+        Ok(user) if user.is_admin() => Ok(user),
+        Ok(_) | Err(diesel::result::Error::NotFound) => Err(HttpResponse::Forbidden().json(json!({
+            "error": "Admin role required"
+        }))),
+        Err(e) => {
+            error!("Failed to fetch user: {}", e);
+            Err(HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to verify permissions"
+            })))
+        }
+    }
+}
+
+// Helper function to validate a new user, including the password policy. The
+// breached password check may read range files from disk, so it runs on the
+// blocking thread pool.
+pub async fn validate_new_user(user_data: &CreateUserRequest) -> Result<(), HttpResponse> {
+    let user_data = user_data.clone();
+    match web::block(move || user_data.validate_with_password_policy()).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(validation_errors)) => Err(HttpResponse::BadRequest().json(json!({
+            "error": "Validation failed",
+            "details": validation_errors
+        }))),
+        Err(e) => {
+            error!("Failed to validate user: {}", e);
+            Err(HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to validate user"
+            })))
+        }
+    }
+}
+
+// Helper function to build the ETag of a versioned task or user
+pub fn etag(version: i32) -> ETag {
+    ETag(EntityTag::new_strong(version.to_string()))
+}
+
+// Helper function to build the ETag of a representation that also shows
+// things outside the resource's version (comment counts, blockers, ...): the
+// version followed by a digest of the body, so `If-None-Match` sees those
+// changes while `If-Match` still only compares the version
+pub fn representation_etag(version: i32, body: &serde_json::Value) -> ETag {
+    let digest = Sha256::digest(body.to_string().as_bytes());
+    ETag(EntityTag::new_strong(format!(
+        "{}-{}",
+        version,
+        hex::encode(&digest[..8])
+    )))
+}
+
+// The version an entity tag from `etag` or `representation_etag` names
+fn tag_version(tag: &EntityTag) -> Option<i32> {
+    let tag = tag.tag();
+    tag.split_once('-')
+        .map_or(tag, |(version, _)| version)
+        .parse()
+        .ok()
+}
+
+// Helper function to read the caller's `If-Match` precondition. A missing header
+// is treated as `*`; a header with no valid entity tags matches nothing.
+pub fn if_match(req: &HttpRequest) -> IfMatch {
+    if !req.headers().contains_key(header::IF_MATCH) {
+        return IfMatch::Any;
+    }
+    IfMatch::parse(req).unwrap_or(IfMatch::Items(Vec::new()))
+}
+
+// Helper function to reject a change made against a stale copy of a resource
+#[allow(clippy::result_large_err)]
+pub fn check_if_match(precondition: &IfMatch, version: i32) -> Result<(), HttpResponse> {
+    let matches = match precondition {
+        IfMatch::Any => true,
+        IfMatch::Items(tags) => tags
+            .iter()
+            .any(|tag| !tag.weak && tag_version(tag) == Some(version)),
+    };
+    if matches {
+        Ok(())
+    } else {
+        Err(precondition_failed())
+    }
+}
+
+// Helper function for the response to a failed `If-Match`
+pub fn precondition_failed() -> HttpResponse {
+    HttpResponse::PreconditionFailed().json(json!({
+        "error": "The resource has been modified; fetch it again and retry"
+    }))
+}
+
+// Helper function to answer a read with 304 when the caller's `If-None-Match`
+// already names the current ETag
+pub fn not_modified(req: &HttpRequest, current: ETag) -> Option<HttpResponse> {
+    let matches = match IfNoneMatch::parse(req) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&current)),
+        Err(_) => false,
+    };
+    matches.then(|| HttpResponse::NotModified().insert_header(current).finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bearer, pool, send, user};
+    use actix_web::{http::StatusCode, test::TestRequest};
+    use chrono::Utc;
+
+    fn list_projects(subject: impl ToString) -> TestRequest {
+        TestRequest::get()
+            .uri("/api/projects/")
+            .insert_header(bearer(subject))
+    }
+
+    #[actix_web::test]
+    async fn rejects_tokens_of_unknown_users() {
+        let Some(pool) = pool() else { return };
+        let (status, _) = send(&pool, list_projects(Uuid::new_v4())).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn rejects_tokens_of_deactivated_users() {
+        let Some(pool) = pool() else { return };
+        let user = user(&pool);
+        let (status, _) = send(&pool, list_projects(user.id)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        diesel::update(schema::users::table.find(user.id))
+            .set(schema::users::active.eq(false))
+            .execute(&mut pool.get().unwrap())
+            .unwrap();
+        let (status, body) = send(&pool, list_projects(user.id)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["error"], "Account is deactivated");
+    }
+
+    #[actix_web::test]
+    async fn rejects_tokens_issued_before_logout() {
+        let Some(pool) = pool() else { return };
+        let user = user(&pool);
+        let request = list_projects(user.id);
+
+        diesel::update(schema::users::table.find(user.id))
+            .set(schema::users::tokens_valid_after.eq(Utc::now()))
+            .execute(&mut pool.get().unwrap())
+            .unwrap();
+        let (status, body) = send(&pool, request).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["error"], "Token has been revoked");
+    }
+}
@@ -2,6 +2,7 @@ use actix_web::{dev::Payload, Error as ActixError, FromRequest, HttpRequest};
 use futures_util::future::{ready, Ready};
 
 
+#[allow(dead_code)]
 #[derive(Clone, Debug)]
 pub struct OidcUser {
     pub sub: String,
@@ -0,0 +1,350 @@
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use diesel::prelude::*;
+use log::error;
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+use super::{get_current_user_id, tasks::create_task_response};
+use crate::{
+    access::{self, AccessError},
+    models::{
+        CreateProjectRequest, CreateTaskRequest, GrantRole, NewProject, Project,
+        ProjectListQuery, TaskListQuery, UpdateProjectRequest,
+    },
+    schema::projects,
+    task_query::{self, TaskQueryError},
+    task_responses, trash, DbPool,
+};
+
+fn project_not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(json!({
+        "error": "Project not found"
+    }))
+}
+
+// Loads a project the user holds at least `required` on; projects the user
+// can't see are reported as not found
+#[allow(clippy::result_large_err)]
+pub(super) fn fetch_project(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    project_id: Uuid,
+    required: GrantRole,
+    action: &str,
+) -> Result<(Project, GrantRole), HttpResponse> {
+    match access::project(conn, user_id, project_id, required) {
+        Ok(found) => Ok(found),
+        Err(AccessError::NotFound) => Err(project_not_found()),
+        Err(AccessError::Forbidden(role)) => Err(HttpResponse::Forbidden().json(json!({
+            "error": format!("Requires {} access to this project", role)
+        }))),
+        Err(AccessError::Database(e)) => {
+            error!("Failed to fetch project: {}", e);
+            Err(HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to {}", action)
+            })))
+        }
+    }
+}
+
+#[get("/")]
+pub async fn get_projects(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    query: web::Query<ProjectListQuery>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    // Projects shared with the user are listed alongside their own
+    let mut projects_query = projects::table
+        .filter(access::visible_projects(current_user_id))
+        .into_boxed();
+    if !query.include_archived.unwrap_or(false) {
+        projects_query = projects_query.filter(projects::archived.eq(false));
+    }
+
+    // The inbox is listed first
+    match projects_query
+        .order((projects::is_inbox.desc(), projects::name.asc()))
+        .load::<Project>(conn)
+    {
+        Ok(projects) => HttpResponse::Ok().json(json!({
+            "projects": projects
+        })),
+        Err(e) => {
+            error!("Failed to fetch projects: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch projects"
+            }))
+        }
+    }
+}
+
+#[get("/{id}")]
+pub async fn get_project(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let project_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    match fetch_project(conn, current_user_id, project_id, GrantRole::Viewer, "fetch project") {
+        Ok((project, role)) => HttpResponse::Ok().json(json!({
+            "project": project,
+            "role": role
+        })),
+        Err(response) => response,
+    }
+}
+
+#[post("/")]
+pub async fn create_project(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    project_data: web::Json<CreateProjectRequest>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Validate input
+    if let Err(validation_errors) = project_data.validate() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "Validation failed",
+            "details": validation_errors
+        }));
+    }
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let new_project = NewProject {
+        user_id: current_user_id,
+        name: project_data.name.clone(),
+        description: project_data.description.clone(),
+        color: project_data.color.clone(),
+        is_inbox: false,
+    };
+
+    match diesel::insert_into(projects::table)
+        .values(&new_project)
+        .get_result::<Project>(conn)
+    {
+        Ok(project) => HttpResponse::Created().json(json!({
+            "message": "Project created successfully",
+            "project": project
+        })),
+        Err(e) => {
+            error!("Failed to create project: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to create project"
+            }))
+        }
+    }
+}
+
+#[put("/{id}")]
+pub async fn update_project(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    project_data: web::Json<UpdateProjectRequest>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let project_id = path.into_inner();
+
+    // Validate input
+    if let Err(validation_errors) = project_data.validate() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "Validation failed",
+            "details": validation_errors
+        }));
+    }
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let (existing_project, _) = match fetch_project(
+        conn,
+        current_user_id,
+        project_id,
+        GrantRole::Owner,
+        "update project",
+    ) {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    // New tasks land in the inbox, so it has to stay open
+    if existing_project.is_inbox && project_data.archived == Some(true) {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "The inbox cannot be archived"
+        }));
+    }
+
+    match diesel::update(projects::table.filter(projects::id.eq(project_id)))
+        .set((
+            project_data.name.as_ref().map(|n| projects::name.eq(n)),
+            project_data
+                .description
+                .as_ref()
+                .map(|d| projects::description.eq(d)),
+            project_data.color.as_ref().map(|c| projects::color.eq(c)),
+            project_data.archived.map(|a| projects::archived.eq(a)),
+        ))
+        .get_result::<Project>(conn)
+    {
+        Ok(project) => HttpResponse::Ok().json(json!({
+            "message": "Project updated successfully",
+            "project": project
+        })),
+        Err(e) => {
+            error!("Failed to update project: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to update project"
+            }))
+        }
+    }
+}
+
+#[delete("/{id}")]
+pub async fn delete_project(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let project_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let (existing_project, _) = match fetch_project(
+        conn,
+        current_user_id,
+        project_id,
+        GrantRole::Owner,
+        "delete project",
+    ) {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    if existing_project.is_inbox {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "The inbox cannot be deleted"
+        }));
+    }
+
+    // The project's tasks go to the trash
+    let result = conn.transaction(|conn| {
+        trash::delete_project(conn, &existing_project, current_user_id)?;
+        diesel::delete(projects::table.filter(projects::id.eq(project_id))).execute(conn)
+    });
+
+    match result {
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "message": "Project deleted successfully"
+        })),
+        Err(e) => {
+            error!("Failed to delete project: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to delete project"
+            }))
+        }
+    }
+}
+
+#[get("/{id}/tasks")]
+pub async fn get_project_tasks(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<TaskListQuery>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let project_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) =
+        fetch_project(conn, current_user_id, project_id, GrantRole::Viewer, "fetch tasks")
+    {
+        return response;
+    }
+
+    let mut params = query.into_inner();
+    params.project_id = Some(project_id);
+
+    let result = task_query::load_page(conn, current_user_id, &params).and_then(|page| {
+        let task_responses = task_responses::build(conn, page.tasks)?;
+        Ok((task_responses, page.next_cursor, page.total))
+    });
+
+    match result {
+        Ok((task_responses, next_cursor, total)) => {
+            let mut body = json!({
+                "tasks": task_responses,
+                "next_cursor": next_cursor
+            });
+            if let Some(total) = total {
+                body["total"] = json!(total);
+            }
+            HttpResponse::Ok().json(body)
+        }
+        Err(TaskQueryError::InvalidParameter(message)) => HttpResponse::BadRequest().json(json!({
+            "error": message
+        })),
+        Err(e) => {
+            error!("Failed to fetch tasks: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch tasks"
+            }))
+        }
+    }
+}
+
+#[post("/{id}/tasks")]
+pub async fn create_project_task(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    task_data: web::Json<CreateTaskRequest>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let project_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) =
+        fetch_project(conn, current_user_id, project_id, GrantRole::Editor, "create task")
+    {
+        return response;
+    }
+
+    let mut task_data = task_data.into_inner();
+    task_data.project_id = Some(project_id);
+    create_task_response(conn, current_user_id, &task_data)
+}
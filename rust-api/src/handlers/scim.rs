@@ -0,0 +1,359 @@
+use actix_web::{
+    delete, get, http::StatusCode, patch, post, put, web, HttpRequest, HttpResponse, Responder,
+};
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use log::{error, info};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    models::{NewScimUser, User, UNUSABLE_PASSWORD_HASH},
+    projects,
+    schema::users,
+    scim::{
+        filtered_users_query, ScimError, ScimListQuery, ScimPatchRequest, ScimUser,
+        ScimUserRequest, ScimUserState, CONTENT_TYPE, LIST_RESPONSE_SCHEMA,
+    },
+    DbPool,
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 200;
+
+// Helper function to check the IdP's bearer token against SCIM_BEARER_TOKEN
+#[allow(clippy::result_large_err)]
+fn authorize(req: &HttpRequest) -> Result<(), HttpResponse> {
+    let expected = match std::env::var("SCIM_BEARER_TOKEN") {
+        Ok(token) if !token.is_empty() => token,
+        _ => {
+            return Err(ScimError::new(
+                StatusCode::UNAUTHORIZED,
+                None,
+                "SCIM provisioning is not enabled",
+            )
+            .into_response());
+        }
+    };
+
+    let provided = req
+        .headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .unwrap_or_default();
+
+    // Compare digests so the comparison time does not depend on the secret
+    if Sha256::digest(provided.as_bytes()) != Sha256::digest(expected.as_bytes()) {
+        return Err(
+            ScimError::new(StatusCode::UNAUTHORIZED, None, "Invalid bearer token").into_response(),
+        );
+    }
+    Ok(())
+}
+
+fn scim_user_response(status: StatusCode, user: User) -> HttpResponse {
+    let location = format!("/scim/v2/Users/{}", user.id);
+    HttpResponse::build(status)
+        .content_type(CONTENT_TYPE)
+        .insert_header(("Location", location))
+        .json(ScimUser::from(user))
+}
+
+fn not_found(user_id: Uuid) -> HttpResponse {
+    ScimError::new(
+        StatusCode::NOT_FOUND,
+        None,
+        format!("User {} not found", user_id),
+    )
+    .into_response()
+}
+
+fn database_error(e: DieselError, action: &str) -> HttpResponse {
+    match e {
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => ScimError::new(
+            StatusCode::CONFLICT,
+            Some("uniqueness"),
+            "A user with this userName, email or externalId already exists",
+        )
+        .into_response(),
+        e => {
+            error!("Failed to {}: {}", action, e);
+            ScimError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                None,
+                format!("Failed to {}", action),
+            )
+            .into_response()
+        }
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn find_user(conn: &mut PgConnection, user_id: Uuid) -> Result<User, HttpResponse> {
+    users::table
+        .filter(users::id.eq(user_id))
+        .filter(users::deleted_at.is_null())
+        .first(conn)
+        .map_err(|e| match e {
+            DieselError::NotFound => not_found(user_id),
+            e => database_error(e, "fetch user"),
+        })
+}
+
+fn save_state(conn: &mut PgConnection, user_id: Uuid, state: ScimUserState) -> HttpResponse {
+    match diesel::update(users::table.filter(users::id.eq(user_id)))
+        .set((
+            users::username.eq(state.user_name),
+            users::email.eq(state.email),
+            users::external_id.eq(state.external_id),
+            users::active.eq(state.active),
+        ))
+        .get_result::<User>(conn)
+    {
+        Ok(user) => {
+            if !user.active {
+                info!("SCIM deactivated user {}", user.id);
+            }
+            scim_user_response(StatusCode::OK, user)
+        }
+        Err(e) => database_error(e, "update user"),
+    }
+}
+
+#[get("")]
+pub async fn get_users(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    query: web::Query<ScimListQuery>,
+) -> impl Responder {
+    if let Err(response) = authorize(&req) {
+        return response;
+    }
+
+    // startIndex is 1-based
+    let start_index = query.start_index.unwrap_or(1).max(1);
+    let count = query
+        .count
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(0, MAX_PAGE_SIZE);
+    let filter = query.filter.as_deref();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let total: i64 = match filtered_users_query(filter) {
+        Ok(users_query) => match users_query.count().get_result(conn) {
+            Ok(total) => total,
+            Err(e) => return database_error(e, "count users"),
+        },
+        Err(e) => return e.into_response(),
+    };
+
+    let page: Vec<User> = match filtered_users_query(filter) {
+        Ok(users_query) => match users_query
+            .order((users::created_at.asc(), users::id.asc()))
+            .offset(start_index - 1)
+            .limit(count)
+            .load(conn)
+        {
+            Ok(page) => page,
+            Err(e) => return database_error(e, "fetch users"),
+        },
+        Err(e) => return e.into_response(),
+    };
+
+    let resources: Vec<ScimUser> = page.into_iter().map(ScimUser::from).collect();
+    HttpResponse::Ok().content_type(CONTENT_TYPE).json(json!({
+        "schemas": [LIST_RESPONSE_SCHEMA],
+        "totalResults": total,
+        "startIndex": start_index,
+        "itemsPerPage": resources.len(),
+        "Resources": resources
+    }))
+}
+
+#[get("/{id}")]
+pub async fn get_user(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    if let Err(response) = authorize(&req) {
+        return response;
+    }
+    let user_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    match find_user(conn, user_id) {
+        Ok(user) => scim_user_response(StatusCode::OK, user),
+        Err(response) => response,
+    }
+}
+
+#[post("")]
+pub async fn create_user(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    user_data: web::Json<ScimUserRequest>,
+) -> impl Responder {
+    if let Err(response) = authorize(&req) {
+        return response;
+    }
+
+    let state = match ScimUserState::from_request(&user_data) {
+        Ok(state) => state,
+        Err(e) => return e.into_response(),
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    // Provisioned users sign in through the IdP, so they get no local password
+    let new_user = NewScimUser {
+        username: state.user_name,
+        email: state.email,
+        password_hash: UNUSABLE_PASSWORD_HASH.to_string(),
+        external_id: state.external_id,
+        active: state.active,
+    };
+
+    match conn.transaction::<User, DieselError, _>(|conn| {
+        let user: User = diesel::insert_into(users::table)
+            .values(&new_user)
+            .get_result(conn)?;
+        projects::inbox(conn, user.id)?;
+        Ok(user)
+    }) {
+        Ok(user) => {
+            info!("SCIM provisioned user {}", user.id);
+            scim_user_response(StatusCode::CREATED, user)
+        }
+        Err(e) => database_error(e, "create user"),
+    }
+}
+
+#[put("/{id}")]
+pub async fn replace_user(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    user_data: web::Json<ScimUserRequest>,
+) -> impl Responder {
+    if let Err(response) = authorize(&req) {
+        return response;
+    }
+    let user_id = path.into_inner();
+
+    let state = match ScimUserState::from_request(&user_data) {
+        Ok(state) => state,
+        Err(e) => return e.into_response(),
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = find_user(conn, user_id) {
+        return response;
+    }
+
+    save_state(conn, user_id, state)
+}
+
+#[patch("/{id}")]
+pub async fn patch_user(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    patch_data: web::Json<ScimPatchRequest>,
+) -> impl Responder {
+    if let Err(response) = authorize(&req) {
+        return response;
+    }
+    let user_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let user = match find_user(conn, user_id) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let mut state = ScimUserState::from(&user);
+    if let Err(e) = state.apply_patch(&patch_data) {
+        return e.into_response();
+    }
+
+    save_state(conn, user_id, state)
+}
+
+#[delete("/{id}")]
+pub async fn delete_user(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    if let Err(response) = authorize(&req) {
+        return response;
+    }
+    let user_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    // Deprovisioning deactivates the user; their data is kept
+    let target = users::table
+        .filter(users::id.eq(user_id))
+        .filter(users::deleted_at.is_null());
+    match diesel::update(target)
+        .set(users::active.eq(false))
+        .execute(conn)
+    {
+        Ok(0) => not_found(user_id),
+        Ok(_) => {
+            info!("SCIM deprovisioned user {}", user_id);
+            HttpResponse::NoContent().finish()
+        }
+        Err(e) => database_error(e, "deactivate user"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bearer, pool, send};
+    use actix_web::test::TestRequest;
+
+    #[actix_web::test]
+    async fn provisioned_users_authenticate_with_their_idp_id() {
+        let Some(pool) = pool() else { return };
+        std::env::set_var("SCIM_BEARER_TOKEN", "scim-secret");
+        let external_id = Uuid::new_v4().to_string();
+        let user_name = format!("scim-{}", &external_id[..8]);
+
+        let (status, created) = send(
+            &pool,
+            TestRequest::post()
+                .uri("/scim/v2/Users")
+                .insert_header(("Authorization", "Bearer scim-secret"))
+                .set_json(json!({
+                    "externalId": external_id,
+                    "userName": user_name,
+                    "emails": [{"value": format!("{}@example.com", user_name), "primary": true}],
+                })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_ne!(created["id"], json!(external_id));
+
+        // The IdP's tokens name the user by its own id
+        let (status, body) = send(
+            &pool,
+            TestRequest::get()
+                .uri("/api/projects/")
+                .insert_header(bearer(&external_id)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["projects"][0]["user_id"], created["id"]);
+        assert_eq!(body["projects"][0]["is_inbox"], json!(true));
+    }
+}
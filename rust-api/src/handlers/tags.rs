@@ -0,0 +1,218 @@
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use log::error;
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+use super::get_current_user_id;
+use crate::{
+    models::{CreateTagRequest, NewTag, Tag, TagResponse, UpdateTagRequest},
+    schema::tags,
+    tags as tag_store,
+    DbPool,
+};
+
+fn save_error(e: DieselError, action: &str) -> HttpResponse {
+    match e {
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+            HttpResponse::Conflict().json(json!({
+                "error": "A tag with this name already exists"
+            }))
+        }
+        e => {
+            error!("Failed to {} tag: {}", action, e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to {} tag", action)
+            }))
+        }
+    }
+}
+
+#[get("/")]
+pub async fn get_tags(pool: web::Data<DbPool>, req: HttpRequest) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    match tag_store::with_task_counts(conn, current_user_id) {
+        Ok(rows) => {
+            let tag_responses: Vec<TagResponse> = rows
+                .into_iter()
+                .map(|(tag, task_count)| TagResponse::new(tag, task_count))
+                .collect();
+            HttpResponse::Ok().json(json!({
+                "tags": tag_responses
+            }))
+        }
+        Err(e) => {
+            error!("Failed to fetch tags: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch tags"
+            }))
+        }
+    }
+}
+
+#[get("/{id}")]
+pub async fn get_tag(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let tag_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let result = tags::table
+        .filter(tags::id.eq(tag_id))
+        .filter(tags::user_id.eq(current_user_id))
+        .first::<Tag>(conn)
+        .and_then(|tag| tag_store::task_count(conn, tag.id).map(|count| (tag, count)));
+
+    match result {
+        Ok((tag, task_count)) => HttpResponse::Ok().json(json!({
+            "tag": TagResponse::new(tag, task_count)
+        })),
+        Err(DieselError::NotFound) => HttpResponse::NotFound().json(json!({
+            "error": "Tag not found"
+        })),
+        Err(e) => {
+            error!("Failed to fetch tag: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch tag"
+            }))
+        }
+    }
+}
+
+#[post("/")]
+pub async fn create_tag(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    tag_data: web::Json<CreateTagRequest>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Validate input
+    if let Err(validation_errors) = tag_data.validate() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "Validation failed",
+            "details": validation_errors
+        }));
+    }
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let new_tag = NewTag {
+        user_id: current_user_id,
+        name: tag_data.name.trim().to_string(),
+        color: tag_data.color.clone(),
+    };
+
+    match diesel::insert_into(tags::table)
+        .values(&new_tag)
+        .get_result::<Tag>(conn)
+    {
+        Ok(tag) => HttpResponse::Created().json(json!({
+            "message": "Tag created successfully",
+            "tag": TagResponse::new(tag, 0)
+        })),
+        Err(e) => save_error(e, "create"),
+    }
+}
+
+#[put("/{id}")]
+pub async fn update_tag(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    tag_data: web::Json<UpdateTagRequest>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let tag_id = path.into_inner();
+
+    // Validate input
+    if let Err(validation_errors) = tag_data.validate() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "Validation failed",
+            "details": validation_errors
+        }));
+    }
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let result = diesel::update(
+        tags::table
+            .filter(tags::id.eq(tag_id))
+            .filter(tags::user_id.eq(current_user_id)),
+    )
+    .set((
+        tag_data.name.as_ref().map(|n| tags::name.eq(n.trim())),
+        tag_data.color.as_ref().map(|c| tags::color.eq(c)),
+    ))
+    .get_result::<Tag>(conn)
+    .and_then(|tag| tag_store::task_count(conn, tag.id).map(|count| (tag, count)));
+
+    match result {
+        Ok((tag, task_count)) => HttpResponse::Ok().json(json!({
+            "message": "Tag updated successfully",
+            "tag": TagResponse::new(tag, task_count)
+        })),
+        Err(DieselError::NotFound) => HttpResponse::NotFound().json(json!({
+            "error": "Tag not found"
+        })),
+        Err(e) => save_error(e, "update"),
+    }
+}
+
+#[delete("/{id}")]
+pub async fn delete_tag(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let tag_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    // Tasks keep existing; only their association with the tag is removed
+    match diesel::delete(
+        tags::table
+            .filter(tags::id.eq(tag_id))
+            .filter(tags::user_id.eq(current_user_id)),
+    )
+    .execute(conn)
+    {
+        Ok(0) => HttpResponse::NotFound().json(json!({
+            "error": "Tag not found"
+        })),
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "message": "Tag deleted successfully"
+        })),
+        Err(e) => {
+            error!("Failed to delete tag: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to delete tag"
+            }))
+        }
+    }
+}
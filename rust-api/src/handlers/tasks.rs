@@ -1,41 +1,47 @@
-use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use actix_web::{
+    delete, get, http::header::IfMatch, patch, post, put, web, HttpRequest, HttpResponse, Responder,
+};
+use chrono::Utc;
 use diesel::prelude::*;
 use log::error;
 use serde_json::json;
 use uuid::Uuid;
 use validator::Validate;
 
+use super::{
+    check_if_match, etag, get_current_user_id, if_match, not_modified, precondition_failed,
+    representation_etag,
+};
 use crate::{
-    auth::extract_user_id_from_token,
-    models::{CreateTaskRequest, NewTask, Task, TaskResponse, UpdateTaskRequest},
-    schema::tasks,
+    access::{self, AccessError},
+    dependencies,
+    models::{
+        CreateTaskRequest, GrantRole, MoveTaskRequest, NewTask, NextTasksQuery, OccurrenceScope,
+        OccurrenceScopeQuery, Project, ReplaceTaskRequest, SkipOccurrenceRequest, Task,
+        TaskDetailQuery, TaskHistoryQuery, TaskListQuery, TaskResponse, TaskSearchQuery,
+        UpcomingTasksQuery, UpdateTaskRequest,
+    },
+    notifications, projects,
+    recurrence::{self, Anchor, Recurrence, RecurrenceError},
+    schema::{tasks, users},
+    subtasks::{self, SubtaskError},
+    tags,
+    task_events::{self, Changes},
+    task_positions::{self, PositionError},
+    task_query::{self, TaskQueryError},
+    task_responses,
+    task_schedule::{self, DueView, DEFAULT_UPCOMING_DAYS, MAX_UPCOMING_DAYS},
+    task_search, trash,
+    workflows::{self, WorkflowError},
     DbPool,
 };
 
-// Helper function to extract user ID from Authorization header
-fn get_current_user_id(req: &HttpRequest) -> Result<Uuid, HttpResponse> {
-    let auth_header = req
-        .headers()
-        .get("Authorization")
-        .and_then(|h| h.to_str().ok())
-        .and_then(|h| h.strip_prefix("Bearer "));
-
-    match auth_header {
-        Some(token) => {
-            extract_user_id_from_token(token).map_err(|_| {
-                HttpResponse::Unauthorized().json(json!({
-                    "error": "Invalid or expired token"
-                }))
-            })
-        }
-        None => Err(HttpResponse::Unauthorized().json(json!({
-            "error": "Authorization header required"
-        }))),
-    }
-}
-
 #[get("/")]
-pub async fn get_tasks(pool: web::Data<DbPool>, req: HttpRequest) -> impl Responder {
+pub async fn get_tasks(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    query: web::Query<TaskListQuery>,
+) -> impl Responder {
     let current_user_id = match get_current_user_id(&req) {
         Ok(id) => id,
         Err(response) => return response,
@@ -43,11 +49,13 @@ pub async fn get_tasks(pool: web::Data<DbPool>, req: HttpRequest) -> impl Respon
 
     let conn = &mut pool.get().expect("Failed to get DB connection");
 
-    let user_tasks: Vec<Task> = match tasks::table
-        .filter(tasks::user_id.eq(current_user_id))
-        .load(conn)
-    {
-        Ok(tasks) => tasks,
+    let page = match task_query::load_page(conn, current_user_id, &query) {
+        Ok(page) => page,
+        Err(TaskQueryError::InvalidParameter(message)) => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": message
+            }));
+        }
         Err(e) => {
             error!("Failed to fetch tasks: {}", e);
             return HttpResponse::InternalServerError().json(json!({
@@ -56,11 +64,144 @@ pub async fn get_tasks(pool: web::Data<DbPool>, req: HttpRequest) -> impl Respon
         }
     };
 
-    let task_responses: Vec<TaskResponse> = user_tasks.into_iter().map(|t| t.into()).collect();
+    let task_responses = match task_responses::build(conn, page.tasks) {
+        Ok(task_responses) => task_responses,
+        Err(e) => {
+            error!("Failed to fetch tasks: {}", e);
+            return HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch tasks"
+            }));
+        }
+    };
 
-    HttpResponse::Ok().json(json!({
-        "tasks": task_responses
-    }))
+    let mut body = json!({
+        "tasks": task_responses,
+        "next_cursor": page.next_cursor
+    });
+    if let Some(total) = page.total {
+        body["total"] = json!(total);
+    }
+    HttpResponse::Ok().json(body)
+}
+
+#[get("/search")]
+pub async fn search_tasks(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    query: web::Query<TaskSearchQuery>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    match task_search::search(conn, current_user_id, &query) {
+        Ok(page) => HttpResponse::Ok().json(json!({
+            "mode": page.mode,
+            "results": page.results,
+            "total": page.total,
+            "next_offset": page.next_offset
+        })),
+        Err(TaskQueryError::InvalidParameter(message)) => HttpResponse::BadRequest().json(json!({
+            "error": message
+        })),
+        Err(e) => {
+            error!("Failed to search tasks: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to search tasks"
+            }))
+        }
+    }
+}
+
+// Shared body of the today/upcoming/overdue views
+fn due_tasks_response(pool: &DbPool, req: &HttpRequest, view: DueView) -> HttpResponse {
+    let current_user_id = match get_current_user_id(req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let result = task_schedule::user_timezone(conn, current_user_id).and_then(|tz| {
+        let due = task_schedule::load(conn, current_user_id, tz, view, Utc::now())?;
+        let task_responses = task_responses::build(conn, due.tasks)?;
+        Ok((tz, due.today, task_responses))
+    });
+
+    match result {
+        Ok((tz, today, task_responses)) => HttpResponse::Ok().json(json!({
+            "tasks": task_responses,
+            "timezone": tz.name(),
+            "today": today
+        })),
+        Err(e) => {
+            error!("Failed to fetch due tasks: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch tasks"
+            }))
+        }
+    }
+}
+
+#[get("/today")]
+pub async fn get_today_tasks(pool: web::Data<DbPool>, req: HttpRequest) -> impl Responder {
+    due_tasks_response(&pool, &req, DueView::Today)
+}
+
+#[get("/upcoming")]
+pub async fn get_upcoming_tasks(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    query: web::Query<UpcomingTasksQuery>,
+) -> impl Responder {
+    let days = query.days.unwrap_or(DEFAULT_UPCOMING_DAYS);
+    if !(1..=MAX_UPCOMING_DAYS).contains(&days) {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!("days must be between 1 and {}", MAX_UPCOMING_DAYS)
+        }));
+    }
+    due_tasks_response(&pool, &req, DueView::Upcoming { days })
+}
+
+#[get("/overdue")]
+pub async fn get_overdue_tasks(pool: web::Data<DbPool>, req: HttpRequest) -> impl Responder {
+    due_tasks_response(&pool, &req, DueView::Overdue)
+}
+
+#[get("/next")]
+pub async fn get_next_tasks(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    query: web::Query<NextTasksQuery>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let limit = query
+        .limit
+        .unwrap_or(task_query::DEFAULT_LIMIT)
+        .clamp(1, task_query::MAX_LIMIT) as usize;
+    let result = dependencies::next(conn, current_user_id, limit)
+        .and_then(|tasks| task_responses::build(conn, tasks));
+
+    match result {
+        Ok(task_responses) => HttpResponse::Ok().json(json!({
+            "tasks": task_responses
+        })),
+        Err(e) => {
+            error!("Failed to fetch next tasks: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch tasks"
+            }))
+        }
+    }
 }
 
 #[get("/{id}")]
@@ -68,6 +209,7 @@ pub async fn get_task(
     pool: web::Data<DbPool>,
     req: HttpRequest,
     path: web::Path<Uuid>,
+    query: web::Query<TaskDetailQuery>,
 ) -> impl Responder {
     let current_user_id = match get_current_user_id(&req) {
         Ok(id) => id,
@@ -77,42 +219,292 @@ pub async fn get_task(
 
     let conn = &mut pool.get().expect("Failed to get DB connection");
 
-    let task: Task = match tasks::table
-        .filter(tasks::id.eq(task_id))
-        .filter(tasks::user_id.eq(current_user_id))
-        .first(conn)
-    {
-        Ok(task) => task,
-        Err(diesel::result::Error::NotFound) => {
-            return HttpResponse::NotFound().json(json!({
-                "error": "Task not found"
-            }));
+    let (task, role) =
+        match fetch_task(conn, current_user_id, task_id, GrantRole::Viewer, "fetch task") {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+    let version = task.version;
+
+    let include_subtree = query.subtree.unwrap_or(false);
+    let result = recurrence::describe(conn, &task).and_then(|recurrence| {
+        let task_response = task_responses::build_one(conn, task)?;
+        let subtasks = if include_subtree {
+            Some(subtasks::load_tree(conn, current_user_id, task_id)?)
+        } else {
+            None
+        };
+        Ok((task_response, subtasks, recurrence))
+    });
+
+    match result {
+        Ok((task_response, subtasks, recurrence)) => {
+            let mut body = json!({
+                "task": task_response,
+                "role": role
+            });
+            if let Some(subtasks) = subtasks {
+                body["subtasks"] = json!(subtasks);
+            }
+            if let Some(recurrence) = recurrence {
+                body["recurrence"] = json!(recurrence);
+            }
+            // The body shows comment counts, blockers, subtasks and the
+            // series, none of which bump the task's version
+            let current = representation_etag(version, &body);
+            if let Some(response) = not_modified(&req, current.clone()) {
+                return response;
+            }
+            HttpResponse::Ok().insert_header(current).json(body)
         }
         Err(e) => {
             error!("Failed to fetch task: {}", e);
-            return HttpResponse::InternalServerError().json(json!({
+            HttpResponse::InternalServerError().json(json!({
                 "error": "Failed to fetch task"
-            }));
+            }))
+        }
+    }
+}
+
+// Loads a task the user holds at least `required` on; tasks the user can't
+// see are reported as not found
+#[allow(clippy::result_large_err)]
+pub(super) fn fetch_task(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    task_id: Uuid,
+    required: GrantRole,
+    action: &str,
+) -> Result<(Task, GrantRole), HttpResponse> {
+    match access::task(conn, user_id, task_id, required) {
+        Ok(found) => Ok(found),
+        Err(AccessError::NotFound) => Err(HttpResponse::NotFound().json(json!({
+            "error": "Task not found"
+        }))),
+        Err(AccessError::Forbidden(role)) => Err(HttpResponse::Forbidden().json(json!({
+            "error": format!("Requires {} access to this task", role)
+        }))),
+        Err(AccessError::Database(e)) => {
+            error!("Failed to fetch task: {}", e);
+            Err(HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to {}", action)
+            })))
         }
+    }
+}
+
+// Resolves the project a task is created in or moved to: an unarchived
+// project the user can edit, or their inbox when none is given
+#[allow(clippy::result_large_err)]
+fn target_project(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    project_id: Option<Uuid>,
+) -> Result<Project, HttpResponse> {
+    let result = match project_id {
+        Some(project_id) => {
+            access::project(conn, user_id, project_id, GrantRole::Editor).map(|(project, _)| project)
+        }
+        None => projects::inbox(conn, user_id).map_err(AccessError::from),
     };
+    match result {
+        Ok(project) if project.archived => Err(HttpResponse::Conflict().json(json!({
+            "error": "Project is archived"
+        }))),
+        Ok(project) => Ok(project),
+        Err(AccessError::NotFound) => Err(HttpResponse::BadRequest().json(json!({
+            "error": "Project not found"
+        }))),
+        Err(AccessError::Forbidden(role)) => Err(HttpResponse::Forbidden().json(json!({
+            "error": format!("Requires {} access to the project", role)
+        }))),
+        Err(AccessError::Database(e)) => {
+            error!("Failed to fetch project: {}", e);
+            Err(HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch project"
+            })))
+        }
+    }
+}
+
+// The assignee must be an active user who can see the task
+#[allow(clippy::result_large_err)]
+fn check_assignee(
+    conn: &mut PgConnection,
+    assignee_id: Uuid,
+    owner_id: Uuid,
+    task_id: Option<Uuid>,
+    project_id: Uuid,
+) -> Result<(), HttpResponse> {
+    let result = users::table
+        .filter(users::id.eq(assignee_id))
+        .filter(users::active.eq(true))
+        .select(users::id)
+        .first::<Uuid>(conn)
+        .optional()
+        .and_then(|user| match user {
+            Some(_) => access::role_for(conn, assignee_id, owner_id, task_id, project_id),
+            None => Ok(None),
+        });
+    match result {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(HttpResponse::BadRequest().json(json!({
+            "error": "Assignee not found or cannot see this task"
+        }))),
+        Err(e) => {
+            error!("Failed to check assignee: {}", e);
+            Err(HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to check assignee"
+            })))
+        }
+    }
+}
+
+// Under a precondition, locks the task for the rest of the transaction and
+// checks that nobody changed it since it was loaded
+fn still_current(
+    conn: &mut PgConnection,
+    task: &Task,
+    precondition: &IfMatch,
+) -> QueryResult<bool> {
+    if matches!(precondition, IfMatch::Any) {
+        return Ok(true);
+    }
+    let version: Option<i32> = tasks::table
+        .filter(tasks::id.eq(task.id))
+        .select(tasks::version)
+        .for_update()
+        .first(conn)
+        .optional()?;
+    Ok(version == Some(task.version))
+}
+
+fn subtask_error_response(e: SubtaskError, action: &str) -> HttpResponse {
+    match e {
+        SubtaskError::InvalidParent(message) => HttpResponse::BadRequest().json(json!({
+            "error": message
+        })),
+        SubtaskError::Conflict(message) => HttpResponse::Conflict().json(json!({
+            "error": message
+        })),
+        SubtaskError::Database(e) => {
+            error!("Failed to {}: {}", action, e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to {}", action)
+            }))
+        }
+    }
+}
 
-    let task_response: TaskResponse = task.into();
-    HttpResponse::Ok().json(json!({
-        "task": task_response
+fn parent_project_mismatch() -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "error": "Subtasks belong to their parent's project"
     }))
 }
 
-#[post("/")]
-pub async fn create_task(
-    pool: web::Data<DbPool>,
-    req: HttpRequest,
-    task_data: web::Json<CreateTaskRequest>,
-) -> impl Responder {
-    let current_user_id = match get_current_user_id(&req) {
-        Ok(id) => id,
-        Err(response) => return response,
+fn recurrence_error_response(e: RecurrenceError, action: &str) -> HttpResponse {
+    match e {
+        RecurrenceError::Invalid(message) => HttpResponse::BadRequest().json(json!({
+            "error": message
+        })),
+        RecurrenceError::Database(e) => {
+            error!("Failed to {}: {}", action, e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to {}", action)
+            }))
+        }
+    }
+}
+
+fn workflow_error_response(e: WorkflowError, action: &str) -> HttpResponse {
+    match e {
+        WorkflowError::Invalid(message) => HttpResponse::BadRequest().json(json!({
+            "error": message
+        })),
+        WorkflowError::Conflict(message) => HttpResponse::Conflict().json(json!({
+            "error": message
+        })),
+        WorkflowError::Database(e) => {
+            error!("Failed to {}: {}", action, e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to {}", action)
+            }))
+        }
+    }
+}
+
+fn subtask_cannot_recur() -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "error": "Subtasks cannot recur"
+    }))
+}
+
+// The series an update re-anchors at the task's due date: a new rule, or the
+// current one when `scope=future` moves the due date
+#[allow(clippy::result_large_err)]
+fn reanchor_for_update(
+    conn: &mut PgConnection,
+    existing_task: &Task,
+    task_data: &UpdateTaskRequest,
+    scope: OccurrenceScope,
+) -> Result<Option<(Recurrence, Anchor)>, HttpResponse> {
+    let recurring = existing_task.series_id.is_some();
+    let (due_at, due_date) = task_data.due_changes();
+    let due_changed = due_at.is_some() || due_date.is_some();
+
+    let recurrence = match &task_data.recurrence {
+        Some(Some(_)) if recurring && scope == OccurrenceScope::This => {
+            return Err(HttpResponse::BadRequest().json(json!({
+                "error": "Changing the recurrence of a series needs scope=future"
+            })));
+        }
+        Some(Some(request)) => Recurrence::parse(conn, existing_task.user_id, request).map(Some),
+        None if recurring && scope == OccurrenceScope::Future && due_changed => {
+            recurrence::current(conn, existing_task).map_err(RecurrenceError::from)
+        }
+        _ => Ok(None),
     };
 
+    let result = recurrence.and_then(|recurrence| match recurrence {
+        Some(recurrence) => {
+            let anchor = recurrence::anchor(
+                conn,
+                existing_task.series_id,
+                Some(existing_task.id),
+                recurrence.tz,
+                due_at.unwrap_or(existing_task.due_at),
+                due_date.unwrap_or(existing_task.due_date),
+            )?;
+            Ok(Some((recurrence, anchor)))
+        }
+        None => Ok(None),
+    });
+    result.map_err(|e| recurrence_error_response(e, "update task"))
+}
+
+// Skips a recurring task's own occurrence: the next one is created and this
+// one moved to the trash
+fn skip_and_delete(
+    conn: &mut PgConnection,
+    task: &Task,
+    actor_id: Uuid,
+) -> Result<Option<TaskResponse>, SubtaskError> {
+    conn.transaction(|conn| {
+        let next_occurrence = recurrence::skip_current(conn, task, actor_id)?;
+        trash::delete_task(conn, task, actor_id)?;
+        Ok(next_occurrence
+            .map(|next| task_responses::build_one(conn, next))
+            .transpose()?)
+    })
+}
+
+/// Validates and inserts a task; shared by `POST /api/tasks` and
+/// `POST /api/projects/{id}/tasks`.
+pub(super) fn create_task_response(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    task_data: &CreateTaskRequest,
+) -> HttpResponse {
     // Validate input
     if let Err(validation_errors) = task_data.validate() {
         return HttpResponse::BadRequest().json(json!({
@@ -121,32 +513,131 @@ pub async fn create_task(
         }));
     }
 
-    let conn = &mut pool.get().expect("Failed to get DB connection");
+    let parent = match task_data.parent_id {
+        Some(parent_id) => match subtasks::check_parent(conn, user_id, None, parent_id) {
+            Ok(parent) => Some(parent),
+            Err(e) => return subtask_error_response(e, "create task"),
+        },
+        None => None,
+    };
+
+    // Subtasks are created in their parent's project
+    let project_id = match (&parent, task_data.project_id) {
+        (Some(parent), Some(project_id)) if project_id != parent.project_id => {
+            return parent_project_mismatch();
+        }
+        (Some(parent), _) => Some(parent.project_id),
+        (None, project_id) => project_id,
+    };
+
+    let project = match target_project(conn, user_id, project_id) {
+        Ok(project) => project,
+        Err(response) => return response,
+    };
+
+    if let Some(assignee_id) = task_data.assignee_id {
+        if let Err(response) = check_assignee(conn, assignee_id, user_id, None, project.id) {
+            return response;
+        }
+    }
+
+    let status = match task_data.status_id {
+        Some(status_id) => match workflows::find_status(conn, project.id, status_id) {
+            Ok(status) => Some(status),
+            Err(e) => return workflow_error_response(e, "create task"),
+        },
+        None => None,
+    };
+
+    let recurrence = match &task_data.recurrence {
+        Some(_) if parent.is_some() => return subtask_cannot_recur(),
+        Some(request) => {
+            let result = Recurrence::parse(conn, user_id, request).and_then(|recurrence| {
+                let anchor = recurrence::anchor(
+                    conn,
+                    None,
+                    None,
+                    recurrence.tz,
+                    task_data.due_at,
+                    task_data.due_date,
+                )?;
+                Ok((recurrence, anchor))
+            });
+            match result {
+                Ok(planned) => Some(planned),
+                Err(e) => return recurrence_error_response(e, "create task"),
+            }
+        }
+        None => None,
+    };
 
     let new_task = NewTask {
         title: task_data.title.clone(),
         description: task_data.description.clone(),
-        user_id: current_user_id,
+        user_id,
+        due_at: task_data.due_at,
+        due_date: task_data.due_date,
+        priority: task_data.priority.unwrap_or_default(),
+        project_id: project.id,
+        parent_id: parent.map(|p| p.id),
+        assignee_id: task_data.assignee_id,
+        series_id: None,
+        occurrence_at: None,
     };
 
-    let task: Task = match diesel::insert_into(tasks::table)
-        .values(&new_task)
-        .get_result(conn)
-    {
-        Ok(task) => task,
+    let result = conn.transaction::<TaskResponse, diesel::result::Error, _>(|conn| {
+        // New tasks go to the end of their list
+        let position = task_positions::append(conn, new_task.project_id, new_task.parent_id)?;
+        let task: Task = diesel::insert_into(tasks::table)
+            .values((
+                &new_task,
+                tasks::position.eq(position),
+                status.as_ref().map(|status| tasks::status_id.eq(status.id)),
+            ))
+            .get_result(conn)?;
+        let task = match &recurrence {
+            Some((recurrence, anchor)) => recurrence::start(conn, &task, recurrence, anchor)?,
+            None => task,
+        };
+        notifications::task_assigned(conn, &task, None, user_id)?;
+        let mut tag_names = Vec::new();
+        if let Some(names) = &task_data.tags {
+            let task_tags = tags::resolve(conn, user_id, names)?;
+            tags::set_task_tags(conn, task.id, &task_tags)?;
+            tag_names = tags::names(&task_tags);
+        }
+        task_events::created(conn, &task, &tag_names, user_id)?;
+        task_responses::build_one(conn, task)
+    });
+
+    match result {
+        Ok(task_response) => HttpResponse::Created().json(json!({
+            "message": "Task created successfully",
+            "task": task_response
+        })),
         Err(e) => {
             error!("Failed to create task: {}", e);
-            return HttpResponse::InternalServerError().json(json!({
+            HttpResponse::InternalServerError().json(json!({
                 "error": "Failed to create task"
-            }));
+            }))
         }
+    }
+}
+
+#[post("/")]
+pub async fn create_task(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    task_data: web::Json<CreateTaskRequest>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
     };
 
-    let task_response: TaskResponse = task.into();
-    HttpResponse::Created().json(json!({
-        "message": "Task created successfully",
-        "task": task_response
-    }))
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    create_task_response(conn, current_user_id, &task_data)
 }
 
 #[put("/{id}")]
@@ -154,6 +645,35 @@ pub async fn update_task(
     pool: web::Data<DbPool>,
     req: HttpRequest,
     path: web::Path<Uuid>,
+    query: web::Query<OccurrenceScopeQuery>,
+    task_data: web::Json<ReplaceTaskRequest>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let task_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    // A replacement is a patch that sets every field
+    let task_data = UpdateTaskRequest::from(task_data.into_inner());
+    update_task_response(
+        conn,
+        current_user_id,
+        task_id,
+        query.scope.unwrap_or_default(),
+        &task_data,
+        &if_match(&req),
+    )
+}
+
+#[patch("/{id}")]
+pub async fn patch_task(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<OccurrenceScopeQuery>,
     task_data: web::Json<UpdateTaskRequest>,
 ) -> impl Responder {
     let current_user_id = match get_current_user_id(&req) {
@@ -162,6 +682,29 @@ pub async fn update_task(
     };
     let task_id = path.into_inner();
 
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    update_task_response(
+        conn,
+        current_user_id,
+        task_id,
+        query.scope.unwrap_or_default(),
+        &task_data,
+        &if_match(&req),
+    )
+}
+
+/// Validates and applies a merge patch to a task; shared by `PUT` and
+/// `PATCH /api/tasks/{id}` and bulk operations. The patch is only applied
+/// while the task's version satisfies `precondition`.
+pub(super) fn update_task_response(
+    conn: &mut PgConnection,
+    current_user_id: Uuid,
+    task_id: Uuid,
+    scope: OccurrenceScope,
+    task_data: &UpdateTaskRequest,
+    precondition: &IfMatch,
+) -> HttpResponse {
     // Validate input
     if let Err(validation_errors) = task_data.validate() {
         return HttpResponse::BadRequest().json(json!({
@@ -170,51 +713,210 @@ pub async fn update_task(
         }));
     }
 
-    let conn = &mut pool.get().expect("Failed to get DB connection");
+    let (existing_task, role) =
+        match fetch_task(conn, current_user_id, task_id, GrantRole::Editor, "update task") {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+    if let Err(response) = check_if_match(precondition, existing_task.version) {
+        return response;
+    }
 
-    // Check if task exists and belongs to user
-    let existing_task: Task = match tasks::table
-        .filter(tasks::id.eq(task_id))
-        .filter(tasks::user_id.eq(current_user_id))
-        .first(conn)
-    {
-        Ok(task) => task,
-        Err(diesel::result::Error::NotFound) => {
-            return HttpResponse::NotFound().json(json!({
-                "error": "Task not found"
-            }));
+    // Some(None) detaches the task from its parent
+    let new_parent: Option<Option<Task>> = match task_data.parent_id {
+        Some(Some(parent_id)) => {
+            match subtasks::check_parent(conn, current_user_id, Some(task_id), parent_id) {
+                Ok(parent) => Some(Some(parent)),
+                Err(e) => return subtask_error_response(e, "update task"),
+            }
         }
-        Err(e) => {
-            error!("Failed to fetch task: {}", e);
-            return HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to update task"
+        Some(None) => Some(None),
+        None => None,
+    };
+
+    // A subtask follows its parent's project; only top-level tasks move on their own
+    let new_project_id = match &new_parent {
+        Some(Some(parent)) => {
+            if task_data.project_id.is_some_and(|p| p != parent.project_id) {
+                return parent_project_mismatch();
+            }
+            Some(parent.project_id)
+        }
+        Some(None) => task_data.project_id,
+        None => {
+            let moves = task_data.project_id.is_some_and(|p| p != existing_task.project_id);
+            if moves && existing_task.parent_id.is_some() {
+                return parent_project_mismatch();
+            }
+            task_data.project_id
+        }
+    }
+    .filter(|project_id| *project_id != existing_task.project_id);
+
+    // Only an owner may move a task, as the owner of the target project comes
+    // to own it too; and only into unarchived projects the user can edit
+    if let Some(project_id) = new_project_id {
+        if role != GrantRole::Owner {
+            return HttpResponse::Forbidden().json(json!({
+                "error": "Requires owner access to move this task to another project"
             }));
         }
+        if let Err(response) = target_project(conn, current_user_id, Some(project_id)) {
+            return response;
+        }
+    }
+
+    if let Some(Some(assignee_id)) = task_data.assignee_id {
+        let project_id = new_project_id.unwrap_or(existing_task.project_id);
+        if let Err(response) = check_assignee(
+            conn,
+            assignee_id,
+            existing_task.user_id,
+            Some(task_id),
+            project_id,
+        ) {
+            return response;
+        }
+    }
+
+    // Status changes follow the project's workflow; `completed` picks a status
+    let target_status = match workflows::target_status(
+        conn,
+        &existing_task,
+        new_project_id.unwrap_or(existing_task.project_id),
+        task_data.status_id,
+        task_data.completed,
+    ) {
+        Ok(target_status) => target_status,
+        Err(e) => return workflow_error_response(e, "update task"),
+    };
+
+    // Recurring tasks change this occurrence only, or the series from here on
+    let ends_series = existing_task.series_id.is_some() && matches!(task_data.recurrence, Some(None));
+    let reanchor = match reanchor_for_update(conn, &existing_task, task_data, scope) {
+        Ok(reanchor) => reanchor,
+        Err(response) => return response,
     };
+    let parent_id = match &new_parent {
+        Some(parent) => parent.as_ref().map(|p| p.id),
+        None => existing_task.parent_id,
+    };
+    let recurs = reanchor.is_some() || (existing_task.series_id.is_some() && !ends_series);
+    if parent_id.is_some() && recurs {
+        return subtask_cannot_recur();
+    }
 
     // Update task
-    let updated_task: Task = match diesel::update(tasks::table.filter(tasks::id.eq(task_id)))
-        .set((
-            task_data.title.as_ref().map(|t| tasks::title.eq(t)),
-            task_data.description.as_ref().map(|d| tasks::description.eq(d)),
-            task_data.completed.as_ref().map(|c| tasks::completed.eq(c)),
-        ))
-        .get_result(conn)
-    {
-        Ok(task) => task,
-        Err(e) => {
-            error!("Failed to update task: {}", e);
-            return HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to update task"
-            }));
+    let (due_at, due_date) = task_data.due_changes();
+    let reassigned = task_data
+        .assignee_id
+        .is_some_and(|assignee_id| assignee_id != existing_task.assignee_id);
+    let completing = target_status.as_ref().is_some_and(|status| status.done) && !existing_task.completed;
+    let result = conn.transaction::<_, SubtaskError, _>(|conn| {
+        if !still_current(conn, &existing_task, precondition)? {
+            return Ok(None);
         }
-    };
+        if completing {
+            subtasks::on_parent_complete(conn, task_id, current_user_id)?;
+        }
+        if let (true, Some(series_id)) = (ends_series, existing_task.series_id) {
+            recurrence::end(conn, series_id)?;
+        }
+        // A task changing lists goes to the end of its new one
+        let new_position = if new_project_id.is_some() || parent_id != existing_task.parent_id {
+            let project_id = new_project_id.unwrap_or(existing_task.project_id);
+            Some(task_positions::append(conn, project_id, parent_id)?)
+        } else {
+            None
+        };
 
-    let task_response: TaskResponse = updated_task.into();
-    HttpResponse::Ok().json(json!({
-        "message": "Task updated successfully",
-        "task": task_response
-    }))
+        // updated_at is always set so that a tags-only change still touches the task
+        let updated_task: Task = diesel::update(tasks::table.filter(tasks::id.eq(task_id)))
+            .set((
+                task_data.title.as_ref().map(|t| tasks::title.eq(t)),
+                task_data.description.as_ref().map(|d| tasks::description.eq(d)),
+                target_status.as_ref().map(|status| tasks::status_id.eq(status.id)),
+                due_at.map(|d| tasks::due_at.eq(d)),
+                due_date.map(|d| tasks::due_date.eq(d)),
+                task_data.priority.map(|p| tasks::priority.eq(p.unwrap_or_default())),
+                new_project_id.map(|p| tasks::project_id.eq(p)),
+                new_parent
+                    .as_ref()
+                    .map(|parent| tasks::parent_id.eq(parent.as_ref().map(|p| p.id))),
+                new_position.map(|p| tasks::position.eq(p)),
+                task_data.assignee_id.map(|a| tasks::assignee_id.eq(a)),
+                tasks::updated_at.eq(Utc::now()),
+            ))
+            .get_result(conn)?;
+        let updated_task = match &reanchor {
+            Some((recurrence, anchor)) => recurrence::start(conn, &updated_task, recurrence, anchor)?,
+            None if scope == OccurrenceScope::Future => {
+                recurrence::sync_template(conn, &updated_task)?;
+                updated_task
+            }
+            None => updated_task,
+        };
+        if reassigned {
+            notifications::task_assigned(
+                conn,
+                &updated_task,
+                existing_task.assignee_id,
+                current_user_id,
+            )?;
+        }
+        if let Some(project_id) = new_project_id {
+            subtasks::move_descendants(conn, task_id, project_id, current_user_id)?;
+        }
+        let mut changes = Changes::between(&existing_task, &updated_task);
+        // Tags live in the task owner's namespace, whoever edits the task
+        if let Some(names) = &task_data.tags {
+            let previous_tags = tags::load_for_tasks(conn, std::slice::from_ref(&existing_task))?;
+            let names = names.as_deref().unwrap_or_default();
+            let task_tags = tags::resolve(conn, existing_task.user_id, names)?;
+            tags::set_task_tags(conn, task_id, &task_tags)?;
+            changes.set("tags", tags::names(&previous_tags[0]), tags::names(&task_tags));
+        }
+        task_events::updated(conn, task_id, changes, current_user_id)?;
+        // Dependents whose last open dependency this was can now be worked on
+        let unblocked = if completing {
+            Some(dependencies::unblocked_by(conn, current_user_id, task_id)?)
+        } else {
+            None
+        };
+        // Completing an occurrence of a series schedules the next one
+        let next_occurrence = if completing {
+            recurrence::advance(conn, &updated_task, current_user_id)?
+        } else {
+            None
+        };
+        let task_response = task_responses::build_one(conn, updated_task)?;
+        let unblocked = unblocked
+            .map(|tasks| task_responses::build(conn, tasks))
+            .transpose()?;
+        let next_occurrence = next_occurrence
+            .map(|next| task_responses::build_one(conn, next))
+            .transpose()?;
+        Ok(Some((task_response, unblocked, next_occurrence)))
+    });
+
+    match result {
+        Ok(Some((task_response, unblocked, next_occurrence))) => {
+            let version = task_response.version;
+            let mut body = json!({
+                "message": "Task updated successfully",
+                "task": task_response
+            });
+            if let Some(unblocked) = unblocked {
+                body["unblocked_tasks"] = json!(unblocked);
+            }
+            if let Some(next_occurrence) = next_occurrence {
+                body["next_occurrence"] = json!(next_occurrence);
+            }
+            HttpResponse::Ok().insert_header(etag(version)).json(body)
+        }
+        Ok(None) => precondition_failed(),
+        Err(e) => subtask_error_response(e, "update task"),
+    }
 }
 
 #[delete("/{id}")]
@@ -222,6 +924,7 @@ pub async fn delete_task(
     pool: web::Data<DbPool>,
     req: HttpRequest,
     path: web::Path<Uuid>,
+    query: web::Query<OccurrenceScopeQuery>,
 ) -> impl Responder {
     let current_user_id = match get_current_user_id(&req) {
         Ok(id) => id,
@@ -231,35 +934,210 @@ pub async fn delete_task(
 
     let conn = &mut pool.get().expect("Failed to get DB connection");
 
-    // Check if task exists and belongs to user
-    let existing_task: Task = match tasks::table
-        .filter(tasks::id.eq(task_id))
-        .filter(tasks::user_id.eq(current_user_id))
-        .first(conn)
-    {
-        Ok(task) => task,
-        Err(diesel::result::Error::NotFound) => {
-            return HttpResponse::NotFound().json(json!({
-                "error": "Task not found"
-            }));
+    delete_task_response(
+        conn,
+        current_user_id,
+        task_id,
+        query.scope.unwrap_or_default(),
+        &if_match(&req),
+    )
+}
+
+/// Moves a task the user owns to the trash if its version satisfies
+/// `precondition`; shared by `DELETE /api/tasks/{id}` and bulk operations.
+pub(super) fn delete_task_response(
+    conn: &mut PgConnection,
+    current_user_id: Uuid,
+    task_id: Uuid,
+    scope: OccurrenceScope,
+    precondition: &IfMatch,
+) -> HttpResponse {
+    let (existing_task, _) =
+        match fetch_task(conn, current_user_id, task_id, GrantRole::Owner, "delete task") {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+    if let Err(response) = check_if_match(precondition, existing_task.version) {
+        return response;
+    }
+
+    let result = conn.transaction(|conn| {
+        if !still_current(conn, &existing_task, precondition)? {
+            return Ok(None);
         }
-        Err(e) => {
-            error!("Failed to fetch task: {}", e);
-            return HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to delete task"
-            }));
+        match (existing_task.series_id, scope) {
+            // Deleting one occurrence of a series skips it; the series carries on
+            (Some(_), OccurrenceScope::This) => {
+                skip_and_delete(conn, &existing_task, current_user_id).map(Some)
+            }
+            // Move the task to the trash, handling its subtasks as configured
+            (series_id, _) => {
+                trash::delete_task(conn, &existing_task, current_user_id)?;
+                if let Some(series_id) = series_id {
+                    recurrence::end(conn, series_id)?;
+                }
+                Ok(Some(None))
+            }
         }
+    });
+
+    match result {
+        Ok(Some(next_occurrence)) => {
+            let mut body = json!({
+                "message": "Task deleted successfully"
+            });
+            if let Some(next_occurrence) = next_occurrence {
+                body["next_occurrence"] = json!(next_occurrence);
+            }
+            HttpResponse::Ok().json(body)
+        }
+        Ok(None) => precondition_failed(),
+        Err(e) => subtask_error_response(e, "delete task"),
+    }
+}
+
+#[post("/{id}/skip")]
+pub async fn skip_occurrence(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    skip_data: Option<web::Json<SkipOccurrenceRequest>>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let task_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    // Skipping this occurrence deletes the task, so it takes the same access
+    let (existing_task, _) =
+        match fetch_task(conn, current_user_id, task_id, GrantRole::Owner, "skip occurrence") {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+    if existing_task.series_id.is_none() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "Task is not recurring"
+        }));
+    }
+
+    let later = skip_data
+        .and_then(|skip_data| skip_data.occurrence_at)
+        .filter(|occurrence_at| Some(*occurrence_at) != existing_task.occurrence_at);
+    match later {
+        Some(occurrence_at) => match recurrence::skip_later(conn, &existing_task, occurrence_at) {
+            Ok(()) => HttpResponse::Ok().json(json!({
+                "message": "Occurrence skipped successfully",
+                "occurrence_at": occurrence_at
+            })),
+            Err(e) => recurrence_error_response(e, "skip occurrence"),
+        },
+        None => match skip_and_delete(conn, &existing_task, current_user_id) {
+            Ok(next_occurrence) => HttpResponse::Ok().json(json!({
+                "message": "Occurrence skipped successfully",
+                "occurrence_at": existing_task.occurrence_at,
+                "next_occurrence": next_occurrence
+            })),
+            Err(e) => subtask_error_response(e, "skip occurrence"),
+        },
+    }
+}
+
+#[get("/{id}/history")]
+pub async fn get_task_history(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<TaskHistoryQuery>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
     };
+    let task_id = path.into_inner();
 
-    // Delete task
-    match diesel::delete(tasks::table.filter(tasks::id.eq(task_id))).execute(conn) {
-        Ok(_) => HttpResponse::Ok().json(json!({
-            "message": "Task deleted successfully"
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = fetch_task(
+        conn,
+        current_user_id,
+        task_id,
+        GrantRole::Viewer,
+        "fetch task history",
+    ) {
+        return response;
+    }
+
+    match task_events::list(conn, task_id, &query) {
+        Ok(page) => HttpResponse::Ok().json(json!({
+            "events": page.events,
+            "total": page.total,
+            "next_offset": page.next_offset
         })),
         Err(e) => {
-            error!("Failed to delete task: {}", e);
+            error!("Failed to fetch task history: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch task history"
+            }))
+        }
+    }
+}
+
+#[post("/{id}/move")]
+pub async fn move_task(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    move_data: web::Json<MoveTaskRequest>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let task_id = path.into_inner();
+    let precondition = if_match(&req);
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let (existing_task, _) =
+        match fetch_task(conn, current_user_id, task_id, GrantRole::Editor, "move task") {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+    if let Err(response) = check_if_match(&precondition, existing_task.version) {
+        return response;
+    }
+
+    let result = conn.transaction::<_, PositionError, _>(|conn| {
+        if !still_current(conn, &existing_task, &precondition)? {
+            return Ok(None);
+        }
+        let moved =
+            task_positions::move_task(conn, &existing_task, move_data.after_id, move_data.before_id)?;
+        Ok(Some(task_responses::build_one(conn, moved)?))
+    });
+
+    match result {
+        Ok(Some(task_response)) => {
+            let version = task_response.version;
+            HttpResponse::Ok().insert_header(etag(version)).json(json!({
+                "message": "Task moved successfully",
+                "task": task_response
+            }))
+        }
+        Ok(None) => precondition_failed(),
+        Err(PositionError::Invalid(message)) => HttpResponse::BadRequest().json(json!({
+            "error": message
+        })),
+        Err(PositionError::Conflict(message)) => HttpResponse::Conflict().json(json!({
+            "error": message
+        })),
+        Err(PositionError::Database(e)) => {
+            error!("Failed to move task: {}", e);
             HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to delete task"
+                "error": "Failed to move task"
             }))
         }
     }
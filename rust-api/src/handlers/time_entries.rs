@@ -0,0 +1,331 @@
+//! Time tracking: the timer and per-task totals under `/api/tasks/{id}`, and
+//! the caller's own entries, daily totals and CSV report under
+//! `/api/time-entries`.
+
+use actix_web::{
+    delete, get,
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    post, web, HttpRequest, HttpResponse, Responder,
+};
+use diesel::prelude::*;
+use log::error;
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+use super::{get_current_user_id, tasks::fetch_task};
+use crate::{
+    models::{
+        CreateTimeEntryRequest, GrantRole, StartTimerRequest, TimeEntryListQuery,
+        TimeEntryResponse, TimeRangeQuery,
+    },
+    task_schedule,
+    time_entries::{self, TimeEntryError},
+    DbPool,
+};
+
+fn time_entry_error_response(e: TimeEntryError, action: &str) -> HttpResponse {
+    match e {
+        TimeEntryError::NotFound => HttpResponse::NotFound().json(json!({
+            "error": "Time entry not found"
+        })),
+        TimeEntryError::Invalid(message) => HttpResponse::BadRequest().json(json!({
+            "error": message
+        })),
+        TimeEntryError::Conflict(message) => HttpResponse::Conflict().json(json!({
+            "error": message
+        })),
+        TimeEntryError::Database(e) => {
+            error!("Failed to {}: {}", action, e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to {}", action)
+            }))
+        }
+        TimeEntryError::Csv(e) => {
+            error!("Failed to {}: {}", action, e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to {}", action)
+            }))
+        }
+    }
+}
+
+#[post("/{id}/timer/start")]
+pub async fn start_timer(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    timer_data: Option<web::Json<StartTimerRequest>>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let task_id = path.into_inner();
+    let timer_data = timer_data.map(web::Json::into_inner).unwrap_or_default();
+
+    // Validate input
+    if let Err(validation_errors) = timer_data.validate() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "Validation failed",
+            "details": validation_errors
+        }));
+    }
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = fetch_task(
+        conn,
+        current_user_id,
+        task_id,
+        GrantRole::Editor,
+        "start timer",
+    ) {
+        return response;
+    }
+
+    let result = conn
+        .transaction(|conn| time_entries::start(conn, current_user_id, task_id, timer_data.note));
+
+    match result {
+        Ok(entry) => HttpResponse::Created().json(json!({
+            "message": "Timer started successfully",
+            "time_entry": TimeEntryResponse::new(entry, chrono::Utc::now())
+        })),
+        Err(e) => time_entry_error_response(e, "start timer"),
+    }
+}
+
+#[post("/{id}/timer/stop")]
+pub async fn stop_timer(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let task_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    match conn.transaction(|conn| time_entries::stop(conn, current_user_id, task_id)) {
+        Ok(entry) => HttpResponse::Ok().json(json!({
+            "message": "Timer stopped successfully",
+            "time_entry": TimeEntryResponse::new(entry, chrono::Utc::now())
+        })),
+        Err(e) => time_entry_error_response(e, "stop timer"),
+    }
+}
+
+#[get("/{id}/time")]
+pub async fn get_task_time(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let task_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = fetch_task(
+        conn,
+        current_user_id,
+        task_id,
+        GrantRole::Viewer,
+        "fetch task time",
+    ) {
+        return response;
+    }
+
+    match time_entries::task_summary(conn, task_id) {
+        Ok(summary) => HttpResponse::Ok().json(summary),
+        Err(e) => {
+            error!("Failed to fetch task time: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch task time"
+            }))
+        }
+    }
+}
+
+#[get("/")]
+pub async fn get_time_entries(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    query: web::Query<TimeEntryListQuery>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let result = task_schedule::user_timezone(conn, current_user_id)
+        .map_err(TimeEntryError::from)
+        .and_then(|tz| time_entries::list(conn, current_user_id, tz, &query));
+
+    match result {
+        Ok(page) => HttpResponse::Ok().json(json!({
+            "time_entries": page.entries,
+            "total": page.total,
+            "next_offset": page.next_offset
+        })),
+        Err(e) => time_entry_error_response(e, "fetch time entries"),
+    }
+}
+
+#[post("/")]
+pub async fn create_time_entry(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    entry_data: web::Json<CreateTimeEntryRequest>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    // Validate input
+    if let Err(validation_errors) = entry_data.validate() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "Validation failed",
+            "details": validation_errors
+        }));
+    }
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = fetch_task(
+        conn,
+        current_user_id,
+        entry_data.task_id,
+        GrantRole::Editor,
+        "create time entry",
+    ) {
+        return response;
+    }
+
+    match conn.transaction(|conn| time_entries::create(conn, current_user_id, &entry_data)) {
+        Ok(entry) => HttpResponse::Created().json(json!({
+            "message": "Time entry created successfully",
+            "time_entry": TimeEntryResponse::new(entry, chrono::Utc::now())
+        })),
+        Err(e) => time_entry_error_response(e, "create time entry"),
+    }
+}
+
+#[get("/running")]
+pub async fn get_running_timer(pool: web::Data<DbPool>, req: HttpRequest) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    match time_entries::running(conn, current_user_id) {
+        Ok(entry) => HttpResponse::Ok().json(json!({
+            "time_entry": entry.map(|entry| TimeEntryResponse::new(entry, chrono::Utc::now()))
+        })),
+        Err(e) => {
+            error!("Failed to fetch running timer: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch running timer"
+            }))
+        }
+    }
+}
+
+#[get("/daily")]
+pub async fn get_daily_time(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    query: web::Query<TimeRangeQuery>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let result = task_schedule::user_timezone(conn, current_user_id)
+        .map_err(TimeEntryError::from)
+        .and_then(|tz| {
+            let days = time_entries::daily(conn, current_user_id, tz, &query)?;
+            Ok((tz, days))
+        });
+
+    match result {
+        Ok((tz, days)) => HttpResponse::Ok().json(json!({
+            "timezone": tz.name(),
+            "total_seconds": days.iter().map(|day| day.total_seconds).sum::<i64>(),
+            "days": days
+        })),
+        Err(e) => time_entry_error_response(e, "fetch daily time"),
+    }
+}
+
+#[get("/report")]
+pub async fn get_time_report(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    query: web::Query<TimeRangeQuery>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let result = task_schedule::user_timezone(conn, current_user_id)
+        .map_err(TimeEntryError::from)
+        .and_then(|tz| time_entries::report(conn, current_user_id, tz, &query));
+
+    match result {
+        Ok(csv) => {
+            let disposition = ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(format!(
+                    "time-report-{}-{}.csv",
+                    query.from, query.to
+                ))],
+            };
+            HttpResponse::Ok()
+                .content_type("text/csv; charset=utf-8")
+                .insert_header(disposition)
+                .body(csv)
+        }
+        Err(e) => time_entry_error_response(e, "build time report"),
+    }
+}
+
+#[delete("/{id}")]
+pub async fn delete_time_entry(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let entry_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    match time_entries::delete(conn, current_user_id, entry_id) {
+        Ok(()) => HttpResponse::Ok().json(json!({
+            "message": "Time entry deleted successfully"
+        })),
+        Err(e) => time_entry_error_response(e, "delete time entry"),
+    }
+}
@@ -0,0 +1,156 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use diesel::prelude::*;
+use log::error;
+use serde_json::json;
+use uuid::Uuid;
+
+use super::{get_auth_context, get_current_user_id, require_admin};
+use crate::{
+    models::{TrashQuery, UserResponse},
+    task_responses,
+    trash::{self, TrashError},
+    DbPool,
+};
+
+#[get("/")]
+pub async fn get_trash(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    query: web::Query<TrashQuery>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    match trash::list_tasks(conn, current_user_id, &query) {
+        Ok(page) => HttpResponse::Ok().json(json!({
+            "tasks": page.items,
+            "total": page.total,
+            "next_offset": page.next_offset
+        })),
+        Err(e) => {
+            error!("Failed to fetch trash: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch trash"
+            }))
+        }
+    }
+}
+
+#[post("/tasks/{id}/restore")]
+pub async fn restore_task(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let task_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    let result = conn.transaction(|conn| {
+        let task = trash::find_task(conn, current_user_id, task_id)?;
+        let restored = trash::restore_task(conn, &task, current_user_id)?;
+        Ok::<_, TrashError>(task_responses::build_one(conn, restored)?)
+    });
+
+    match result {
+        Ok(task) => HttpResponse::Ok().json(json!({
+            "message": "Task restored successfully",
+            "task": task
+        })),
+        Err(TrashError::NotFound) => HttpResponse::NotFound().json(json!({
+            "error": "Task not found"
+        })),
+        Err(TrashError::Conflict(message)) => HttpResponse::Conflict().json(json!({
+            "error": message
+        })),
+        Err(TrashError::Database(e)) => {
+            error!("Failed to restore task: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to restore task"
+            }))
+        }
+    }
+}
+
+#[get("/users")]
+pub async fn get_trashed_users(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    query: web::Query<TrashQuery>,
+) -> impl Responder {
+    let context = match get_auth_context(&req) {
+        Ok(context) => context,
+        Err(response) => return response,
+    };
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = require_admin(conn, context.user_id) {
+        return response;
+    }
+
+    match trash::list_users(conn, &query) {
+        Ok(page) => HttpResponse::Ok().json(json!({
+            "users": page.items,
+            "total": page.total,
+            "next_offset": page.next_offset
+        })),
+        Err(e) => {
+            error!("Failed to fetch trashed users: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch trashed users"
+            }))
+        }
+    }
+}
+
+#[post("/users/{id}/restore")]
+pub async fn restore_user(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let context = match get_auth_context(&req) {
+        Ok(context) => context,
+        Err(response) => return response,
+    };
+    let user_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = require_admin(conn, context.user_id) {
+        return response;
+    }
+
+    let result = conn.transaction(|conn| {
+        let user = trash::find_user(conn, user_id)?;
+        Ok::<_, TrashError>(trash::restore_user(conn, &user, context.user_id)?)
+    });
+
+    match result {
+        Ok(user) => HttpResponse::Ok().json(json!({
+            "message": "User restored successfully",
+            "user": UserResponse::from(user)
+        })),
+        Err(TrashError::NotFound) => HttpResponse::NotFound().json(json!({
+            "error": "User not found"
+        })),
+        Err(TrashError::Conflict(message)) => HttpResponse::Conflict().json(json!({
+            "error": message
+        })),
+        Err(TrashError::Database(e)) => {
+            error!("Failed to restore user: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to restore user"
+            }))
+        }
+    }
+}
@@ -1,40 +1,28 @@
-use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use actix_web::{
+    delete, get, http::header::IfMatch, patch, post, put, web, HttpRequest, HttpResponse, Responder,
+};
 use bcrypt::{hash, DEFAULT_COST};
-use diesel::prelude::*;
+use diesel::{prelude::*, sql_types::Bool};
 use log::error;
 use serde_json::json;
 use uuid::Uuid;
 use validator::Validate;
 
+use super::{
+    check_if_match, etag, forbid_impersonation, get_auth_context, get_current_user_id, if_match,
+    not_modified, precondition_failed, require_admin, validate_new_user,
+};
 use crate::{
-    auth::extract_user_id_from_token,
-    models::{CreateUserRequest, NewUser, UpdateUserRequest, User, UserResponse},
+    models::{
+        CreateUserRequest, NewUser, ReplaceUserRequest, UpdateUserRequest, User, UserResponse,
+        DEFAULT_TIMEZONE,
+    },
+    projects,
     schema::users,
+    trash,
     DbPool,
 };
 
-// Helper function to extract user ID from Authorization header
-fn get_current_user_id(req: &HttpRequest) -> Result<Uuid, HttpResponse> {
-    let auth_header = req
-        .headers()
-        .get("Authorization")
-        .and_then(|h| h.to_str().ok())
-        .and_then(|h| h.strip_prefix("Bearer "));
-
-    match auth_header {
-        Some(token) => {
-            extract_user_id_from_token(token).map_err(|_| {
-                HttpResponse::Unauthorized().json(json!({
-                    "error": "Invalid or expired token"
-                }))
-            })
-        }
-        None => Err(HttpResponse::Unauthorized().json(json!({
-            "error": "Authorization header required"
-        }))),
-    }
-}
-
 #[get("/")]
 pub async fn get_users(pool: web::Data<DbPool>, req: HttpRequest) -> impl Responder {
     let _current_user_id = match get_current_user_id(&req) {
@@ -44,7 +32,10 @@ pub async fn get_users(pool: web::Data<DbPool>, req: HttpRequest) -> impl Respon
 
     let conn = &mut pool.get().expect("Failed to get DB connection");
 
-    let all_users: Vec<User> = match users::table.load(conn) {
+    let all_users: Vec<User> = match users::table
+        .filter(users::deleted_at.is_null())
+        .load(conn)
+    {
         Ok(users) => users,
         Err(e) => {
             error!("Failed to fetch users: {}", e);
@@ -77,6 +68,7 @@ pub async fn get_user(
 
     let user: User = match users::table
         .filter(users::id.eq(user_id))
+        .filter(users::deleted_at.is_null())
         .first(conn)
     {
         Ok(user) => user,
@@ -93,10 +85,16 @@ pub async fn get_user(
         }
     };
 
+    if let Some(response) = not_modified(&req, etag(user.version)) {
+        return response;
+    }
+
     let user_response: UserResponse = user.into();
-    HttpResponse::Ok().json(json!({
-        "user": user_response
-    }))
+    HttpResponse::Ok()
+        .insert_header(etag(user_response.version))
+        .json(json!({
+            "user": user_response
+        }))
 }
 
 #[post("/")]
@@ -105,21 +103,23 @@ pub async fn create_user(
     req: HttpRequest,
     user_data: web::Json<CreateUserRequest>,
 ) -> impl Responder {
-    let _current_user_id = match get_current_user_id(&req) {
-        Ok(id) => id,
+    let context = match get_auth_context(&req) {
+        Ok(context) => context,
         Err(response) => return response,
     };
 
-    // Validate input
-    if let Err(validation_errors) = user_data.validate() {
-        return HttpResponse::BadRequest().json(json!({
-            "error": "Validation failed",
-            "details": validation_errors
-        }));
+    // Validate input, including the password policy
+    if let Err(response) = validate_new_user(&user_data).await {
+        return response;
     }
 
     let conn = &mut pool.get().expect("Failed to get DB connection");
 
+    // Creating accounts directly bypasses the registration policy
+    if let Err(response) = require_admin(conn, context.user_id) {
+        return response;
+    }
+
     // Check if user already exists
     let existing_user: Result<User, diesel::result::Error> = users::table
         .filter(users::email.eq(&user_data.email))
@@ -149,10 +149,13 @@ pub async fn create_user(
         password_hash,
     };
 
-    let user: User = match diesel::insert_into(users::table)
-        .values(&new_user)
-        .get_result(conn)
-    {
+    let user: User = match conn.transaction::<User, diesel::result::Error, _>(|conn| {
+        let user: User = diesel::insert_into(users::table)
+            .values(&new_user)
+            .get_result(conn)?;
+        projects::inbox(conn, user.id)?;
+        Ok(user)
+    }) {
         Ok(user) => user,
         Err(e) => {
             error!("Failed to create user: {}", e);
@@ -171,16 +174,42 @@ pub async fn create_user(
 
 #[put("/{id}")]
 pub async fn update_user(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    user_data: web::Json<ReplaceUserRequest>,
+) -> impl Responder {
+    let user_data = UpdateUserRequest::from(user_data.into_inner());
+    update_user_response(&pool, &req, path.into_inner(), &user_data)
+}
+
+#[patch("/{id}")]
+pub async fn patch_user(
     pool: web::Data<DbPool>,
     req: HttpRequest,
     path: web::Path<Uuid>,
     user_data: web::Json<UpdateUserRequest>,
 ) -> impl Responder {
-    let current_user_id = match get_current_user_id(&req) {
-        Ok(id) => id,
+    update_user_response(&pool, &req, path.into_inner(), &user_data)
+}
+
+// Applies a merge patch to the user's own profile
+fn update_user_response(
+    pool: &DbPool,
+    req: &HttpRequest,
+    user_id: Uuid,
+    user_data: &UpdateUserRequest,
+) -> HttpResponse {
+    let context = match get_auth_context(req) {
+        Ok(context) => context,
         Err(response) => return response,
     };
-    let user_id = path.into_inner();
+    let current_user_id = context.user_id;
+
+    // Account changes must be made by the account holder, not an impersonating admin
+    if let Err(response) = forbid_impersonation(&context) {
+        return response;
+    }
 
     // Only allow users to update their own profile
     if current_user_id != user_id {
@@ -202,6 +231,7 @@ pub async fn update_user(
     // Check if user exists
     let existing_user: User = match users::table
         .filter(users::id.eq(user_id))
+        .filter(users::deleted_at.is_null())
         .first(conn)
     {
         Ok(user) => user,
@@ -217,16 +247,33 @@ pub async fn update_user(
             }));
         }
     };
+    let precondition = if_match(req);
+    if let Err(response) = check_if_match(&precondition, existing_user.version) {
+        return response;
+    }
+    // Nothing to set, and an UPDATE without columns isn't valid SQL
+    if user_data.is_empty() {
+        return user_updated(existing_user);
+    }
 
-    // Update user
-    let updated_user: User = match diesel::update(users::table.filter(users::id.eq(user_id)))
+    // Update user, unless it changed since the precondition was checked
+    let unconditional = matches!(precondition, IfMatch::Any).into_sql::<Bool>();
+    let target = users::table
+        .filter(users::id.eq(user_id))
+        .filter(users::version.eq(existing_user.version).or(unconditional));
+    let updated_user: User = match diesel::update(target)
         .set((
             user_data.username.as_ref().map(|u| users::username.eq(u)),
             user_data.email.as_ref().map(|e| users::email.eq(e)),
+            user_data
+                .timezone
+                .as_ref()
+                .map(|tz| users::timezone.eq(tz.as_deref().unwrap_or(DEFAULT_TIMEZONE))),
         ))
         .get_result(conn)
     {
         Ok(user) => user,
+        Err(diesel::result::Error::NotFound) => return precondition_failed(),
         Err(e) => {
             error!("Failed to update user: {}", e);
             return HttpResponse::InternalServerError().json(json!({
@@ -235,11 +282,17 @@ pub async fn update_user(
         }
     };
 
-    let user_response: UserResponse = updated_user.into();
-    HttpResponse::Ok().json(json!({
-        "message": "User updated successfully",
-        "user": user_response
-    }))
+    user_updated(updated_user)
+}
+
+fn user_updated(user: User) -> HttpResponse {
+    let user_response: UserResponse = user.into();
+    HttpResponse::Ok()
+        .insert_header(etag(user_response.version))
+        .json(json!({
+            "message": "User updated successfully",
+            "user": user_response
+        }))
 }
 
 #[delete("/{id}")]
@@ -248,12 +301,18 @@ pub async fn delete_user(
     req: HttpRequest,
     path: web::Path<Uuid>,
 ) -> impl Responder {
-    let current_user_id = match get_current_user_id(&req) {
-        Ok(id) => id,
+    let context = match get_auth_context(&req) {
+        Ok(context) => context,
         Err(response) => return response,
     };
+    let current_user_id = context.user_id;
     let user_id = path.into_inner();
 
+    // Account changes must be made by the account holder, not an impersonating admin
+    if let Err(response) = forbid_impersonation(&context) {
+        return response;
+    }
+
     // Only allow users to delete their own account
     if current_user_id != user_id {
         return HttpResponse::Forbidden().json(json!({
@@ -266,6 +325,7 @@ pub async fn delete_user(
     // Check if user exists
     let existing_user: User = match users::table
         .filter(users::id.eq(user_id))
+        .filter(users::deleted_at.is_null())
         .first(conn)
     {
         Ok(user) => user,
@@ -281,12 +341,32 @@ pub async fn delete_user(
             }));
         }
     };
+    let precondition = if_match(&req);
+    if let Err(response) = check_if_match(&precondition, existing_user.version) {
+        return response;
+    }
 
-    // Delete user
-    match diesel::delete(users::table.filter(users::id.eq(user_id))).execute(conn) {
-        Ok(_) => HttpResponse::Ok().json(json!({
+    // Move the user to the trash, unless it changed since the precondition was checked
+    let unconditional = matches!(precondition, IfMatch::Any).into_sql::<Bool>();
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let current: Option<Uuid> = users::table
+            .filter(users::id.eq(user_id))
+            .filter(users::version.eq(existing_user.version).or(unconditional))
+            .select(users::id)
+            .for_update()
+            .first(conn)
+            .optional()?;
+        if current.is_none() {
+            return Ok(false);
+        }
+        trash::delete_user(conn, user_id)?;
+        Ok(true)
+    });
+    match result {
+        Ok(true) => HttpResponse::Ok().json(json!({
             "message": "User deleted successfully"
         })),
+        Ok(false) => precondition_failed(),
         Err(e) => {
             error!("Failed to delete user: {}", e);
             HttpResponse::InternalServerError().json(json!({
@@ -295,3 +375,39 @@ pub async fn delete_user(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bearer, pool, send, user};
+    use actix_web::{http::StatusCode, test::TestRequest};
+
+    fn patch(user: &User, if_match: Option<String>) -> TestRequest {
+        let request = TestRequest::patch()
+            .uri(&format!("/api/users/{}", user.id))
+            .insert_header(bearer(user.id))
+            .set_json(json!({}));
+        match if_match {
+            Some(tag) => request.insert_header(("If-Match", tag)),
+            None => request,
+        }
+    }
+
+    #[actix_web::test]
+    async fn empty_patch_returns_the_user_unchanged() {
+        let Some(pool) = pool() else { return };
+        let user = user(&pool);
+
+        let (status, body) = send(&pool, patch(&user, None)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["user"]["username"], json!(user.username));
+        assert_eq!(body["user"]["version"], json!(user.version));
+
+        let current = format!("\"{}\"", user.version);
+        let (status, _) = send(&pool, patch(&user, Some(current))).await;
+        assert_eq!(status, StatusCode::OK);
+        let stale = format!("\"{}\"", user.version - 1);
+        let (status, _) = send(&pool, patch(&user, Some(stale))).await;
+        assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    }
+}
@@ -0,0 +1,173 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use log::{error, info, warn};
+use serde_json::json;
+use sha2::Sha256;
+
+use super::find_user_by_subject;
+use crate::{
+    keycloak_events::{KeycloakEvent, UserChange},
+    models::{NewKeycloakEvent, User},
+    schema::{keycloak_events, users},
+    DbPool,
+};
+
+const SIGNATURE_HEADER: &str = "X-Keycloak-Signature";
+
+// Helper function to verify the HMAC-SHA256 signature of the raw body
+#[allow(clippy::result_large_err)]
+fn verify_signature(req: &HttpRequest, body: &[u8]) -> Result<(), HttpResponse> {
+    let secret = match std::env::var("KEYCLOAK_WEBHOOK_SECRET") {
+        Ok(secret) if !secret.is_empty() => secret,
+        _ => {
+            return Err(HttpResponse::Unauthorized().json(json!({
+                "error": "Keycloak webhook is not enabled"
+            })));
+        }
+    };
+
+    let signature = req
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .map(|h| h.trim().trim_start_matches("sha256="))
+        .and_then(|h| hex::decode(h).ok());
+
+    let Some(signature) = signature else {
+        return Err(HttpResponse::Unauthorized().json(json!({
+            "error": "Missing or malformed signature"
+        })));
+    };
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac.verify_slice(&signature).map_err(|_| {
+        HttpResponse::Unauthorized().json(json!({
+            "error": "Invalid signature"
+        }))
+    })
+}
+
+fn apply_change(conn: &mut PgConnection, user: &User, change: &UserChange) -> QueryResult<()> {
+    let target = users::table.filter(users::id.eq(user.id));
+    let now = Utc::now();
+    match change {
+        // Local data is kept; the account is deactivated like a SCIM deprovision
+        UserChange::Deleted | UserChange::Disabled => {
+            diesel::update(target)
+                .set((users::active.eq(false), users::tokens_valid_after.eq(now)))
+                .execute(conn)?;
+        }
+        UserChange::Enabled => {
+            diesel::update(target)
+                .set(users::active.eq(true))
+                .execute(conn)?;
+        }
+        UserChange::Logout => {
+            diesel::update(target)
+                .set(users::tokens_valid_after.eq(now))
+                .execute(conn)?;
+        }
+        UserChange::EmailUpdated(email) => {
+            // Run in a savepoint so a clash with another account's email
+            // doesn't abort the rest of the event
+            let result = conn.transaction(|conn| {
+                diesel::update(target)
+                    .set(users::email.eq(email))
+                    .execute(conn)
+            });
+            if let Err(e) = result {
+                warn!("Could not update email for user {}: {}", user.id, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[post("/keycloak")]
+pub async fn keycloak_webhook(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> impl Responder {
+    if let Err(response) = verify_signature(&req, &body) {
+        return response;
+    }
+
+    let event: KeycloakEvent = match serde_json::from_slice(&body) {
+        Ok(event) => event,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": format!("Invalid event payload: {}", e)
+            }));
+        }
+    };
+
+    let event_id = event.event_id(&body);
+    let interpreted = event.interpret();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    // Record the event and apply it atomically; a redelivery of the same id
+    // finds the existing row and is acknowledged without side effects
+    let result = conn.transaction::<Option<String>, diesel::result::Error, _>(|conn| {
+        let user = match &interpreted.keycloak_user_id {
+            Some(keycloak_user_id) => find_user_by_subject(conn, keycloak_user_id)?,
+            None => None,
+        };
+
+        let outcome = match (&user, interpreted.changes.is_empty()) {
+            (_, true) => "ignored",
+            (None, false) => "unknown_user",
+            (Some(_), false) => "applied",
+        };
+
+        let inserted = diesel::insert_into(keycloak_events::table)
+            .values(&NewKeycloakEvent {
+                id: event_id.clone(),
+                event_type: interpreted.event_type.clone(),
+                keycloak_user_id: interpreted.keycloak_user_id.clone(),
+                user_id: user.as_ref().map(|u| u.id),
+                outcome: outcome.to_string(),
+            })
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+
+        if inserted == 0 {
+            return Ok(None);
+        }
+
+        if let Some(user) = &user {
+            for change in &interpreted.changes {
+                apply_change(conn, user, change)?;
+            }
+        }
+
+        Ok(Some(outcome.to_string()))
+    });
+
+    match result {
+        Ok(Some(outcome)) => {
+            info!(
+                "Processed Keycloak event {} ({}): {}",
+                event_id, interpreted.event_type, outcome
+            );
+            HttpResponse::Ok().json(json!({
+                "status": "processed",
+                "outcome": outcome
+            }))
+        }
+        Ok(None) => HttpResponse::Ok().json(json!({
+            "status": "duplicate"
+        })),
+        Err(e) => {
+            error!("Failed to process Keycloak event {}: {}", event_id, e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to process event"
+            }))
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! Project workflows (`/api/projects/{id}/workflow`) and the Kanban board
+//! built from them (`/api/projects/{id}/board`).
+
+use actix_web::{get, put, web, HttpRequest, HttpResponse, Responder};
+use diesel::prelude::*;
+use log::error;
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+use super::{get_current_user_id, projects::fetch_project};
+use crate::{
+    models::{BoardQuery, GrantRole, WorkflowRequest},
+    workflows::{self, WorkflowError},
+    DbPool,
+};
+
+#[get("/{id}/workflow")]
+pub async fn get_workflow(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let project_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = fetch_project(
+        conn,
+        current_user_id,
+        project_id,
+        GrantRole::Viewer,
+        "fetch workflow",
+    ) {
+        return response;
+    }
+
+    match workflows::statuses(conn, project_id) {
+        Ok(statuses) => HttpResponse::Ok().json(json!({
+            "statuses": statuses
+        })),
+        Err(e) => {
+            error!("Failed to fetch workflow: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch workflow"
+            }))
+        }
+    }
+}
+
+#[put("/{id}/workflow")]
+pub async fn replace_workflow(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    workflow_data: web::Json<WorkflowRequest>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let project_id = path.into_inner();
+
+    // Validate input
+    if let Err(validation_errors) = workflow_data.validate() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": "Validation failed",
+            "details": validation_errors
+        }));
+    }
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = fetch_project(
+        conn,
+        current_user_id,
+        project_id,
+        GrantRole::Owner,
+        "update workflow",
+    ) {
+        return response;
+    }
+
+    match conn.transaction(|conn| {
+        workflows::replace(conn, project_id, &workflow_data, current_user_id)
+    }) {
+        Ok(statuses) => HttpResponse::Ok().json(json!({
+            "message": "Workflow updated successfully",
+            "statuses": statuses
+        })),
+        Err(WorkflowError::Invalid(message)) => HttpResponse::BadRequest().json(json!({
+            "error": message
+        })),
+        Err(WorkflowError::Conflict(message)) => HttpResponse::Conflict().json(json!({
+            "error": message
+        })),
+        Err(WorkflowError::Database(e)) => {
+            error!("Failed to update workflow: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to update workflow"
+            }))
+        }
+    }
+}
+
+#[get("/{id}/board")]
+pub async fn get_board(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<BoardQuery>,
+) -> impl Responder {
+    let current_user_id = match get_current_user_id(&req) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let project_id = path.into_inner();
+
+    let conn = &mut pool.get().expect("Failed to get DB connection");
+
+    if let Err(response) = fetch_project(
+        conn,
+        current_user_id,
+        project_id,
+        GrantRole::Viewer,
+        "fetch board",
+    ) {
+        return response;
+    }
+
+    match workflows::board(conn, current_user_id, project_id, &query) {
+        Ok(columns) => HttpResponse::Ok().json(json!({
+            "project_id": project_id,
+            "columns": columns
+        })),
+        Err(e) => {
+            error!("Failed to fetch board: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to fetch board"
+            }))
+        }
+    }
+}
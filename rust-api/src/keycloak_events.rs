@@ -0,0 +1,133 @@
+//! Keycloak user and admin events, as delivered by the events webhook.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// A Keycloak `Event` (user events such as `LOGOUT`) or `AdminEvent`
+/// (`operationType`/`resourcePath`). Only the fields we act on are read.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeycloakEvent {
+    pub id: Option<String>,
+    pub uid: Option<String>,
+    #[serde(rename = "type")]
+    pub event_type: Option<String>,
+    pub user_id: Option<String>,
+    #[serde(default)]
+    pub details: HashMap<String, String>,
+    pub operation_type: Option<String>,
+    pub resource_type: Option<String>,
+    pub resource_path: Option<String>,
+    // JSON-encoded UserRepresentation on admin events
+    pub representation: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct UserRepresentation {
+    enabled: Option<bool>,
+    email: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserChange {
+    Deleted,
+    Disabled,
+    Enabled,
+    EmailUpdated(String),
+    Logout,
+}
+
+#[derive(Debug, Clone)]
+pub struct InterpretedEvent {
+    pub event_type: String,
+    pub keycloak_user_id: Option<String>,
+    pub changes: Vec<UserChange>,
+}
+
+impl KeycloakEvent {
+    /// Identifier used for idempotency. Falls back to a hash of the payload
+    /// for senders that do not include an id.
+    pub fn event_id(&self, body: &[u8]) -> String {
+        self.id
+            .clone()
+            .or_else(|| self.uid.clone())
+            .unwrap_or_else(|| format!("sha256:{}", hex::encode(Sha256::digest(body))))
+    }
+
+    pub fn interpret(&self) -> InterpretedEvent {
+        match &self.operation_type {
+            Some(operation) => self.interpret_admin_event(operation),
+            None => self.interpret_user_event(),
+        }
+    }
+
+    fn interpret_admin_event(&self, operation: &str) -> InterpretedEvent {
+        let resource_type = self.resource_type.as_deref().unwrap_or("UNKNOWN");
+        let event_type = format!("admin.{}-{}", resource_type, operation);
+
+        // resourcePath is "users/{id}" or "users/{id}/<sub-resource>"
+        let mut segments = self.resource_path.as_deref().unwrap_or_default().split('/');
+        let keycloak_user_id = match (resource_type, segments.next(), segments.next()) {
+            ("USER", Some("users"), Some(id)) => Some(id.to_string()),
+            _ => None,
+        };
+        let sub_resource = segments.next();
+
+        let mut changes = Vec::new();
+        if keycloak_user_id.is_some() {
+            match (operation, sub_resource) {
+                ("DELETE", None) => changes.push(UserChange::Deleted),
+                ("UPDATE", None) => {
+                    let representation = self
+                        .representation
+                        .as_deref()
+                        .and_then(|r| serde_json::from_str::<UserRepresentation>(r).ok());
+                    if let Some(representation) = representation {
+                        match representation.enabled {
+                            Some(false) => changes.push(UserChange::Disabled),
+                            Some(true) => changes.push(UserChange::Enabled),
+                            None => {}
+                        }
+                        if let Some(email) = representation.email {
+                            changes.push(UserChange::EmailUpdated(email));
+                        }
+                    }
+                }
+                ("ACTION", Some("logout")) => changes.push(UserChange::Logout),
+                _ => {}
+            }
+        }
+
+        InterpretedEvent {
+            event_type,
+            keycloak_user_id,
+            changes,
+        }
+    }
+
+    fn interpret_user_event(&self) -> InterpretedEvent {
+        let raw_type = self.event_type.as_deref().unwrap_or("UNKNOWN");
+        // Some webhook plugins prefix user events with "access."
+        let kind = raw_type.strip_prefix("access.").unwrap_or(raw_type);
+
+        let changes = match kind {
+            "LOGOUT" => vec![UserChange::Logout],
+            "DELETE_ACCOUNT" => vec![UserChange::Deleted],
+            "UPDATE_EMAIL" => self
+                .details
+                .get("updated_email")
+                .or_else(|| self.details.get("email"))
+                .map(|email| vec![UserChange::EmailUpdated(email.clone())])
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        };
+
+        InterpretedEvent {
+            event_type: format!("user.{}", kind),
+            keycloak_user_id: self.user_id.clone(),
+            changes,
+        }
+    }
+}
@@ -1,15 +1,42 @@
 use actix_cors::Cors;
-use actix_web::{middleware::Logger, web, App, HttpServer, HttpResponse, Responder};
+use actix_web::{middleware::{from_fn, Logger}, web, App, HttpServer, HttpResponse, Responder};
 use diesel::pg::PgConnection;
 use diesel::r2d2::{self, ConnectionManager};
 use dotenvy::dotenv;
 use log::info;
 
+mod access;
+mod attachments;
+mod audit;
 mod auth;
+mod blob_store;
+mod comments;
 mod db;
+mod dependencies;
 mod handlers;
+mod keycloak_events;
 mod models;
+mod notifications;
+mod password_policy;
+mod projects;
+mod recurrence;
+mod registration;
+mod rrule;
 mod schema;
+mod scim;
+mod subtasks;
+mod tags;
+mod task_events;
+mod task_positions;
+mod task_query;
+mod task_responses;
+mod task_schedule;
+mod task_search;
+#[cfg(test)]
+mod test_support;
+mod time_entries;
+mod trash;
+mod workflows;
 
 pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 
@@ -39,6 +66,21 @@ async fn main() -> std::io::Result<()> {
     // Run database migrations
     db::run_migrations(&pool).expect("Failed to run migrations");
 
+    // Load policies now so configuration errors fail at startup
+    once_cell::sync::Lazy::force(&attachments::POLICY);
+    once_cell::sync::Lazy::force(&auth::IMPERSONATION_POLICY);
+    once_cell::sync::Lazy::force(&blob_store::STORE);
+    once_cell::sync::Lazy::force(&comments::POLICY);
+    once_cell::sync::Lazy::force(&password_policy::POLICY);
+    once_cell::sync::Lazy::force(&registration::POLICY);
+    once_cell::sync::Lazy::force(&subtasks::POLICY);
+    once_cell::sync::Lazy::force(&trash::POLICY);
+
+    // Permanently remove what has been in the trash past the retention period
+    trash::spawn_purge_job(pool.clone());
+    // Remove blobs of deleted attachments from the store
+    attachments::spawn_sweep_job(pool.clone());
+
     let port = std::env::var("PORT").unwrap_or_else(|_| "8080".to_string());
     let bind_address = format!("0.0.0.0:{}", port);
 
@@ -49,9 +91,11 @@ async fn main() -> std::io::Result<()> {
             .allow_any_origin()
             .allow_any_method()
             .allow_any_header()
+            .expose_any_header()
             .max_age(3600);
 
         App::new()
+            .wrap(from_fn(audit::impersonation_audit))
             .wrap(Logger::default())
             .wrap(cors)
             .app_data(web::Data::new(pool.clone()))
@@ -66,29 +110,124 @@ async fn main() -> std::io::Result<()> {
                     }
                 }))
             }))
-            .service(
-                web::scope("/api")
-                    .service(handlers::auth::register)
-                    .service(handlers::auth::login)
-                    .service(
-                        web::scope("/users")
-                            .service(handlers::users::get_users)
-                            .service(handlers::users::get_user)
-                            .service(handlers::users::create_user)
-                            .service(handlers::users::update_user)
-                            .service(handlers::users::delete_user),
-                    )
-                    .service(
-                        web::scope("/tasks")
-                            .service(handlers::tasks::get_tasks)
-                            .service(handlers::tasks::get_task)
-                            .service(handlers::tasks::create_task)
-                            .service(handlers::tasks::update_task)
-                            .service(handlers::tasks::delete_task),
-                    ),
-            )
+            .configure(routes)
     })
     .bind(bind_address)?
     .run()
     .await
 }
+
+// Everything but the health check and the welcome message, shared with the
+// tests
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/webhooks")
+            .service(handlers::webhooks::keycloak_webhook),
+    )
+    .service(
+        web::scope("/scim/v2/Users")
+            .service(handlers::scim::get_users)
+            .service(handlers::scim::get_user)
+            .service(handlers::scim::create_user)
+            .service(handlers::scim::replace_user)
+            .service(handlers::scim::patch_user)
+            .service(handlers::scim::delete_user),
+    )
+    .service(
+        web::scope("/api")
+            .service(handlers::auth::register)
+            .service(handlers::auth::login)
+            .service(
+                web::scope("/admin")
+                    .service(handlers::admin::impersonate)
+                    .service(handlers::admin::get_audit_log)
+                    .service(handlers::admin::create_invite)
+                    .service(handlers::admin::get_invites)
+                    .service(handlers::admin::delete_invite),
+            )
+            .service(
+                web::scope("/users")
+                    .service(handlers::users::get_users)
+                    .service(handlers::users::get_user)
+                    .service(handlers::users::create_user)
+                    .service(handlers::users::update_user)
+                    .service(handlers::users::patch_user)
+                    .service(handlers::users::delete_user),
+            )
+            .service(
+                web::scope("/projects")
+                    .service(handlers::projects::get_projects)
+                    .service(handlers::projects::get_project)
+                    .service(handlers::projects::create_project)
+                    .service(handlers::projects::update_project)
+                    .service(handlers::projects::delete_project)
+                    .service(handlers::projects::get_project_tasks)
+                    .service(handlers::projects::create_project_task)
+                    .service(handlers::workflows::get_workflow)
+                    .service(handlers::workflows::replace_workflow)
+                    .service(handlers::workflows::get_board)
+                    .service(handlers::collaborators::get_project_collaborators)
+                    .service(handlers::collaborators::add_project_collaborator)
+                    .service(handlers::collaborators::remove_project_collaborator),
+            )
+            .service(
+                web::scope("/tags")
+                    .service(handlers::tags::get_tags)
+                    .service(handlers::tags::get_tag)
+                    .service(handlers::tags::create_tag)
+                    .service(handlers::tags::update_tag)
+                    .service(handlers::tags::delete_tag),
+            )
+            .service(
+                web::scope("/tasks")
+                    .service(handlers::tasks::get_tasks)
+                    .service(handlers::tasks::search_tasks)
+                    .service(handlers::tasks::get_today_tasks)
+                    .service(handlers::tasks::get_upcoming_tasks)
+                    .service(handlers::tasks::get_overdue_tasks)
+                    .service(handlers::tasks::get_next_tasks)
+                    .service(handlers::bulk::bulk_tasks)
+                    .service(handlers::tasks::get_task)
+                    .service(handlers::tasks::create_task)
+                    .service(handlers::tasks::update_task)
+                    .service(handlers::tasks::patch_task)
+                    .service(handlers::tasks::delete_task)
+                    .service(handlers::tasks::skip_occurrence)
+                    .service(handlers::tasks::move_task)
+                    .service(handlers::tasks::get_task_history)
+                    .service(handlers::time_entries::start_timer)
+                    .service(handlers::time_entries::stop_timer)
+                    .service(handlers::time_entries::get_task_time)
+                    .service(handlers::dependencies::get_dependencies)
+                    .service(handlers::dependencies::add_dependency)
+                    .service(handlers::dependencies::remove_dependency)
+                    .service(handlers::collaborators::get_task_collaborators)
+                    .service(handlers::collaborators::add_task_collaborator)
+                    .service(handlers::collaborators::remove_task_collaborator)
+                    .service(handlers::comments::get_comments)
+                    .service(handlers::comments::create_comment)
+                    .service(handlers::comments::update_comment)
+                    .service(handlers::comments::delete_comment)
+                    .service(handlers::attachments::get_attachments)
+                    .service(handlers::attachments::upload_attachment)
+                    .service(handlers::attachments::download_attachment)
+                    .service(handlers::attachments::delete_attachment),
+            )
+            .service(
+                web::scope("/time-entries")
+                    .service(handlers::time_entries::get_time_entries)
+                    .service(handlers::time_entries::create_time_entry)
+                    .service(handlers::time_entries::get_running_timer)
+                    .service(handlers::time_entries::get_daily_time)
+                    .service(handlers::time_entries::get_time_report)
+                    .service(handlers::time_entries::delete_time_entry),
+            )
+            .service(
+                web::scope("/trash")
+                    .service(handlers::trash::get_trash)
+                    .service(handlers::trash::restore_task)
+                    .service(handlers::trash::get_trashed_users)
+                    .service(handlers::trash::restore_user),
+            ),
+    );
+}
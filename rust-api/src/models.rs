@@ -1,10 +1,61 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
 use diesel::prelude::*;
-use serde::{Deserialize, Serialize};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::{SmallInt, Text};
+use serde::{Deserialize, Deserializer, Serialize};
 use uuid::Uuid;
-use validator::Validate;
+use validator::{Validate, ValidationError, ValidationErrors};
 
-use crate::schema::{tasks, users};
+use crate::password_policy;
+use crate::schema::{
+    access_grants, audit_log, keycloak_events, notification_events, project_status_transitions,
+    project_statuses, projects, registration_invites,
+    tags, task_attachments, task_comments, task_dependencies, task_events, task_series,
+    task_series_exceptions, task_tags, tasks, time_entries, users,
+};
+
+pub const ROLE_ADMIN: &str = "admin";
+
+// Stored for users without a local password (e.g. provisioned over SCIM).
+// It is not a valid bcrypt hash, so password login always fails.
+pub const UNUSABLE_PASSWORD_HASH: &str = "!";
+
+// Time zone of users who haven't chosen one
+pub const DEFAULT_TIMEZONE: &str = "UTC";
+
+// Distinguishes an explicit `null` (`Some(None)`, clear the field) from an
+// absent field (`None`, leave it unchanged)
+fn deserialize_nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+// For merge patch fields that can't be removed: absent is `None`, and an
+// explicit `null` is rejected rather than ignored
+fn deserialize_non_null<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+fn validate_timezone(timezone: &str) -> Result<(), ValidationError> {
+    match timezone.parse::<chrono_tz::Tz>() {
+        Ok(_) => Ok(()),
+        Err(_) => {
+            let mut error = ValidationError::new("timezone");
+            error.message = Some("Must be an IANA time zone name such as Europe/Berlin".into());
+            Err(error)
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable)]
 #[diesel(table_name = users)]
@@ -15,6 +66,19 @@ pub struct User {
     pub password_hash: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub role: String,
+    pub active: bool,
+    pub external_id: Option<String>,
+    pub tokens_valid_after: Option<DateTime<Utc>>,
+    pub timezone: String,
+    pub version: i32,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl User {
+    pub fn is_admin(&self) -> bool {
+        self.role == ROLE_ADMIN
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
@@ -25,22 +89,83 @@ pub struct NewUser {
     pub password_hash: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = users)]
+pub struct NewScimUser {
+    pub username: String,
+    pub email: String,
+    pub password_hash: String,
+    pub external_id: Option<String>,
+    pub active: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct CreateUserRequest {
     #[validate(length(min = 3, max = 50))]
     pub username: String,
     #[validate(email)]
     pub email: String,
-    #[validate(length(min = 8))]
+    // Checked against the configurable password policy, see `validate_with_password_policy`
     pub password: String,
+    // Required by `/api/register` when registration is invite-only
+    pub invite_code: Option<String>,
 }
 
+impl CreateUserRequest {
+    /// Runs the derived validators and the password policy, merging both
+    /// into one set of errors so clients see a single `details` object.
+    pub fn validate_with_password_policy(&self) -> Result<(), ValidationErrors> {
+        let mut errors = self.validate().err().unwrap_or_default();
+        for error in password_policy::POLICY.check(&self.password, &[&self.username, &self.email]) {
+            errors.add("password", error);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// RFC 7396 merge patch of a user's profile.
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct UpdateUserRequest {
+    #[serde(default, deserialize_with = "deserialize_non_null")]
     #[validate(length(min = 3, max = 50))]
     pub username: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_non_null")]
     #[validate(email)]
     pub email: Option<String>,
+    // `null` resets it to UTC
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    #[validate(custom = "validate_timezone")]
+    pub timezone: Option<Option<String>>,
+}
+
+/// The whole profile, replacing the current one; `PUT /api/users/{id}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplaceUserRequest {
+    pub username: String,
+    pub email: String,
+    // Defaults to UTC
+    pub timezone: Option<String>,
+}
+
+impl UpdateUserRequest {
+    /// Whether the patch leaves every field as it is.
+    pub fn is_empty(&self) -> bool {
+        self.username.is_none() && self.email.is_none() && self.timezone.is_none()
+    }
+}
+
+impl From<ReplaceUserRequest> for UpdateUserRequest {
+    fn from(user: ReplaceUserRequest) -> Self {
+        Self {
+            username: Some(user.username),
+            email: Some(user.email),
+            timezone: Some(user.timezone),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
@@ -61,7 +186,11 @@ pub struct UserResponse {
     pub id: Uuid,
     pub username: String,
     pub email: String,
+    pub role: String,
+    pub active: bool,
+    pub timezone: String,
     pub created_at: DateTime<Utc>,
+    pub version: i32,
 }
 
 impl From<User> for UserResponse {
@@ -70,7 +199,46 @@ impl From<User> for UserResponse {
             id: user.id,
             username: user.username,
             email: user.email,
+            role: user.role,
+            active: user.active,
+            timezone: user.timezone,
             created_at: user.created_at,
+            version: user.version,
+        }
+    }
+}
+
+/// Stored as a SMALLINT so that sorting by priority follows urgency.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+    AsExpression, FromSqlRow,
+)]
+#[diesel(sql_type = SmallInt)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskPriority {
+    #[default]
+    None = 0,
+    Low = 1,
+    Medium = 2,
+    High = 3,
+    Urgent = 4,
+}
+
+impl ToSql<SmallInt, Pg> for TaskPriority {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <i16 as ToSql<SmallInt, Pg>>::to_sql(&(*self as i16), &mut out.reborrow())
+    }
+}
+
+impl FromSql<SmallInt, Pg> for TaskPriority {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <i16 as FromSql<SmallInt, Pg>>::from_sql(bytes)? {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Low),
+            2 => Ok(Self::Medium),
+            3 => Ok(Self::High),
+            4 => Ok(Self::Urgent),
+            n => Err(format!("Unknown task priority {}", n).into()),
         }
     }
 }
@@ -86,6 +254,18 @@ pub struct Task {
     pub user_id: Uuid,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub due_date: Option<NaiveDate>,
+    pub priority: TaskPriority,
+    pub project_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub assignee_id: Option<Uuid>,
+    pub series_id: Option<Uuid>,
+    pub occurrence_at: Option<DateTime<Utc>>,
+    pub version: i32,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub position: String,
+    pub status_id: Uuid,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
@@ -94,21 +274,341 @@ pub struct NewTask {
     pub title: String,
     pub description: Option<String>,
     pub user_id: Uuid,
+    pub due_at: Option<DateTime<Utc>>,
+    pub due_date: Option<NaiveDate>,
+    pub priority: TaskPriority,
+    pub project_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub assignee_id: Option<Uuid>,
+    pub series_id: Option<Uuid>,
+    pub occurrence_at: Option<DateTime<Utc>>,
+}
+
+pub const MAX_TAGS_PER_TASK: usize = 20;
+
+fn validate_tag_names(names: &[String]) -> Result<(), ValidationError> {
+    if names.len() > MAX_TAGS_PER_TASK {
+        let mut error = ValidationError::new("too_many_tags");
+        error.message = Some(format!("At most {} tags per task", MAX_TAGS_PER_TASK).into());
+        return Err(error);
+    }
+    if names.iter().any(|name| name.trim().is_empty() || name.trim().chars().count() > 50) {
+        let mut error = ValidationError::new("tag_name");
+        error.message = Some("Tag names must be 1 to 50 characters".into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+fn validate_color(color: &str) -> Result<(), ValidationError> {
+    let valid = color.len() == 7
+        && color.starts_with('#')
+        && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+    if valid {
+        Ok(())
+    } else {
+        let mut error = ValidationError::new("color");
+        error.message = Some("Must be a hex color such as #1e90ff".into());
+        Err(error)
+    }
+}
+
+fn single_due_error() -> ValidationError {
+    let mut error = ValidationError::new("single_due");
+    error.message = Some("Set either due_at or due_date, not both".into());
+    error
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_create_task_due"))]
 pub struct CreateTaskRequest {
     #[validate(length(min = 1, max = 200))]
     pub title: String,
     pub description: Option<String>,
+    pub due_at: Option<DateTime<Utc>>,
+    // All-day deadline, interpreted in the owner's time zone
+    pub due_date: Option<NaiveDate>,
+    pub priority: Option<TaskPriority>,
+    // Tag names; tags that don't exist yet are created
+    #[validate(custom = "validate_tag_names")]
+    pub tags: Option<Vec<String>>,
+    // Defaults to the user's inbox, or the parent's project for subtasks
+    pub project_id: Option<Uuid>,
+    pub parent_id: Option<Uuid>,
+    // Must be able to see the task
+    pub assignee_id: Option<Uuid>,
+    // A status of the task's project; defaults to its first open status
+    pub status_id: Option<Uuid>,
+    // Makes the task the first occurrence of a series; needs a due date
+    #[validate]
+    pub recurrence: Option<RecurrenceRequest>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+fn validate_create_task_due(task: &CreateTaskRequest) -> Result<(), ValidationError> {
+    match (task.due_at, task.due_date) {
+        (Some(_), Some(_)) => Err(single_due_error()),
+        _ => Ok(()),
+    }
+}
+
+/// RFC 7396 merge patch of a task: absent fields are left alone and `null`
+/// clears a field, or resets it to its default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_update_task_due"))]
 pub struct UpdateTaskRequest {
+    #[serde(default, deserialize_with = "deserialize_non_null")]
     #[validate(length(min = 1, max = 200))]
     pub title: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub description: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_non_null")]
+    pub completed: Option<bool>,
+    // Takes precedence over `completed`, which follows the status
+    #[serde(default, deserialize_with = "deserialize_non_null")]
+    pub status_id: Option<Uuid>,
+    // `null` clears the due date; setting one clears the other
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub due_at: Option<Option<DateTime<Utc>>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub due_date: Option<Option<NaiveDate>>,
+    // `null` resets it to `none`
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub priority: Option<Option<TaskPriority>>,
+    // Replaces the task's tags; `null` removes them all
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    #[validate(custom = "validate_tag_names")]
+    pub tags: Option<Option<Vec<String>>>,
+    // Moves the task to another of the user's projects
+    #[serde(default, deserialize_with = "deserialize_non_null")]
+    pub project_id: Option<Uuid>,
+    // `null` turns a subtask into a top-level task
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub parent_id: Option<Option<Uuid>>,
+    // `null` unassigns the task
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub assignee_id: Option<Option<Uuid>>,
+    // `null` ends the series; changing an existing one needs `scope=future`
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    #[validate]
+    pub recurrence: Option<Option<RecurrenceRequest>>,
+}
+
+fn validate_update_task_due(task: &UpdateTaskRequest) -> Result<(), ValidationError> {
+    match (task.due_at, task.due_date) {
+        (Some(Some(_)), Some(Some(_))) => Err(single_due_error()),
+        _ => Ok(()),
+    }
+}
+
+/// The whole task, replacing its fields; `PUT /api/tasks/{id}`. Omitted
+/// fields are cleared or reset, except the project (kept unless given or
+/// implied by the parent) and the task's recurrence (changed with `PATCH`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplaceTaskRequest {
+    pub title: String,
     pub description: Option<String>,
+    #[serde(default)]
+    pub completed: bool,
+    pub status_id: Option<Uuid>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub due_date: Option<NaiveDate>,
+    pub priority: Option<TaskPriority>,
+    pub tags: Option<Vec<String>>,
+    pub project_id: Option<Uuid>,
+    pub parent_id: Option<Uuid>,
+    pub assignee_id: Option<Uuid>,
+}
+
+impl From<ReplaceTaskRequest> for UpdateTaskRequest {
+    fn from(task: ReplaceTaskRequest) -> Self {
+        Self {
+            title: Some(task.title),
+            description: Some(task.description),
+            completed: Some(task.completed),
+            status_id: task.status_id,
+            due_at: Some(task.due_at),
+            due_date: Some(task.due_date),
+            priority: Some(task.priority),
+            tags: Some(task.tags),
+            project_id: task.project_id,
+            parent_id: Some(task.parent_id),
+            assignee_id: Some(task.assignee_id),
+            recurrence: None,
+        }
+    }
+}
+
+/// An RFC 5545 RRULE and the IANA time zone its occurrences are anchored in.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct RecurrenceRequest {
+    // e.g. `FREQ=WEEKLY;BYDAY=MO,TH`
+    #[validate(length(min = 1, max = 500))]
+    pub rrule: String,
+    // Defaults to the user's time zone
+    #[validate(custom = "validate_timezone")]
+    pub timezone: Option<String>,
+}
+
+/// Which occurrences of a recurring task an update or delete applies to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OccurrenceScope {
+    /// Only this occurrence
+    #[default]
+    This,
+    /// This occurrence and the ones created after it
+    Future,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OccurrenceScopeQuery {
+    pub scope: Option<OccurrenceScope>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SkipOccurrenceRequest {
+    // A later occurrence to skip instead of the current one
+    pub occurrence_at: Option<DateTime<Utc>>,
+}
+
+impl UpdateTaskRequest {
+    /// The `(due_at, due_date)` changes to apply, keeping the two exclusive.
+    #[allow(clippy::type_complexity)]
+    pub fn due_changes(&self) -> (Option<Option<DateTime<Utc>>>, Option<Option<NaiveDate>>) {
+        match (self.due_at, self.due_date) {
+            (Some(Some(due_at)), _) => (Some(Some(due_at)), Some(None)),
+            (_, Some(Some(due_date))) => (Some(None), Some(Some(due_date))),
+            changes => changes,
+        }
+    }
+}
+
+/// One operation of a bulk request, tagged by `op`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum BulkOperation {
+    Create {
+        task: CreateTaskRequest,
+    },
+    Update {
+        id: Uuid,
+        task: UpdateTaskRequest,
+        #[serde(default)]
+        scope: Option<OccurrenceScope>,
+    },
+    Complete {
+        id: Uuid,
+    },
+    Delete {
+        id: Uuid,
+        #[serde(default)]
+        scope: Option<OccurrenceScope>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BulkMode {
+    /// Apply every operation or none of them
+    #[default]
+    Atomic,
+    /// Apply the operations that succeed and report each one's result
+    Partial,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct BulkTaskRequest {
+    #[serde(default)]
+    pub mode: BulkMode,
+    #[validate(length(min = 1, max = 100))]
+    pub operations: Vec<BulkOperation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskListQuery {
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+    // Comma-separated fields, `-` prefix for descending (e.g. `-updated_at,title`)
+    pub sort: Option<String>,
+    pub include_total: Option<bool>,
     pub completed: Option<bool>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub updated_after: Option<DateTime<Utc>>,
+    pub updated_before: Option<DateTime<Utc>>,
+    pub title_contains: Option<String>,
+    pub project_id: Option<Uuid>,
+    pub parent_id: Option<Uuid>,
+    // Only tasks without a parent
+    pub top_level: Option<bool>,
+    // Comma-separated tag names
+    pub tag: Option<String>,
+    pub tag_mode: Option<TagMatch>,
+    pub assigned_to: Option<AssigneeFilter>,
+}
+
+/// `?assigned_to=` value: `me` or a user id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssigneeFilter {
+    Me,
+    User(Uuid),
+}
+
+impl Serialize for AssigneeFilter {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Me => serializer.serialize_str("me"),
+            Self::User(id) => serializer.collect_str(id),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for AssigneeFilter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        if value.eq_ignore_ascii_case("me") {
+            return Ok(Self::Me);
+        }
+        value
+            .parse::<Uuid>()
+            .map(Self::User)
+            .map_err(|_| serde::de::Error::custom("expected `me` or a user id"))
+    }
+}
+
+/// Whether `?tag=a,b` matches tasks with any or all of the tags.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatch {
+    #[default]
+    Any,
+    All,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskSearchQuery {
+    // Web search syntax: quoted phrases, `or`, `-excluded`
+    pub q: String,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpcomingTasksQuery {
+    pub days: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskDetailQuery {
+    // Include nested subtasks
+    pub subtree: Option<bool>,
+}
+
+/// Completed and total subtasks at any depth below a task.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct SubtaskProgress {
+    pub completed: i64,
+    pub total: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -120,10 +620,34 @@ pub struct TaskResponse {
     pub user_id: Uuid,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub due_date: Option<NaiveDate>,
+    pub priority: TaskPriority,
+    pub project_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub assignee_id: Option<Uuid>,
+    pub series_id: Option<Uuid>,
+    pub occurrence_at: Option<DateTime<Utc>>,
+    /// Sort key within the task's list (`sort=position`)
+    pub position: String,
+    pub status_id: Uuid,
+    pub tags: Vec<TagSummary>,
+    pub subtask_progress: SubtaskProgress,
+    /// Whether any task this one depends on is still open
+    pub blocked: bool,
+    /// Comments not deleted
+    pub comment_count: i64,
+    pub version: i32,
 }
 
-impl From<Task> for TaskResponse {
-    fn from(task: Task) -> Self {
+impl TaskResponse {
+    pub fn new(
+        task: Task,
+        tags: Vec<Tag>,
+        subtask_progress: SubtaskProgress,
+        blocked: bool,
+        comment_count: i64,
+    ) -> Self {
         Self {
             id: task.id,
             title: task.title,
@@ -132,8 +656,834 @@ impl From<Task> for TaskResponse {
             user_id: task.user_id,
             created_at: task.created_at,
             updated_at: task.updated_at,
+            due_at: task.due_at,
+            due_date: task.due_date,
+            priority: task.priority,
+            project_id: task.project_id,
+            parent_id: task.parent_id,
+            assignee_id: task.assignee_id,
+            series_id: task.series_id,
+            occurrence_at: task.occurrence_at,
+            position: task.position,
+            status_id: task.status_id,
+            tags: tags.into_iter().map(TagSummary::from).collect(),
+            subtask_progress,
+            blocked,
+            comment_count,
+            version: task.version,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = task_series)]
+pub struct TaskSeries {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub rrule: String,
+    pub timezone: String,
+    pub dtstart: NaiveDateTime,
+    pub all_day: bool,
+    pub title: String,
+    pub description: Option<String>,
+    pub priority: TaskPriority,
+    pub project_id: Uuid,
+    pub assignee_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// Also the changeset when a series is re-anchored, so cleared fields are cleared
+#[derive(Debug, Clone, Serialize, Deserialize, Insertable, AsChangeset)]
+#[diesel(table_name = task_series, treat_none_as_null = true)]
+pub struct NewTaskSeries {
+    pub user_id: Uuid,
+    pub rrule: String,
+    pub timezone: String,
+    pub dtstart: NaiveDateTime,
+    pub all_day: bool,
+    pub title: String,
+    pub description: Option<String>,
+    pub priority: TaskPriority,
+    pub project_id: Uuid,
+    pub assignee_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Insertable)]
+#[diesel(table_name = task_series_exceptions)]
+pub struct TaskSeriesException {
+    pub series_id: Uuid,
+    pub occurrence_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Insertable)]
+#[diesel(table_name = task_dependencies)]
+pub struct TaskDependency {
+    pub task_id: Uuid,
+    pub depends_on_id: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateDependencyRequest {
+    pub depends_on_id: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NextTasksQuery {
+    pub limit: Option<i64>,
+}
+
+/// Where to put a task in its list: after `after_id`, before `before_id`, or
+/// between the two when both are given.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoveTaskRequest {
+    pub after_id: Option<Uuid>,
+    pub before_id: Option<Uuid>,
+}
+
+pub const INBOX_PROJECT_NAME: &str = "Inbox";
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
+#[diesel(belongs_to(User))]
+#[diesel(table_name = projects)]
+pub struct Project {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub color: String,
+    pub archived: bool,
+    pub is_inbox: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = projects)]
+pub struct NewProject {
+    pub user_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    // None uses the column default
+    pub color: Option<String>,
+    pub is_inbox: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CreateProjectRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    pub description: Option<String>,
+    #[validate(custom = "validate_color")]
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct UpdateProjectRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    pub description: Option<Option<String>>,
+    #[validate(custom = "validate_color")]
+    pub color: Option<String>,
+    pub archived: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectListQuery {
+    pub include_archived: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
+#[diesel(belongs_to(Project))]
+#[diesel(table_name = project_statuses)]
+pub struct ProjectStatus {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    pub name: String,
+    pub done: bool,
+    pub position: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = project_statuses)]
+pub struct NewProjectStatus {
+    pub project_id: Uuid,
+    pub name: String,
+    pub done: bool,
+    pub position: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Insertable)]
+#[diesel(table_name = project_status_transitions)]
+pub struct StatusTransition {
+    pub from_status_id: Uuid,
+    pub to_status_id: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectStatusResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub done: bool,
+    pub position: i32,
+    /// Statuses a task in this one may move to
+    pub transitions_to: Vec<Uuid>,
+}
+
+/// The whole workflow of a project, replacing the current one. Statuses keep
+/// their ids when their names are kept, and are listed in board order.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct WorkflowRequest {
+    #[validate(length(min = 2, max = 20))]
+    #[validate]
+    pub statuses: Vec<WorkflowStatusRequest>,
+    #[serde(default)]
+    pub transitions: Vec<WorkflowTransitionRequest>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct WorkflowStatusRequest {
+    #[validate(length(min = 1, max = 50))]
+    pub name: String,
+    #[serde(default)]
+    pub done: bool,
+}
+
+/// An allowed move, by status name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowTransitionRequest {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BoardQuery {
+    // Tasks per column
+    pub limit: Option<i64>,
+    // Subtasks are left out unless true
+    pub include_subtasks: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BoardColumn {
+    pub status: ProjectStatusResponse,
+    pub tasks: Vec<TaskResponse>,
+    /// Tasks in the status, including those past `limit`
+    pub total: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
+#[diesel(belongs_to(User))]
+#[diesel(table_name = tags)]
+pub struct Tag {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub color: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = tags)]
+pub struct NewTag {
+    pub user_id: Uuid,
+    pub name: String,
+    // None uses the column default
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations, Insertable)]
+#[diesel(belongs_to(Task))]
+#[diesel(belongs_to(Tag))]
+#[diesel(table_name = task_tags)]
+#[diesel(primary_key(task_id, tag_id))]
+pub struct TaskTag {
+    pub task_id: Uuid,
+    pub tag_id: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CreateTagRequest {
+    #[validate(length(min = 1, max = 50))]
+    pub name: String,
+    #[validate(custom = "validate_color")]
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct UpdateTagRequest {
+    #[validate(length(min = 1, max = 50))]
+    pub name: Option<String>,
+    #[validate(custom = "validate_color")]
+    pub color: Option<String>,
+}
+
+/// A tag as embedded in task responses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagSummary {
+    pub id: Uuid,
+    pub name: String,
+    pub color: String,
+}
+
+impl From<Tag> for TagSummary {
+    fn from(tag: Tag) -> Self {
+        Self {
+            id: tag.id,
+            name: tag.name,
+            color: tag.color,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub color: String,
+    pub task_count: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TagResponse {
+    pub fn new(tag: Tag, task_count: i64) -> Self {
+        Self {
+            id: tag.id,
+            name: tag.name,
+            color: tag.color,
+            task_count,
+            created_at: tag.created_at,
+            updated_at: tag.updated_at,
         }
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = audit_log)]
+pub struct AuditLogEntry {
+    pub id: Uuid,
+    pub actor_id: Uuid,
+    pub subject_id: Option<Uuid>,
+    pub action: String,
+    pub method: Option<String>,
+    pub path: Option<String>,
+    pub status_code: Option<i32>,
+    pub details: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
 
+#[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = audit_log)]
+pub struct NewAuditLogEntry {
+    pub actor_id: Uuid,
+    pub subject_id: Option<Uuid>,
+    pub action: String,
+    pub method: Option<String>,
+    pub path: Option<String>,
+    pub status_code: Option<i32>,
+    pub details: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = notification_events)]
+pub struct NotificationEvent {
+    pub id: Uuid,
+    pub event_type: String,
+    pub recipient_id: Uuid,
+    pub actor_id: Option<Uuid>,
+    pub payload: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = notification_events)]
+pub struct NewNotificationEvent {
+    pub event_type: String,
+    pub recipient_id: Uuid,
+    pub actor_id: Option<Uuid>,
+    pub payload: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ImpersonateRequest {
+    pub user_id: Uuid,
+    #[validate(length(min = 3, max = 500))]
+    pub reason: String,
+    #[validate(range(min = 1))]
+    pub ttl_minutes: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditLogQuery {
+    pub actor_id: Option<Uuid>,
+    pub subject_id: Option<Uuid>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = registration_invites)]
+pub struct RegistrationInvite {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub code_hash: String,
+    pub email: Option<String>,
+    pub created_by: Option<Uuid>,
+    pub used_by: Option<Uuid>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = registration_invites)]
+pub struct NewRegistrationInvite {
+    pub code_hash: String,
+    pub email: Option<String>,
+    pub created_by: Option<Uuid>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CreateInviteRequest {
+    // Restricts the invite to this address when set
+    #[validate(email)]
+    pub email: Option<String>,
+    #[validate(range(min = 1, max = 8760))]
+    pub expires_in_hours: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = keycloak_events)]
+pub struct NewKeycloakEvent {
+    pub id: String,
+    pub event_type: String,
+    pub keycloak_user_id: Option<String>,
+    pub user_id: Option<Uuid>,
+    pub outcome: String,
+}
+
+/// Access level on a shared task or project; each level includes the ones
+/// below it.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, AsExpression,
+    FromSqlRow,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum GrantRole {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl GrantRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Editor => "editor",
+            Self::Owner => "owner",
+        }
+    }
+}
+
+impl std::fmt::Display for GrantRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl ToSql<Text, Pg> for GrantRole {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), &mut out.reborrow())
+    }
+}
+
+impl FromSql<Text, Pg> for GrantRole {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "viewer" => Ok(Self::Viewer),
+            "editor" => Ok(Self::Editor),
+            "owner" => Ok(Self::Owner),
+            other => Err(format!("Unknown grant role '{}'", other).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = access_grants)]
+pub struct AccessGrant {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub task_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub role: GrantRole,
+    pub granted_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = access_grants)]
+pub struct NewAccessGrant {
+    pub user_id: Uuid,
+    pub task_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub role: GrantRole,
+    pub granted_by: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_invitee"))]
+pub struct InviteCollaboratorRequest {
+    // Exactly one of email and username identifies the invitee
+    #[validate(email)]
+    pub email: Option<String>,
+    pub username: Option<String>,
+    pub role: GrantRole,
+}
+
+fn validate_invitee(invite: &InviteCollaboratorRequest) -> Result<(), ValidationError> {
+    match (&invite.email, &invite.username) {
+        (Some(_), None) | (None, Some(_)) => Ok(()),
+        _ => {
+            let mut error = ValidationError::new("invitee");
+            error.message = Some("Set either email or username".into());
+            Err(error)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserSummary {
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+}
+
+impl From<User> for UserSummary {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollaboratorResponse {
+    pub user: UserSummary,
+    pub role: GrantRole,
+    pub granted_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl CollaboratorResponse {
+    pub fn new(grant: AccessGrant, user: User) -> Self {
+        Self {
+            user: UserSummary::from(user),
+            role: grant.role,
+            granted_by: grant.granted_by,
+            created_at: grant.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
+#[diesel(belongs_to(Task))]
+#[diesel(belongs_to(User))]
+#[diesel(table_name = task_comments)]
+pub struct TaskComment {
+    pub id: Uuid,
+    pub task_id: Uuid,
+    pub user_id: Uuid,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = task_comments)]
+pub struct NewTaskComment {
+    pub task_id: Uuid,
+    pub user_id: Uuid,
+    pub body: String,
+}
+
+pub const MAX_COMMENT_LENGTH: u64 = 10_000;
+
+fn validate_comment_body(body: &str) -> Result<(), ValidationError> {
+    if body.trim().is_empty() {
+        let mut error = ValidationError::new("blank");
+        error.message = Some("Comment cannot be blank".into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CommentRequest {
+    #[validate(length(min = 1, max = "MAX_COMMENT_LENGTH"), custom = "validate_comment_body")]
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommentListQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommentResponse {
+    pub id: Uuid,
+    pub task_id: Uuid,
+    pub author: UserSummary,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
+}
+
+impl CommentResponse {
+    pub fn new(comment: TaskComment, author: User) -> Self {
+        Self {
+            id: comment.id,
+            task_id: comment.task_id,
+            author: UserSummary::from(author),
+            body: comment.body,
+            created_at: comment.created_at,
+            edited_at: comment.edited_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
+#[diesel(belongs_to(Task))]
+#[diesel(belongs_to(User))]
+#[diesel(table_name = task_attachments)]
+pub struct TaskAttachment {
+    pub id: Uuid,
+    pub task_id: Uuid,
+    pub user_id: Uuid,
+    pub filename: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub checksum_sha256: String,
+    #[serde(skip_serializing)]
+    pub storage_key: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = task_attachments)]
+pub struct NewTaskAttachment {
+    pub id: Uuid,
+    pub task_id: Uuid,
+    pub user_id: Uuid,
+    pub filename: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub checksum_sha256: String,
+    pub storage_key: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = task_events)]
+pub struct TaskEvent {
+    pub id: Uuid,
+    pub task_id: Uuid,
+    pub actor_id: Option<Uuid>,
+    pub event_type: String,
+    pub changes: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = task_events)]
+pub struct NewTaskEvent {
+    pub task_id: Uuid,
+    pub actor_id: Option<Uuid>,
+    pub event_type: String,
+    pub changes: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskHistoryQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskEventResponse {
+    pub id: Uuid,
+    pub event_type: String,
+    /// `None` once the acting user has been deleted
+    pub actor: Option<UserSummary>,
+    pub changes: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TaskEventResponse {
+    pub fn new(event: TaskEvent, actor: Option<User>) -> Self {
+        Self {
+            id: event.id,
+            event_type: event.event_type,
+            actor: actor.map(UserSummary::from),
+            changes: event.changes,
+            created_at: event.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// A deleted task with the time it will be purged.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrashedTaskResponse {
+    #[serde(flatten)]
+    pub task: TaskResponse,
+    pub deleted_at: DateTime<Utc>,
+    pub purge_at: DateTime<Utc>,
+}
+
+/// A deleted user with the time it will be purged.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrashedUserResponse {
+    #[serde(flatten)]
+    pub user: UserResponse,
+    pub deleted_at: DateTime<Utc>,
+    pub purge_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
+#[diesel(belongs_to(Task))]
+#[diesel(belongs_to(User))]
+#[diesel(table_name = time_entries)]
+pub struct TimeEntry {
+    pub id: Uuid,
+    pub task_id: Uuid,
+    pub user_id: Uuid,
+    pub started_at: DateTime<Utc>,
+    /// `None` while the timer is running
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = time_entries)]
+pub struct NewTimeEntry {
+    pub task_id: Uuid,
+    pub user_id: Uuid,
+    pub started_at: DateTime<Utc>,
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub note: Option<String>,
+}
+
+pub const MAX_TIME_ENTRY_NOTE_LENGTH: u64 = 1_000;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+pub struct StartTimerRequest {
+    #[validate(length(max = "MAX_TIME_ENTRY_NOTE_LENGTH"))]
+    pub note: Option<String>,
+}
+
+/// A time entry logged by hand rather than with the timer.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_time_entry_span"))]
+pub struct CreateTimeEntryRequest {
+    pub task_id: Uuid,
+    pub started_at: DateTime<Utc>,
+    pub stopped_at: DateTime<Utc>,
+    #[validate(length(max = "MAX_TIME_ENTRY_NOTE_LENGTH"))]
+    pub note: Option<String>,
+}
+
+fn validate_time_entry_span(entry: &CreateTimeEntryRequest) -> Result<(), ValidationError> {
+    if entry.stopped_at <= entry.started_at {
+        let mut error = ValidationError::new("time_entry_span");
+        error.message = Some("stopped_at must be after started_at".into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeEntryListQuery {
+    pub task_id: Option<Uuid>,
+    // Days in the user's time zone, both inclusive
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// An inclusive range of days in the user's time zone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeRangeQuery {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeEntryResponse {
+    pub id: Uuid,
+    pub task_id: Uuid,
+    pub user_id: Uuid,
+    pub started_at: DateTime<Utc>,
+    pub stopped_at: Option<DateTime<Utc>>,
+    /// Up to now for a running timer
+    pub duration_seconds: i64,
+    pub note: Option<String>,
+}
+
+impl TimeEntryResponse {
+    pub fn new(entry: TimeEntry, now: DateTime<Utc>) -> Self {
+        let stopped_at = entry.stopped_at.unwrap_or(now);
+        Self {
+            id: entry.id,
+            task_id: entry.task_id,
+            user_id: entry.user_id,
+            started_at: entry.started_at,
+            stopped_at: entry.stopped_at,
+            duration_seconds: (stopped_at - entry.started_at).num_seconds().max(0),
+            note: entry.note,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserTimeTotal {
+    pub user: UserSummary,
+    pub total_seconds: i64,
+}
+
+/// Time logged on a task by everyone, running timers included.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskTimeSummary {
+    pub task_id: Uuid,
+    pub total_seconds: i64,
+    pub entries: i64,
+    pub running: i64,
+    pub users: Vec<UserTimeTotal>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyTimeTotal {
+    pub date: NaiveDate,
+    pub total_seconds: i64,
+}
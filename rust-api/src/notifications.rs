@@ -0,0 +1,60 @@
+//! Outbox of events for the notification layer. Events are inserted in the
+//! transaction that causes them and announced with `NOTIFY` on the
+//! `notification_events` channel once it commits; consumers read rows with no
+//! `delivered_at` and set it when done.
+
+use diesel::prelude::*;
+use diesel::sql_types;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    models::{NewNotificationEvent, NotificationEvent, Task},
+    schema::notification_events,
+};
+
+pub const CHANNEL: &str = "notification_events";
+
+pub const EVENT_TASK_ASSIGNED: &str = "task.assigned";
+
+pub fn emit(
+    conn: &mut PgConnection,
+    event: NewNotificationEvent,
+) -> QueryResult<NotificationEvent> {
+    let event: NotificationEvent = diesel::insert_into(notification_events::table)
+        .values(&event)
+        .get_result(conn)?;
+    // Held back by Postgres until commit, so listeners never see rolled-back events
+    diesel::sql_query("SELECT pg_notify($1, $2)")
+        .bind::<sql_types::Text, _>(CHANNEL)
+        .bind::<sql_types::Text, _>(event.id.to_string())
+        .execute(conn)?;
+    Ok(event)
+}
+
+/// Tells the task's new assignee about it.
+pub fn task_assigned(
+    conn: &mut PgConnection,
+    task: &Task,
+    previous_assignee_id: Option<Uuid>,
+    actor_id: Uuid,
+) -> QueryResult<()> {
+    let Some(assignee_id) = task.assignee_id else {
+        return Ok(());
+    };
+    emit(
+        conn,
+        NewNotificationEvent {
+            event_type: EVENT_TASK_ASSIGNED.to_string(),
+            recipient_id: assignee_id,
+            actor_id: Some(actor_id),
+            payload: json!({
+                "task_id": task.id,
+                "title": task.title,
+                "project_id": task.project_id,
+                "previous_assignee_id": previous_assignee_id,
+            }),
+        },
+    )?;
+    Ok(())
+}
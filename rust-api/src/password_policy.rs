@@ -0,0 +1,382 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::info;
+use once_cell::sync::Lazy;
+use sha1::{Digest, Sha1};
+use validator::ValidationError;
+
+// bcrypt only looks at the first 72 bytes, so anything longer would give a
+// false sense of strength.
+const BCRYPT_MAX_BYTES: usize = 72;
+
+static COMMON_PASSWORDS: Lazy<HashSet<String>> = Lazy::new(|| {
+    include_str!("common_passwords.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect()
+});
+
+/// Policy loaded from the environment at startup.
+pub static POLICY: Lazy<PasswordPolicy> =
+    Lazy::new(|| PasswordPolicy::from_env().expect("Invalid password policy configuration"));
+
+#[derive(Debug)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    pub reject_user_info: bool,
+    pub reject_common: bool,
+    pub breached: Option<BreachedPasswords>,
+}
+
+/// Offline copy of the Have I Been Pwned password list.
+#[derive(Debug)]
+pub struct BreachedPasswords {
+    source: BreachedSource,
+    min_count: u64,
+}
+
+#[derive(Debug)]
+enum BreachedSource {
+    /// Directory of k-anonymity range files, one per 5-character SHA-1 prefix
+    /// (`ABCDE` or `ABCDE.txt`), each line `SUFFIX:COUNT`. Files are read on
+    /// demand so the full list never has to fit in memory.
+    RangeDirectory(PathBuf),
+    /// Single file of full `SHA1:COUNT` lines, loaded into memory.
+    HashSet(HashSet<String>),
+}
+
+fn env_bool(name: &str, default: bool) -> anyhow::Result<bool> {
+    match std::env::var(name) {
+        Ok(value) => match value.to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(true),
+            "0" | "false" | "no" | "off" => Ok(false),
+            _ => Err(anyhow::anyhow!("{} must be true or false", name)),
+        },
+        Err(_) => Ok(default),
+    }
+}
+
+fn env_usize(name: &str, default: usize) -> anyhow::Result<usize> {
+    match std::env::var(name) {
+        Ok(value) => Ok(value
+            .parse()
+            .map_err(|_| anyhow::anyhow!("{} must be a positive integer", name))?),
+        Err(_) => Ok(default),
+    }
+}
+
+fn password_error(code: &'static str, message: String) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(Cow::from(message));
+    error
+}
+
+impl PasswordPolicy {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let min_length = env_usize("PASSWORD_MIN_LENGTH", 8)?;
+        let max_length = env_usize("PASSWORD_MAX_LENGTH", BCRYPT_MAX_BYTES)?.min(BCRYPT_MAX_BYTES);
+        if min_length > max_length {
+            anyhow::bail!("PASSWORD_MIN_LENGTH cannot exceed PASSWORD_MAX_LENGTH");
+        }
+
+        let breached = match std::env::var("PASSWORD_BREACHED_HASHES_PATH") {
+            Ok(path) if !path.is_empty() => {
+                let min_count = env_usize("PASSWORD_BREACHED_MIN_COUNT", 1)? as u64;
+                Some(BreachedPasswords::load(Path::new(&path), min_count)?)
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            min_length,
+            max_length,
+            require_lowercase: env_bool("PASSWORD_REQUIRE_LOWERCASE", false)?,
+            require_uppercase: env_bool("PASSWORD_REQUIRE_UPPERCASE", false)?,
+            require_digit: env_bool("PASSWORD_REQUIRE_DIGIT", false)?,
+            require_symbol: env_bool("PASSWORD_REQUIRE_SYMBOL", false)?,
+            reject_user_info: env_bool("PASSWORD_REJECT_USER_INFO", true)?,
+            reject_common: env_bool("PASSWORD_REJECT_COMMON", true)?,
+            breached,
+        })
+    }
+
+    /// Checks `password` against the policy. `user_inputs` are values the
+    /// password must not contain, such as the username and email.
+    pub fn check(&self, password: &str, user_inputs: &[&str]) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+
+        let length = password.chars().count();
+        if length < self.min_length {
+            let mut error = password_error(
+                "password_too_short",
+                format!("Password must be at least {} characters", self.min_length),
+            );
+            error.add_param(Cow::from("min"), &self.min_length);
+            errors.push(error);
+        }
+        if password.len() > self.max_length {
+            let mut error = password_error(
+                "password_too_long",
+                format!("Password must be at most {} bytes", self.max_length),
+            );
+            error.add_param(Cow::from("max"), &self.max_length);
+            errors.push(error);
+        }
+
+        let classes = [
+            (
+                self.require_lowercase,
+                password.chars().any(char::is_lowercase),
+                "password_missing_lowercase",
+                "a lowercase letter",
+            ),
+            (
+                self.require_uppercase,
+                password.chars().any(char::is_uppercase),
+                "password_missing_uppercase",
+                "an uppercase letter",
+            ),
+            (
+                self.require_digit,
+                password.chars().any(|c| c.is_ascii_digit()),
+                "password_missing_digit",
+                "a digit",
+            ),
+            (
+                self.require_symbol,
+                password.chars().any(|c| !c.is_alphanumeric()),
+                "password_missing_symbol",
+                "a symbol",
+            ),
+        ];
+        for (required, present, code, description) in classes {
+            if required && !present {
+                errors.push(password_error(
+                    code,
+                    format!("Password must contain {}", description),
+                ));
+            }
+        }
+
+        let lowered = password.to_lowercase();
+
+        if self.reject_user_info {
+            let contains_user_info = user_inputs
+                .iter()
+                .flat_map(|input| {
+                    // Check the local part of an email on its own as well
+                    let local_part = input.split('@').next().unwrap_or(input);
+                    [input.to_lowercase(), local_part.to_lowercase()]
+                })
+                .filter(|input| input.chars().count() >= 3)
+                .any(|input| lowered.contains(&input));
+            if contains_user_info {
+                errors.push(password_error(
+                    "password_contains_user_info",
+                    "Password must not contain your username or email".to_string(),
+                ));
+            }
+        }
+
+        if self.reject_common && COMMON_PASSWORDS.contains(&lowered) {
+            errors.push(password_error(
+                "password_too_common",
+                "Password is too common".to_string(),
+            ));
+        }
+
+        if let Some(breached) = &self.breached {
+            if breached.contains(password) {
+                errors.push(password_error(
+                    "password_breached",
+                    "Password has appeared in a known data breach".to_string(),
+                ));
+            }
+        }
+
+        errors
+    }
+}
+
+impl BreachedPasswords {
+    pub fn load(path: &Path, min_count: u64) -> anyhow::Result<Self> {
+        let source = if path.is_dir() {
+            info!(
+                "Using breached password range files from {}",
+                path.display()
+            );
+            BreachedSource::RangeDirectory(path.to_path_buf())
+        } else {
+            let contents = fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+            let hashes: HashSet<String> = contents
+                .lines()
+                .filter_map(parse_hash_line)
+                .filter(|(hash, count)| hash.len() == 40 && *count >= min_count)
+                .map(|(hash, _)| hash)
+                .collect();
+            info!(
+                "Loaded {} breached password hashes from {}",
+                hashes.len(),
+                path.display()
+            );
+            BreachedSource::HashSet(hashes)
+        };
+        Ok(Self { source, min_count })
+    }
+
+    pub fn contains(&self, password: &str) -> bool {
+        let digest = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        match &self.source {
+            BreachedSource::HashSet(hashes) => hashes.contains(&digest),
+            BreachedSource::RangeDirectory(dir) => {
+                let (prefix, suffix) = digest.split_at(5);
+                let contents = [dir.join(prefix), dir.join(format!("{}.txt", prefix))]
+                    .iter()
+                    .find_map(|file| fs::read_to_string(file).ok());
+                let Some(contents) = contents else {
+                    return false;
+                };
+                contents
+                    .lines()
+                    .filter_map(parse_hash_line)
+                    .any(|(hash, count)| hash == suffix && count >= self.min_count)
+            }
+        }
+    }
+}
+
+// Parses `HASH:COUNT` (count optional), normalising the hash to uppercase.
+fn parse_hash_line(line: &str) -> Option<(String, u64)> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    let (hash, count) = match line.split_once(':') {
+        Some((hash, count)) => (hash, count.trim().parse().ok()?),
+        None => (line, 1),
+    };
+    Some((hash.trim().to_uppercase(), count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 8,
+            max_length: BCRYPT_MAX_BYTES,
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            reject_user_info: true,
+            reject_common: true,
+            breached: None,
+        }
+    }
+
+    fn codes(errors: Vec<ValidationError>) -> Vec<String> {
+        errors
+            .into_iter()
+            .map(|error| error.code.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn accepts_a_password_meeting_the_policy() {
+        assert!(policy()
+            .check("correct horse battery", &["alice"])
+            .is_empty());
+    }
+
+    #[test]
+    fn enforces_length_in_characters_and_bytes() {
+        assert_eq!(codes(policy().check("short", &[])), ["password_too_short"]);
+        // Eight characters, but more bytes than bcrypt reads
+        let long = "é".repeat(40);
+        assert_eq!(codes(policy().check(&long, &[])), ["password_too_long"]);
+    }
+
+    #[test]
+    fn requires_configured_character_classes() {
+        let policy = PasswordPolicy {
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+            ..policy()
+        };
+        assert_eq!(
+            codes(policy.check("UPPERCASEONLY", &[])),
+            [
+                "password_missing_lowercase",
+                "password_missing_digit",
+                "password_missing_symbol"
+            ]
+        );
+        assert!(policy.check("Mixed-case 42", &[]).is_empty());
+    }
+
+    #[test]
+    fn rejects_username_and_email_local_part() {
+        let inputs = ["alice", "wonder@example.com"];
+        assert_eq!(
+            codes(policy().check("xxALICExx99", &inputs)),
+            ["password_contains_user_info"]
+        );
+        assert_eq!(
+            codes(policy().check("my-wonder-pass", &inputs)),
+            ["password_contains_user_info"]
+        );
+        // Inputs shorter than three characters are ignored
+        assert!(policy().check("ab-unrelated-phrase", &["ab"]).is_empty());
+    }
+
+    #[test]
+    fn rejects_common_passwords_case_insensitively() {
+        assert_eq!(
+            codes(policy().check("PASSWORD", &[])),
+            ["password_too_common"]
+        );
+    }
+
+    #[test]
+    fn rejects_breached_passwords_from_a_hash_set() {
+        let digest = hex::encode_upper(Sha1::digest(b"tr0ub4dor&3 horse"));
+        let policy = PasswordPolicy {
+            breached: Some(BreachedPasswords {
+                source: BreachedSource::HashSet(HashSet::from([digest])),
+                min_count: 1,
+            }),
+            ..policy()
+        };
+        assert_eq!(
+            codes(policy.check("tr0ub4dor&3 horse", &[])),
+            ["password_breached"]
+        );
+        assert!(policy.check("tr0ub4dor&3 zebra", &[]).is_empty());
+    }
+
+    #[test]
+    fn parses_hash_lines() {
+        assert_eq!(
+            parse_hash_line(" abc12:42 "),
+            Some(("ABC12".to_string(), 42))
+        );
+        assert_eq!(parse_hash_line("ABC12"), Some(("ABC12".to_string(), 1)));
+        assert_eq!(parse_hash_line("ABC12:many"), None);
+        assert_eq!(parse_hash_line("   "), None);
+    }
+}
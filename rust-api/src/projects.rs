@@ -0,0 +1,39 @@
+//! Projects group a user's tasks. Each user has an inbox project that holds
+//! tasks created without an explicit project.
+
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    models::{NewProject, Project, INBOX_PROJECT_NAME},
+    schema::projects,
+};
+
+/// The user's inbox, created if missing (e.g. for accounts that predate projects).
+pub fn inbox(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<Project> {
+    let existing = projects::table
+        .filter(projects::user_id.eq(user_id))
+        .filter(projects::is_inbox.eq(true))
+        .first(conn)
+        .optional()?;
+    if let Some(project) = existing {
+        return Ok(project);
+    }
+
+    // A concurrent request may create it first; the partial unique index
+    // turns our insert into a no-op
+    diesel::insert_into(projects::table)
+        .values(&NewProject {
+            user_id,
+            name: INBOX_PROJECT_NAME.to_string(),
+            description: None,
+            color: None,
+            is_inbox: true,
+        })
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    projects::table
+        .filter(projects::user_id.eq(user_id))
+        .filter(projects::is_inbox.eq(true))
+        .first(conn)
+}
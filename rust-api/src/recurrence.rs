@@ -0,0 +1,387 @@
+//! Recurring tasks. A series holds an RRULE anchored at a local time in its
+//! time zone, plus the fields each new occurrence starts from. Only one
+//! occurrence exists ahead of time: completing or skipping the latest one
+//! creates the next.
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use log::warn;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    models::{NewTask, NewTaskSeries, RecurrenceRequest, Task, TaskSeries},
+    notifications,
+    rrule::{self, Rule},
+    schema::{task_series, task_series_exceptions, tasks},
+    tags, task_events, task_positions, task_schedule,
+};
+
+/// Upcoming occurrences listed with a recurring task.
+const PREVIEW_COUNT: usize = 5;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RecurrenceError {
+    /// The rule, time zone or task can't form a series; maps to 400.
+    #[error("{0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+/// A parsed rule and the time zone its occurrences are anchored in.
+pub struct Recurrence {
+    pub rule: Rule,
+    pub tz: Tz,
+}
+
+impl Recurrence {
+    /// Parses a requested rule; the time zone defaults to the user's.
+    pub fn parse(
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        request: &RecurrenceRequest,
+    ) -> Result<Self, RecurrenceError> {
+        let rule = request
+            .rrule
+            .parse::<Rule>()
+            .map_err(|e| RecurrenceError::Invalid(format!("Invalid recurrence rule: {}", e)))?;
+        let tz = match &request.timezone {
+            Some(name) => name
+                .parse::<Tz>()
+                .map_err(|_| RecurrenceError::Invalid(format!("Unknown time zone '{}'", name)))?,
+            None => task_schedule::user_timezone(conn, user_id)?,
+        };
+        Ok(Self { rule, tz })
+    }
+
+    /// The stored rule of a series; `None` if it no longer parses.
+    pub fn of(series: &TaskSeries) -> Option<Self> {
+        let parsed = series.rrule.parse::<Rule>().and_then(|rule| {
+            let tz = series
+                .timezone
+                .parse::<Tz>()
+                .map_err(|_| format!("unknown time zone '{}'", series.timezone))?;
+            Ok((rule, tz))
+        });
+        match parsed {
+            Ok((rule, tz)) => Some(Self { rule, tz }),
+            Err(e) => {
+                warn!("Series {} has an unusable rule: {}", series.id, e);
+                None
+            }
+        }
+    }
+}
+
+/// Where a series starts: its first occurrence as a local time, and as an
+/// instant.
+pub struct Anchor {
+    pub dtstart: NaiveDateTime,
+    pub all_day: bool,
+    pub occurrence_at: DateTime<Utc>,
+}
+
+/// Anchors a series at a task due at `due_at` or on `due_date`. When the
+/// task is already part of `series_id`, no other occurrence may sit at the
+/// same instant.
+pub fn anchor(
+    conn: &mut PgConnection,
+    series_id: Option<Uuid>,
+    task_id: Option<Uuid>,
+    tz: Tz,
+    due_at: Option<DateTime<Utc>>,
+    due_date: Option<NaiveDate>,
+) -> Result<Anchor, RecurrenceError> {
+    let (dtstart, all_day) = match (due_at, due_date) {
+        (Some(due_at), _) => (due_at.with_timezone(&tz).naive_local(), false),
+        (None, Some(due_date)) => (due_date.and_time(NaiveTime::MIN), true),
+        (None, None) => {
+            return Err(RecurrenceError::Invalid(
+                "Recurring tasks need a due_at or due_date".to_string(),
+            ));
+        }
+    };
+    let anchor = Anchor {
+        dtstart,
+        all_day,
+        occurrence_at: rrule::resolve_local(tz, dtstart),
+    };
+
+    if let Some(series_id) = series_id {
+        let taken: i64 = tasks::table
+            .filter(tasks::series_id.eq(series_id))
+            .filter(tasks::occurrence_at.eq(anchor.occurrence_at))
+            .filter(tasks::id.ne(task_id.unwrap_or_default()))
+            .count()
+            .get_result(conn)?;
+        if taken > 0 {
+            return Err(RecurrenceError::Invalid(
+                "Another occurrence of this task is due at that time".to_string(),
+            ));
+        }
+    }
+    Ok(anchor)
+}
+
+/// Makes `task` the first occurrence of a series following `recurrence`,
+/// re-anchoring the task's existing series if it has one.
+pub fn start(
+    conn: &mut PgConnection,
+    task: &Task,
+    recurrence: &Recurrence,
+    anchor: &Anchor,
+) -> QueryResult<Task> {
+    let values = NewTaskSeries {
+        user_id: task.user_id,
+        rrule: recurrence.rule.as_str().to_string(),
+        timezone: recurrence.tz.name().to_string(),
+        dtstart: anchor.dtstart,
+        all_day: anchor.all_day,
+        title: task.title.clone(),
+        description: task.description.clone(),
+        priority: task.priority,
+        project_id: task.project_id,
+        assignee_id: task.assignee_id,
+    };
+    let series_id = match task.series_id {
+        Some(series_id) => {
+            diesel::update(task_series::table.find(series_id))
+                .set(&values)
+                .execute(conn)?;
+            series_id
+        }
+        None => diesel::insert_into(task_series::table)
+            .values(&values)
+            .returning(task_series::id)
+            .get_result(conn)?,
+    };
+
+    diesel::update(tasks::table.find(task.id))
+        .set((
+            tasks::series_id.eq(series_id),
+            tasks::occurrence_at.eq(anchor.occurrence_at),
+        ))
+        .get_result(conn)
+}
+
+/// Copies the fields new occurrences start from out of `task`.
+pub fn sync_template(conn: &mut PgConnection, task: &Task) -> QueryResult<()> {
+    let Some(series_id) = task.series_id else {
+        return Ok(());
+    };
+    diesel::update(task_series::table.find(series_id))
+        .set((
+            task_series::title.eq(&task.title),
+            task_series::description.eq(&task.description),
+            task_series::priority.eq(task.priority),
+            task_series::project_id.eq(task.project_id),
+            task_series::assignee_id.eq(task.assignee_id),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Ends a series. Its occurrences stay as ordinary tasks.
+pub fn end(conn: &mut PgConnection, series_id: Uuid) -> QueryResult<()> {
+    diesel::delete(task_series::table.find(series_id)).execute(conn)?;
+    Ok(())
+}
+
+/// The rule the task's series follows, if it recurs.
+pub fn current(conn: &mut PgConnection, task: &Task) -> QueryResult<Option<Recurrence>> {
+    Ok(find_series(conn, task)?.as_ref().and_then(Recurrence::of))
+}
+
+fn find_series(conn: &mut PgConnection, task: &Task) -> QueryResult<Option<TaskSeries>> {
+    match task.series_id {
+        Some(series_id) => task_series::table.find(series_id).first(conn).optional(),
+        None => Ok(None),
+    }
+}
+
+/// Up to `limit` occurrences of the series after `after`, leaving out
+/// skipped ones.
+fn upcoming(
+    conn: &mut PgConnection,
+    series: &TaskSeries,
+    after: DateTime<Utc>,
+    limit: usize,
+) -> QueryResult<Vec<DateTime<Utc>>> {
+    let Some(recurrence) = Recurrence::of(series) else {
+        return Ok(Vec::new());
+    };
+    let skipped: HashSet<DateTime<Utc>> = task_series_exceptions::table
+        .filter(task_series_exceptions::series_id.eq(series.id))
+        .filter(task_series_exceptions::occurrence_at.gt(after))
+        .select(task_series_exceptions::occurrence_at)
+        .load::<DateTime<Utc>>(conn)?
+        .into_iter()
+        .collect();
+    Ok(recurrence
+        .rule
+        .occurrences(series.dtstart, recurrence.tz)
+        .skip_while(|at| *at <= after)
+        .filter(|at| !skipped.contains(at))
+        .take(limit)
+        .collect())
+}
+
+/// Creates the occurrence following `task`. Nothing is created when the
+/// series has ended, the rule is exhausted or `task` isn't the latest
+/// occurrence.
+pub fn advance(conn: &mut PgConnection, task: &Task, actor_id: Uuid) -> QueryResult<Option<Task>> {
+    let (Some(series), Some(occurrence_at)) = (find_series(conn, task)?, task.occurrence_at) else {
+        return Ok(None);
+    };
+    let later: i64 = tasks::table
+        .filter(tasks::series_id.eq(series.id))
+        .filter(tasks::occurrence_at.gt(occurrence_at))
+        .count()
+        .get_result(conn)?;
+    if later > 0 {
+        return Ok(None);
+    }
+    let Some(next_at) = upcoming(conn, &series, occurrence_at, 1)?
+        .into_iter()
+        .next()
+    else {
+        return Ok(None);
+    };
+
+    let (due_at, due_date) = if series.all_day {
+        let tz = series.timezone.parse::<Tz>().unwrap_or(Tz::UTC);
+        (None, Some(next_at.with_timezone(&tz).date_naive()))
+    } else {
+        (Some(next_at), None)
+    };
+    let new_task = NewTask {
+        title: series.title.clone(),
+        description: series.description.clone(),
+        user_id: series.user_id,
+        due_at,
+        due_date,
+        priority: series.priority,
+        project_id: series.project_id,
+        parent_id: None,
+        assignee_id: series.assignee_id,
+        series_id: Some(series.id),
+        occurrence_at: Some(next_at),
+    };
+    let position = task_positions::append(conn, new_task.project_id, None)?;
+    // A concurrent completion may have created it already
+    let created: Option<Task> = diesel::insert_into(tasks::table)
+        .values((&new_task, tasks::position.eq(position)))
+        .on_conflict((tasks::series_id, tasks::occurrence_at))
+        .do_nothing()
+        .get_result(conn)
+        .optional()?;
+
+    if let Some(next) = &created {
+        let task_tags = tags::load_for_tasks(conn, std::slice::from_ref(task))?
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        tags::set_task_tags(conn, next.id, &task_tags)?;
+        task_events::created(conn, next, &tags::names(&task_tags), actor_id)?;
+        notifications::task_assigned(conn, next, None, actor_id)?;
+    }
+    Ok(created)
+}
+
+fn add_exception(
+    conn: &mut PgConnection,
+    series_id: Uuid,
+    occurrence_at: DateTime<Utc>,
+) -> QueryResult<()> {
+    diesel::insert_into(task_series_exceptions::table)
+        .values((
+            task_series_exceptions::series_id.eq(series_id),
+            task_series_exceptions::occurrence_at.eq(occurrence_at),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Skips the task's own occurrence and creates the next one; the caller
+/// deletes `task` afterwards.
+pub fn skip_current(
+    conn: &mut PgConnection,
+    task: &Task,
+    actor_id: Uuid,
+) -> QueryResult<Option<Task>> {
+    if let (Some(series_id), Some(occurrence_at)) = (task.series_id, task.occurrence_at) {
+        add_exception(conn, series_id, occurrence_at)?;
+    }
+    advance(conn, task, actor_id)
+}
+
+/// Skips a later occurrence of the task's series before it is created.
+pub fn skip_later(
+    conn: &mut PgConnection,
+    task: &Task,
+    occurrence_at: DateTime<Utc>,
+) -> Result<(), RecurrenceError> {
+    let not_upcoming =
+        || RecurrenceError::Invalid("Not an upcoming occurrence of this task".to_string());
+    let Some(series) = find_series(conn, task)? else {
+        return Err(RecurrenceError::Invalid(
+            "Task is not recurring".to_string(),
+        ));
+    };
+    let current = task.occurrence_at.ok_or_else(not_upcoming)?;
+    if occurrence_at <= current {
+        return Err(not_upcoming());
+    }
+    let scheduled = Recurrence::of(&series).is_some_and(|recurrence| {
+        recurrence
+            .rule
+            .occurrences(series.dtstart, recurrence.tz)
+            .find(|at| *at >= occurrence_at)
+            == Some(occurrence_at)
+    });
+    if !scheduled {
+        return Err(not_upcoming());
+    }
+
+    let created: i64 = tasks::table
+        .filter(tasks::series_id.eq(series.id))
+        .filter(tasks::occurrence_at.eq(occurrence_at))
+        .count()
+        .get_result(conn)?;
+    if created > 0 {
+        return Err(RecurrenceError::Invalid(
+            "That occurrence already exists; skip its own task instead".to_string(),
+        ));
+    }
+    add_exception(conn, series.id, occurrence_at)?;
+    Ok(())
+}
+
+/// The series of a recurring task, as shown in its details.
+#[derive(Debug, Serialize)]
+pub struct RecurrenceSummary {
+    pub rrule: String,
+    pub timezone: String,
+    pub all_day: bool,
+    /// The next few occurrences after this task's
+    pub upcoming: Vec<DateTime<Utc>>,
+}
+
+pub fn describe(conn: &mut PgConnection, task: &Task) -> QueryResult<Option<RecurrenceSummary>> {
+    let Some(series) = find_series(conn, task)? else {
+        return Ok(None);
+    };
+    let after = task.occurrence_at.unwrap_or_else(Utc::now);
+    let upcoming = upcoming(conn, &series, after, PREVIEW_COUNT)?;
+    Ok(Some(RecurrenceSummary {
+        rrule: series.rrule,
+        timezone: series.timezone,
+        all_day: series.all_day,
+        upcoming,
+    }))
+}
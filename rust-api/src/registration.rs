@@ -0,0 +1,71 @@
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Policy loaded from the environment at startup.
+pub static POLICY: Lazy<RegistrationPolicy> = Lazy::new(|| {
+    RegistrationPolicy::from_env().expect("Invalid registration policy configuration")
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationMode {
+    Open,
+    Closed,
+    InviteOnly,
+    DomainAllowlist,
+}
+
+#[derive(Debug)]
+pub struct RegistrationPolicy {
+    pub mode: RegistrationMode,
+    pub allowed_domains: Vec<String>,
+}
+
+impl RegistrationPolicy {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mode = match std::env::var("REGISTRATION_MODE")
+            .unwrap_or_else(|_| "open".to_string())
+            .to_lowercase()
+            .as_str()
+        {
+            "open" => RegistrationMode::Open,
+            "closed" => RegistrationMode::Closed,
+            "invite-only" | "invite_only" => RegistrationMode::InviteOnly,
+            "domain-allowlist" | "domain_allowlist" => RegistrationMode::DomainAllowlist,
+            other => anyhow::bail!("Unknown REGISTRATION_MODE '{}'", other),
+        };
+
+        let allowed_domains: Vec<String> = std::env::var("REGISTRATION_ALLOWED_DOMAINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|d| d.trim().trim_start_matches('@').to_lowercase())
+            .filter(|d| !d.is_empty())
+            .collect();
+
+        if mode == RegistrationMode::DomainAllowlist && allowed_domains.is_empty() {
+            anyhow::bail!("REGISTRATION_ALLOWED_DOMAINS must be set in domain-allowlist mode");
+        }
+
+        Ok(Self {
+            mode,
+            allowed_domains,
+        })
+    }
+
+    pub fn is_domain_allowed(&self, email: &str) -> bool {
+        let Some((_, domain)) = email.rsplit_once('@') else {
+            return false;
+        };
+        let domain = domain.to_lowercase();
+        self.allowed_domains.contains(&domain)
+    }
+}
+
+/// Generates a new invite code. Only its hash is persisted.
+pub fn generate_invite_code() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+pub fn hash_invite_code(code: &str) -> String {
+    hex::encode(Sha256::digest(code.trim().as_bytes()))
+}
@@ -0,0 +1,674 @@
+//! The subset of RFC 5545 recurrence rules used by recurring tasks: `FREQ`
+//! (daily to yearly), `INTERVAL`, `COUNT`, `UNTIL`, `BYDAY` (with ordinals
+//! for monthly and yearly rules), `BYMONTHDAY`, `BYMONTH` and `WKST`.
+//! Occurrences keep the wall-clock time of `DTSTART` in the rule's time zone.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{
+    DateTime, Datelike, Days, Duration, LocalResult, Months, NaiveDate, NaiveDateTime, TimeZone,
+    Utc, Weekday,
+};
+use chrono_tz::Tz;
+
+/// Largest accepted `INTERVAL`, which keeps period arithmetic well within
+/// range; yearly rules would skip past `MAX_YEAR` long before it.
+const MAX_INTERVAL: u32 = 1000;
+
+/// Occurrences end with this year.
+const MAX_YEAR: i32 = 9999;
+
+/// Months in a cycle of the Gregorian calendar: dates fall on the same
+/// weekdays again every 400 years.
+const CALENDAR_CYCLE_MONTHS: u32 = 400 * 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Until {
+    /// Last local date an occurrence may fall on
+    Date(NaiveDate),
+    Instant(DateTime<Utc>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    text: String,
+    frequency: Frequency,
+    interval: u32,
+    count: Option<u32>,
+    until: Option<Until>,
+    /// Weekdays, with an optional position within the month or year (`-1FR`)
+    by_day: Vec<(Option<i32>, Weekday)>,
+    by_month_day: Vec<i32>,
+    by_month: Vec<u32>,
+    week_start: Weekday,
+}
+
+fn parse_weekday(value: &str) -> Option<Weekday> {
+    match value {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+fn parse_list<T>(
+    name: &str,
+    value: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<Vec<T>, String> {
+    value
+        .split(',')
+        .map(|item| parse(item).ok_or_else(|| format!("Invalid {} value '{}'", name, item)))
+        .collect()
+}
+
+fn parse_until(value: &str) -> Option<Until> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d") {
+        return Some(Until::Date(date));
+    }
+    // Floating times are read as UTC
+    let value = value.strip_suffix('Z').unwrap_or(value);
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .ok()
+        .map(|until| Until::Instant(until.and_utc()))
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let body = text.strip_prefix("RRULE:").unwrap_or(text);
+
+        let mut frequency = None;
+        let mut rule = Rule {
+            text: body.to_string(),
+            frequency: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_month: Vec::new(),
+            week_start: Weekday::Mon,
+        };
+
+        for part in body.split(';').filter(|part| !part.is_empty()) {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid rule part '{}'", part))?;
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        other => return Err(format!("Unsupported FREQ '{}'", other)),
+                    })
+                }
+                "INTERVAL" => {
+                    rule.interval = value
+                        .parse()
+                        .ok()
+                        .filter(|interval| (1..=MAX_INTERVAL).contains(interval))
+                        .ok_or_else(|| {
+                            format!("INTERVAL must be an integer from 1 to {}", MAX_INTERVAL)
+                        })?
+                }
+                "COUNT" => {
+                    rule.count = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|count| *count >= 1)
+                            .ok_or("COUNT must be a positive integer")?,
+                    )
+                }
+                "UNTIL" => {
+                    rule.until = Some(parse_until(value).ok_or("Invalid UNTIL value")?);
+                }
+                "BYDAY" => {
+                    rule.by_day = parse_list("BYDAY", &value.to_ascii_uppercase(), |item| {
+                        let split = item.len().checked_sub(2)?;
+                        let weekday = parse_weekday(item.get(split..)?)?;
+                        let ordinal = match item.get(..split)? {
+                            "" => None,
+                            n => Some(n.parse::<i32>().ok().filter(|n| *n != 0 && n.abs() <= 53)?),
+                        };
+                        Some((ordinal, weekday))
+                    })?
+                }
+                "BYMONTHDAY" => {
+                    rule.by_month_day = parse_list("BYMONTHDAY", value, |item| {
+                        item.parse::<i32>()
+                            .ok()
+                            .filter(|day| *day != 0 && day.abs() <= 31)
+                    })?
+                }
+                "BYMONTH" => {
+                    rule.by_month = parse_list("BYMONTH", value, |item| {
+                        item.parse::<u32>()
+                            .ok()
+                            .filter(|month| (1..=12).contains(month))
+                    })?
+                }
+                "WKST" => {
+                    rule.week_start = parse_weekday(&value.to_ascii_uppercase())
+                        .ok_or_else(|| format!("Invalid WKST value '{}'", value))?
+                }
+                other => return Err(format!("Unsupported rule part '{}'", other)),
+            }
+        }
+
+        rule.frequency = frequency.ok_or("FREQ is required")?;
+        if rule.count.is_some() && rule.until.is_some() {
+            return Err("COUNT and UNTIL cannot both be set".to_string());
+        }
+        let positioned = rule.by_day.iter().any(|(ordinal, _)| ordinal.is_some());
+        if positioned && !matches!(rule.frequency, Frequency::Monthly | Frequency::Yearly) {
+            return Err("BYDAY positions are only allowed in MONTHLY and YEARLY rules".to_string());
+        }
+        if rule.frequency == Frequency::Weekly && !rule.by_month_day.is_empty() {
+            return Err("BYMONTHDAY is not allowed in WEEKLY rules".to_string());
+        }
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> Option<u32> {
+    let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let next = first.checked_add_months(Months::new(1))?;
+    Some((next - first).num_days() as u32)
+}
+
+/// The dates in `year`/`month` matching a signed day of the month.
+fn month_day(year: i32, month: u32, day: i32) -> Option<NaiveDate> {
+    let last = days_in_month(year, month)? as i32;
+    let day = if day < 0 { last + day + 1 } else { day };
+    (1..=last)
+        .contains(&day)
+        .then(|| NaiveDate::from_ymd_opt(year, month, day as u32))
+        .flatten()
+}
+
+/// Dates from `first` to `last` falling on `weekday`; with a position only
+/// the nth one (negative counts from the end).
+fn weekdays_between(
+    first: NaiveDate,
+    last: NaiveDate,
+    ordinal: Option<i32>,
+    weekday: Weekday,
+) -> Vec<NaiveDate> {
+    let offset = (7 + weekday.num_days_from_monday() - first.weekday().num_days_from_monday()) % 7;
+    let all: Vec<NaiveDate> = first
+        .checked_add_days(Days::new(offset as u64))
+        .into_iter()
+        .flat_map(|start| start.iter_weeks())
+        .take_while(|date| *date <= last)
+        .collect();
+    match ordinal {
+        None => all,
+        Some(n) if n > 0 => all.get(n as usize - 1).copied().into_iter().collect(),
+        Some(n) => all
+            .len()
+            .checked_sub(n.unsigned_abs() as usize)
+            .and_then(|index| all.get(index))
+            .copied()
+            .into_iter()
+            .collect(),
+    }
+}
+
+impl Rule {
+    /// The rule as given, without any `RRULE:` prefix.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Occurrences starting at `dtstart` (a local time in `tz`), which is
+    /// always the first one.
+    pub fn occurrences(&self, dtstart: NaiveDateTime, tz: Tz) -> Occurrences<'_> {
+        Occurrences {
+            rule: self,
+            dtstart,
+            tz,
+            period: 0,
+            pending: VecDeque::from([dtstart.date()]),
+            emitted: 0,
+            last_found: dtstart.date(),
+            done: false,
+        }
+    }
+
+    fn month_dates(&self, year: i32, month: u32) -> Vec<NaiveDate> {
+        let Some((first, days)) =
+            NaiveDate::from_ymd_opt(year, month, 1).zip(days_in_month(year, month))
+        else {
+            return Vec::new();
+        };
+        let last = first + Days::new(days as u64 - 1);
+        let by_month_day: Vec<NaiveDate> = self
+            .by_month_day
+            .iter()
+            .filter_map(|day| month_day(year, month, *day))
+            .collect();
+        let by_day: Vec<NaiveDate> = self
+            .by_day
+            .iter()
+            .flat_map(|(ordinal, weekday)| weekdays_between(first, last, *ordinal, *weekday))
+            .collect();
+        match (self.by_month_day.is_empty(), self.by_day.is_empty()) {
+            (false, false) => by_month_day
+                .into_iter()
+                .filter(|date| by_day.contains(date))
+                .collect(),
+            (false, true) => by_month_day,
+            (true, false) => by_day,
+            (true, true) => Vec::new(),
+        }
+    }
+
+    /// First date of the `period`th period after the one holding `start`;
+    /// `None` once periods start after `MAX_YEAR`.
+    fn period_start(&self, start: NaiveDate, period: u32) -> Option<NaiveDate> {
+        let step = period.checked_mul(self.interval)?;
+        let first = match self.frequency {
+            Frequency::Daily => start.checked_add_days(Days::new(step as u64)),
+            Frequency::Weekly => {
+                let back = (7 + start.weekday().num_days_from_monday()
+                    - self.week_start.num_days_from_monday())
+                    % 7;
+                start
+                    .checked_sub_days(Days::new(back as u64))?
+                    .checked_add_days(Days::new(7 * step as u64))
+            }
+            Frequency::Monthly => start.with_day(1)?.checked_add_months(Months::new(step)),
+            Frequency::Yearly => {
+                let year = start.year().checked_add(i32::try_from(step).ok()?)?;
+                NaiveDate::from_ymd_opt(year, 1, 1)
+            }
+        };
+        first.filter(|first| first.year() <= MAX_YEAR)
+    }
+
+    /// Candidate dates in the period starting on `first`, for a series
+    /// starting on `start`.
+    fn period_dates(&self, start: NaiveDate, first: NaiveDate) -> Vec<NaiveDate> {
+        let mut dates = match self.frequency {
+            Frequency::Daily => Some(first)
+                .filter(|date| {
+                    (self.by_month_day.is_empty()
+                        || self
+                            .by_month_day
+                            .iter()
+                            .any(|day| month_day(date.year(), date.month(), *day) == Some(*date)))
+                        && (self.by_day.is_empty()
+                            || self
+                                .by_day
+                                .iter()
+                                .any(|(_, weekday)| *weekday == date.weekday()))
+                })
+                .into_iter()
+                .collect(),
+            Frequency::Weekly => first
+                .iter_days()
+                .take(7)
+                .filter(|date| {
+                    if self.by_day.is_empty() {
+                        date.weekday() == start.weekday()
+                    } else {
+                        self.by_day
+                            .iter()
+                            .any(|(_, weekday)| *weekday == date.weekday())
+                    }
+                })
+                .collect(),
+            Frequency::Monthly => {
+                if self.by_day.is_empty() && self.by_month_day.is_empty() {
+                    month_day(first.year(), first.month(), start.day() as i32)
+                        .into_iter()
+                        .collect()
+                } else {
+                    self.month_dates(first.year(), first.month())
+                }
+            }
+            Frequency::Yearly => {
+                let year = first.year();
+                if !self.by_month.is_empty() {
+                    self.by_month
+                        .iter()
+                        .flat_map(|month| {
+                            if self.by_day.is_empty() && self.by_month_day.is_empty() {
+                                month_day(year, *month, start.day() as i32)
+                                    .into_iter()
+                                    .collect()
+                            } else {
+                                self.month_dates(year, *month)
+                            }
+                        })
+                        .collect()
+                } else if !self.by_month_day.is_empty() {
+                    (1..=12)
+                        .flat_map(|month| self.month_dates(year, month))
+                        .collect()
+                } else if !self.by_day.is_empty() {
+                    let last = NaiveDate::from_ymd_opt(year, 12, 31);
+                    self.by_day
+                        .iter()
+                        .flat_map(|(ordinal, weekday)| {
+                            last.into_iter()
+                                .flat_map(|last| weekdays_between(first, last, *ordinal, *weekday))
+                        })
+                        .collect()
+                } else {
+                    month_day(year, start.month(), start.day() as i32)
+                        .into_iter()
+                        .collect()
+                }
+            }
+        };
+
+        if self.frequency != Frequency::Yearly && !self.by_month.is_empty() {
+            dates.retain(|date| self.by_month.contains(&date.month()));
+        }
+        dates.retain(|date| *date > start && date.year() <= MAX_YEAR);
+        dates.sort();
+        dates.dedup();
+        dates
+    }
+}
+
+/// The instant a local time in `tz` refers to. Ambiguous times take the
+/// earlier instant; times skipped by a DST change move forward by the gap.
+pub fn resolve_local(tz: Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(instant) | LocalResult::Ambiguous(instant, _) => {
+            instant.with_timezone(&Utc)
+        }
+        LocalResult::None => (1..=8)
+            .map(|step| local + Duration::minutes(15 * step))
+            .find_map(|later| tz.from_local_datetime(&later).earliest())
+            .map(|instant| instant.with_timezone(&Utc))
+            .unwrap_or_else(|| local.and_utc()),
+    }
+}
+
+/// Iterator over the instants a rule produces, in order.
+pub struct Occurrences<'a> {
+    rule: &'a Rule,
+    dtstart: NaiveDateTime,
+    tz: Tz,
+    period: u32,
+    pending: VecDeque<NaiveDate>,
+    emitted: u32,
+    /// Date of the latest occurrence found
+    last_found: NaiveDate,
+    done: bool,
+}
+
+impl Iterator for Occurrences<'_> {
+    type Item = DateTime<Utc>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let Some(date) = self.pending.pop_front() else {
+                // Occurrences repeat every `interval` calendar cycles, so a
+                // rule with none in that span after the latest will have no
+                // more, e.g. `FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=30`
+                let horizon = self
+                    .last_found
+                    .checked_add_months(Months::new(CALENDAR_CYCLE_MONTHS * self.rule.interval));
+                let first = self
+                    .rule
+                    .period_start(self.dtstart.date(), self.period)
+                    .filter(|first| horizon.is_none_or(|horizon| *first <= horizon));
+                let Some(first) = first else {
+                    self.done = true;
+                    break;
+                };
+                let dates = self.rule.period_dates(self.dtstart.date(), first);
+                self.period += 1;
+                if let Some(last) = dates.last() {
+                    self.last_found = *last;
+                }
+                self.pending.extend(dates);
+                continue;
+            };
+
+            let instant = resolve_local(self.tz, date.and_time(self.dtstart.time()));
+            let past_until = match self.rule.until {
+                Some(Until::Date(until)) => date > until,
+                Some(Until::Instant(until)) => instant > until,
+                None => false,
+            };
+            if past_until {
+                self.done = true;
+                break;
+            }
+            self.emitted += 1;
+            if self.rule.count.is_some_and(|count| self.emitted >= count) {
+                self.done = true;
+            }
+            return Some(instant);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn dates(rule: &str, dtstart: &str, take: usize) -> Vec<String> {
+        rule.parse::<Rule>()
+            .unwrap()
+            .occurrences(at(dtstart), chrono_tz::UTC)
+            .take(take)
+            .map(|instant| instant.format("%Y-%m-%d").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn parses_rule_with_prefix() {
+        let rule: Rule = "RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,FR;WKST=SU"
+            .parse()
+            .unwrap();
+        assert_eq!(rule.as_str(), "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,FR;WKST=SU");
+        assert_eq!(rule.frequency, Frequency::Weekly);
+        assert_eq!(rule.interval, 2);
+        assert_eq!(
+            rule.by_day,
+            vec![(None, Weekday::Mon), (None, Weekday::Fri)]
+        );
+        assert_eq!(rule.week_start, Weekday::Sun);
+    }
+
+    #[test]
+    fn parses_positions_and_until() {
+        let rule: Rule = "FREQ=MONTHLY;BYDAY=-1FR,2MO;UNTIL=20240630"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            rule.by_day,
+            vec![(Some(-1), Weekday::Fri), (Some(2), Weekday::Mon)]
+        );
+        assert_eq!(
+            rule.until,
+            Some(Until::Date(NaiveDate::from_ymd_opt(2024, 6, 30).unwrap()))
+        );
+
+        let rule: Rule = "FREQ=DAILY;UNTIL=20240630T120000Z".parse().unwrap();
+        assert_eq!(
+            rule.until,
+            Some(Until::Instant(at("2024-06-30 12:00").and_utc()))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for text in [
+            "",
+            "INTERVAL=2",
+            "FREQ=HOURLY",
+            "FREQ=DAILY;INTERVAL=0",
+            "FREQ=DAILY;INTERVAL=1001",
+            "FREQ=YEARLY;INTERVAL=3000000000",
+            "FREQ=DAILY;INTERVAL=-1",
+            "FREQ=DAILY;COUNT=0",
+            "FREQ=DAILY;COUNT=2;UNTIL=20240101",
+            "FREQ=DAILY;UNTIL=tomorrow",
+            "FREQ=WEEKLY;BYDAY=1MO",
+            "FREQ=WEEKLY;BYMONTHDAY=1",
+            "FREQ=MONTHLY;BYDAY=0MO",
+            "FREQ=MONTHLY;BYMONTHDAY=32",
+            "FREQ=YEARLY;BYMONTH=13",
+            "FREQ=DAILY;BYSETPOS=1",
+            "FREQ=DAILY;COUNT",
+        ] {
+            assert!(
+                text.parse::<Rule>().is_err(),
+                "{:?} should be rejected",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn daily_with_count() {
+        assert_eq!(
+            dates("FREQ=DAILY;INTERVAL=2;COUNT=3", "2024-01-30 09:00", 10),
+            ["2024-01-30", "2024-02-01", "2024-02-03"]
+        );
+    }
+
+    #[test]
+    fn weekly_by_day() {
+        // 2024-01-03 is a Wednesday
+        assert_eq!(
+            dates("FREQ=WEEKLY;BYDAY=MO,WE", "2024-01-03 09:00", 4),
+            ["2024-01-03", "2024-01-08", "2024-01-10", "2024-01-15"]
+        );
+    }
+
+    #[test]
+    fn monthly_skips_months_without_the_day() {
+        assert_eq!(
+            dates("FREQ=MONTHLY", "2024-01-31 09:00", 4),
+            ["2024-01-31", "2024-03-31", "2024-05-31", "2024-07-31"]
+        );
+    }
+
+    #[test]
+    fn monthly_last_friday() {
+        assert_eq!(
+            dates("FREQ=MONTHLY;BYDAY=-1FR", "2024-01-26 09:00", 3),
+            ["2024-01-26", "2024-02-23", "2024-03-29"]
+        );
+    }
+
+    #[test]
+    fn yearly_leap_day() {
+        assert_eq!(
+            dates("FREQ=YEARLY", "2024-02-29 09:00", 3),
+            ["2024-02-29", "2028-02-29", "2032-02-29"]
+        );
+    }
+
+    #[test]
+    fn sparse_daily_rule_keeps_going() {
+        // More than 1000 empty days between leap days
+        assert_eq!(
+            dates("FREQ=DAILY;BYMONTH=2;BYMONTHDAY=29", "2025-03-01 09:00", 4),
+            ["2025-03-01", "2028-02-29", "2032-02-29", "2036-02-29"]
+        );
+        // Centuries that are not leap years are skipped
+        assert_eq!(
+            dates("FREQ=DAILY;BYMONTH=2;BYMONTHDAY=29", "2096-03-01 09:00", 2),
+            ["2096-03-01", "2104-02-29"]
+        );
+    }
+
+    #[test]
+    fn impossible_rule_ends_after_dtstart() {
+        assert_eq!(
+            dates("FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=30", "2024-01-01 09:00", 5),
+            ["2024-01-01"]
+        );
+        assert_eq!(
+            dates("FREQ=DAILY;BYMONTH=4;BYMONTHDAY=31", "2024-01-01 09:00", 5),
+            ["2024-01-01"]
+        );
+    }
+
+    #[test]
+    fn large_intervals_end_at_max_year() {
+        assert_eq!(
+            dates("FREQ=YEARLY;INTERVAL=1000", "2024-06-01 09:00", 20),
+            [
+                "2024-06-01",
+                "3024-06-01",
+                "4024-06-01",
+                "5024-06-01",
+                "6024-06-01",
+                "7024-06-01",
+                "8024-06-01",
+                "9024-06-01"
+            ]
+        );
+        assert_eq!(
+            dates("FREQ=MONTHLY;INTERVAL=1000", "9990-01-15 09:00", 5),
+            ["9990-01-15"]
+        );
+    }
+
+    #[test]
+    fn until_date_is_inclusive() {
+        assert_eq!(
+            dates("FREQ=WEEKLY;UNTIL=20240115", "2024-01-01 09:00", 10),
+            ["2024-01-01", "2024-01-08", "2024-01-15"]
+        );
+    }
+
+    #[test]
+    fn keeps_wall_clock_time_across_dst() {
+        let times: Vec<_> = "FREQ=DAILY;COUNT=2"
+            .parse::<Rule>()
+            .unwrap()
+            .occurrences(at("2024-03-09 09:00"), chrono_tz::America::New_York)
+            .map(|instant| instant.format("%Y-%m-%d %H:%M").to_string())
+            .collect();
+        assert_eq!(times, ["2024-03-09 14:00", "2024-03-10 13:00"]);
+    }
+
+    #[test]
+    fn skipped_local_time_moves_forward() {
+        let instant = resolve_local(chrono_tz::America::New_York, at("2024-03-10 02:30"));
+        assert_eq!(instant, at("2024-03-10 07:00").and_utc());
+    }
+}
@@ -1,5 +1,198 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    access_grants (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        task_id -> Nullable<Uuid>,
+        project_id -> Nullable<Uuid>,
+        role -> Varchar,
+        granted_by -> Nullable<Uuid>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    audit_log (id) {
+        id -> Uuid,
+        actor_id -> Uuid,
+        subject_id -> Nullable<Uuid>,
+        action -> Varchar,
+        method -> Nullable<Varchar>,
+        path -> Nullable<Text>,
+        status_code -> Nullable<Int4>,
+        details -> Nullable<Jsonb>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    blob_deletions (storage_key) {
+        storage_key -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    keycloak_events (id) {
+        id -> Varchar,
+        event_type -> Varchar,
+        keycloak_user_id -> Nullable<Varchar>,
+        user_id -> Nullable<Uuid>,
+        outcome -> Varchar,
+        received_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    notification_events (id) {
+        id -> Uuid,
+        event_type -> Varchar,
+        recipient_id -> Uuid,
+        actor_id -> Nullable<Uuid>,
+        payload -> Jsonb,
+        created_at -> Timestamptz,
+        delivered_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    projects (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        name -> Varchar,
+        description -> Nullable<Text>,
+        color -> Varchar,
+        archived -> Bool,
+        is_inbox -> Bool,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    project_status_transitions (from_status_id, to_status_id) {
+        from_status_id -> Uuid,
+        to_status_id -> Uuid,
+    }
+}
+
+diesel::table! {
+    project_statuses (id) {
+        id -> Uuid,
+        project_id -> Uuid,
+        name -> Varchar,
+        done -> Bool,
+        position -> Int4,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    registration_invites (id) {
+        id -> Uuid,
+        code_hash -> Varchar,
+        email -> Nullable<Varchar>,
+        created_by -> Nullable<Uuid>,
+        used_by -> Nullable<Uuid>,
+        expires_at -> Nullable<Timestamptz>,
+        used_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    tags (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        name -> Varchar,
+        color -> Varchar,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    task_attachments (id) {
+        id -> Uuid,
+        task_id -> Uuid,
+        user_id -> Uuid,
+        filename -> Varchar,
+        content_type -> Varchar,
+        size_bytes -> Int8,
+        checksum_sha256 -> Varchar,
+        storage_key -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    task_comments (id) {
+        id -> Uuid,
+        task_id -> Uuid,
+        user_id -> Uuid,
+        body -> Text,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        edited_at -> Nullable<Timestamptz>,
+        deleted_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    task_dependencies (task_id, depends_on_id) {
+        task_id -> Uuid,
+        depends_on_id -> Uuid,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    task_events (id) {
+        id -> Uuid,
+        task_id -> Uuid,
+        actor_id -> Nullable<Uuid>,
+        event_type -> Varchar,
+        changes -> Jsonb,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    task_series (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        rrule -> Text,
+        timezone -> Varchar,
+        dtstart -> Timestamp,
+        all_day -> Bool,
+        title -> Varchar,
+        description -> Nullable<Text>,
+        priority -> Int2,
+        project_id -> Uuid,
+        assignee_id -> Nullable<Uuid>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    task_series_exceptions (series_id, occurrence_at) {
+        series_id -> Uuid,
+        occurrence_at -> Timestamptz,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    task_tags (task_id, tag_id) {
+        task_id -> Uuid,
+        tag_id -> Uuid,
+    }
+}
+
 diesel::table! {
     tasks (id) {
         id -> Uuid,
@@ -9,6 +202,31 @@ diesel::table! {
         user_id -> Uuid,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
+        due_at -> Nullable<Timestamptz>,
+        due_date -> Nullable<Date>,
+        priority -> Int2,
+        project_id -> Uuid,
+        parent_id -> Nullable<Uuid>,
+        assignee_id -> Nullable<Uuid>,
+        series_id -> Nullable<Uuid>,
+        occurrence_at -> Nullable<Timestamptz>,
+        version -> Int4,
+        deleted_at -> Nullable<Timestamptz>,
+        position -> Varchar,
+        status_id -> Uuid,
+    }
+}
+
+diesel::table! {
+    time_entries (id) {
+        id -> Uuid,
+        task_id -> Uuid,
+        user_id -> Uuid,
+        started_at -> Timestamptz,
+        stopped_at -> Nullable<Timestamptz>,
+        note -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
     }
 }
 
@@ -20,13 +238,61 @@ diesel::table! {
         password_hash -> Varchar,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
+        role -> Varchar,
+        active -> Bool,
+        external_id -> Nullable<Varchar>,
+        tokens_valid_after -> Nullable<Timestamptz>,
+        timezone -> Varchar,
+        version -> Int4,
+        deleted_at -> Nullable<Timestamptz>,
     }
 }
 
+diesel::joinable!(access_grants -> projects (project_id));
+diesel::joinable!(access_grants -> tasks (task_id));
+diesel::joinable!(access_grants -> users (user_id));
+diesel::joinable!(keycloak_events -> users (user_id));
+diesel::joinable!(notification_events -> users (recipient_id));
+diesel::joinable!(project_statuses -> projects (project_id));
+diesel::joinable!(projects -> users (user_id));
+diesel::joinable!(tags -> users (user_id));
+diesel::joinable!(task_attachments -> tasks (task_id));
+diesel::joinable!(task_attachments -> users (user_id));
+diesel::joinable!(task_comments -> tasks (task_id));
+diesel::joinable!(task_comments -> users (user_id));
+diesel::joinable!(task_dependencies -> tasks (depends_on_id));
+diesel::joinable!(task_events -> users (actor_id));
+diesel::joinable!(task_series -> projects (project_id));
+diesel::joinable!(task_series_exceptions -> task_series (series_id));
+diesel::joinable!(task_tags -> tags (tag_id));
+diesel::joinable!(task_tags -> tasks (task_id));
+diesel::joinable!(tasks -> project_statuses (status_id));
+diesel::joinable!(tasks -> projects (project_id));
+diesel::joinable!(tasks -> task_series (series_id));
 diesel::joinable!(tasks -> users (user_id));
+diesel::joinable!(time_entries -> tasks (task_id));
+diesel::joinable!(time_entries -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    access_grants,
+    audit_log,
+    blob_deletions,
+    keycloak_events,
+    notification_events,
+    project_status_transitions,
+    project_statuses,
+    projects,
+    registration_invites,
+    tags,
+    task_attachments,
+    task_comments,
+    task_dependencies,
+    task_events,
+    task_series,
+    task_series_exceptions,
+    task_tags,
     tasks,
+    time_entries,
     users,
 );
 
@@ -0,0 +1,689 @@
+//! SCIM 2.0 (RFC 7643/7644) representations of `models::User`.
+
+use actix_web::{http::StatusCode, HttpResponse};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{models::User, schema::users};
+
+pub const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub const LIST_RESPONSE_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+pub const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+pub const CONTENT_TYPE: &str = "application/scim+json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScimEmail {
+    pub value: String,
+    #[serde(default)]
+    pub primary: bool,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimMeta {
+    pub resource_type: &'static str,
+    pub created: chrono::DateTime<chrono::Utc>,
+    pub last_modified: chrono::DateTime<chrono::Utc>,
+    pub location: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimUser {
+    pub schemas: Vec<&'static str>,
+    pub id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    pub user_name: String,
+    pub emails: Vec<ScimEmail>,
+    pub active: bool,
+    pub meta: ScimMeta,
+}
+
+impl From<User> for ScimUser {
+    fn from(user: User) -> Self {
+        Self {
+            schemas: vec![USER_SCHEMA],
+            id: user.id,
+            external_id: user.external_id,
+            user_name: user.username,
+            emails: vec![ScimEmail {
+                value: user.email,
+                primary: true,
+                kind: Some("work".to_string()),
+            }],
+            active: user.active,
+            meta: ScimMeta {
+                resource_type: "User",
+                created: user.created_at,
+                last_modified: user.updated_at,
+                location: format!("/scim/v2/Users/{}", user.id),
+            },
+        }
+    }
+}
+
+/// Body of `POST` and `PUT /scim/v2/Users`. Attributes we do not store
+/// (`name`, `displayName`, ...) are accepted and ignored.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimUserRequest {
+    pub external_id: Option<String>,
+    pub user_name: String,
+    #[serde(default)]
+    pub emails: Vec<ScimEmail>,
+    pub active: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScimPatchRequest {
+    #[serde(rename = "Operations")]
+    pub operations: Vec<ScimPatchOperation>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScimPatchOperation {
+    pub op: String,
+    pub path: Option<String>,
+    pub value: Option<Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimListQuery {
+    pub filter: Option<String>,
+    pub start_index: Option<i64>,
+    pub count: Option<i64>,
+}
+
+#[derive(Debug)]
+pub struct ScimError {
+    pub status: StatusCode,
+    pub scim_type: Option<&'static str>,
+    pub detail: String,
+}
+
+impl ScimError {
+    pub fn new(
+        status: StatusCode,
+        scim_type: Option<&'static str>,
+        detail: impl Into<String>,
+    ) -> Self {
+        Self {
+            status,
+            scim_type,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn invalid_value(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, Some("invalidValue"), detail)
+    }
+
+    pub fn into_response(self) -> HttpResponse {
+        let mut body = json!({
+            "schemas": [ERROR_SCHEMA],
+            "status": self.status.as_u16().to_string(),
+            "detail": self.detail
+        });
+        if let Some(scim_type) = self.scim_type {
+            body["scimType"] = json!(scim_type);
+        }
+        HttpResponse::build(self.status)
+            .content_type(CONTENT_TYPE)
+            .json(body)
+    }
+}
+
+/// The user attributes SCIM can change, before they are written back.
+#[derive(Debug, Clone)]
+pub struct ScimUserState {
+    pub user_name: String,
+    pub email: String,
+    pub external_id: Option<String>,
+    pub active: bool,
+}
+
+impl From<&User> for ScimUserState {
+    fn from(user: &User) -> Self {
+        Self {
+            user_name: user.username.clone(),
+            email: user.email.clone(),
+            external_id: user.external_id.clone(),
+            active: user.active,
+        }
+    }
+}
+
+impl ScimUserState {
+    pub fn from_request(request: &ScimUserRequest) -> Result<Self, ScimError> {
+        let state = Self {
+            user_name: request.user_name.clone(),
+            email: primary_email(&request.emails)
+                .ok_or_else(|| ScimError::invalid_value("At least one email is required"))?,
+            external_id: request.external_id.clone(),
+            active: request.active.unwrap_or(true),
+        };
+        state.validate()?;
+        Ok(state)
+    }
+
+    pub fn validate(&self) -> Result<(), ScimError> {
+        let length = self.user_name.chars().count();
+        if !(3..=50).contains(&length) {
+            return Err(ScimError::invalid_value(
+                "userName must be between 3 and 50 characters",
+            ));
+        }
+        if !validator::validate_email(&self.email) {
+            return Err(ScimError::invalid_value(
+                "emails contains an invalid address",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Applies a PatchOp request (RFC 7644 section 3.5.2).
+    pub fn apply_patch(&mut self, patch: &ScimPatchRequest) -> Result<(), ScimError> {
+        for operation in &patch.operations {
+            match operation.op.to_lowercase().as_str() {
+                "add" | "replace" => {
+                    let value = operation
+                        .value
+                        .as_ref()
+                        .ok_or_else(|| ScimError::invalid_value("Operation is missing a value"))?;
+                    match &operation.path {
+                        Some(path) => self.set_attribute(path, value)?,
+                        None => {
+                            let attributes = value.as_object().ok_or_else(|| {
+                                ScimError::invalid_value(
+                                    "Operation without a path needs an object value",
+                                )
+                            })?;
+                            for (name, value) in attributes {
+                                self.set_attribute(name, value)?;
+                            }
+                        }
+                    }
+                }
+                "remove" => {
+                    let path = operation.path.as_deref().ok_or_else(|| {
+                        ScimError::new(
+                            StatusCode::BAD_REQUEST,
+                            Some("noTarget"),
+                            "remove requires a path",
+                        )
+                    })?;
+                    match normalize_path(path).as_str() {
+                        "externalid" => self.external_id = None,
+                        "username" | "emails" | "emails.value" | "active" => {
+                            return Err(ScimError::new(
+                                StatusCode::BAD_REQUEST,
+                                Some("mutability"),
+                                format!("{} cannot be removed", path),
+                            ));
+                        }
+                        _ => {}
+                    }
+                }
+                other => {
+                    return Err(ScimError::invalid_value(format!(
+                        "Unsupported operation '{}'",
+                        other
+                    )));
+                }
+            }
+        }
+        self.validate()
+    }
+
+    fn set_attribute(&mut self, path: &str, value: &Value) -> Result<(), ScimError> {
+        match normalize_path(path).as_str() {
+            "active" => self.active = parse_bool(value)?,
+            "username" => self.user_name = parse_string(value, path)?,
+            "externalid" => {
+                self.external_id = match value {
+                    Value::Null => None,
+                    other => Some(parse_string(other, path)?),
+                }
+            }
+            "emails" => {
+                let emails: Vec<ScimEmail> =
+                    serde_json::from_value(value.clone()).map_err(|_| {
+                        ScimError::invalid_value("emails must be a list of email objects")
+                    })?;
+                self.email = primary_email(&emails)
+                    .ok_or_else(|| ScimError::invalid_value("At least one email is required"))?;
+            }
+            "emails.value" => self.email = parse_string(value, path)?,
+            // Attributes we don't store (name, displayName, ...) are ignored
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+// Lowercases a path, strips the core schema prefix and reduces value filters
+// such as `emails[type eq "work"].value` to `emails.value`.
+fn normalize_path(path: &str) -> String {
+    let path = path.trim();
+    let path = path
+        .strip_prefix(USER_SCHEMA)
+        .map(|p| p.trim_start_matches(':'))
+        .unwrap_or(path);
+    let mut normalized = String::with_capacity(path.len());
+    let mut depth = 0;
+    for c in path.chars() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            _ if depth == 0 => normalized.push(c.to_ascii_lowercase()),
+            _ => {}
+        }
+    }
+    normalized
+}
+
+fn parse_bool(value: &Value) -> Result<bool, ScimError> {
+    match value {
+        Value::Bool(b) => Ok(*b),
+        // Some IdPs send booleans as strings ("True"/"False")
+        Value::String(s) if s.eq_ignore_ascii_case("true") => Ok(true),
+        Value::String(s) if s.eq_ignore_ascii_case("false") => Ok(false),
+        _ => Err(ScimError::invalid_value("active must be a boolean")),
+    }
+}
+
+fn parse_string(value: &Value, path: &str) -> Result<String, ScimError> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| ScimError::invalid_value(format!("{} must be a string", path)))
+}
+
+fn primary_email(emails: &[ScimEmail]) -> Option<String> {
+    emails
+        .iter()
+        .find(|e| e.primary)
+        .or_else(|| emails.first())
+        .map(|e| e.value.clone())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FilterOp {
+    Eq,
+    Ne,
+    Co,
+    Sw,
+    Ew,
+    Pr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FilterAttr {
+    Id,
+    UserName,
+    ExternalId,
+    Email,
+    Active,
+}
+
+#[derive(Debug, Clone)]
+struct FilterClause {
+    attr: FilterAttr,
+    op: FilterOp,
+    value: Option<String>,
+}
+
+fn invalid_filter(detail: impl Into<String>) -> ScimError {
+    ScimError::new(StatusCode::BAD_REQUEST, Some("invalidFilter"), detail)
+}
+
+// Splits a filter into words, keeping quoted strings together (without quotes)
+// and value filters such as `emails[type eq "work"].value` in one word.
+fn tokenize(filter: &str) -> Result<Vec<String>, ScimError> {
+    let mut tokens = Vec::new();
+    let mut chars = filter.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some('\\') => value.extend(chars.next()),
+                    Some('"') => break,
+                    Some(c) => value.push(c),
+                    None => return Err(invalid_filter("Unterminated string in filter")),
+                }
+            }
+            tokens.push(value);
+        } else {
+            let mut word = String::new();
+            let mut depth = 0;
+            let mut quoted = false;
+            while let Some(&c) = chars.peek() {
+                match c {
+                    _ if c.is_whitespace() && depth == 0 => break,
+                    '"' => quoted = !quoted,
+                    '[' if !quoted => depth += 1,
+                    ']' if !quoted && depth > 0 => depth -= 1,
+                    _ => {}
+                }
+                word.push(c);
+                chars.next();
+            }
+            if depth > 0 {
+                return Err(invalid_filter("Unterminated value filter in filter"));
+            }
+            tokens.push(word);
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parses the subset of SCIM filters IdPs use for user lookups: one or more
+/// `attribute op value` expressions joined with `and`.
+fn parse_filter(filter: &str) -> Result<Vec<FilterClause>, ScimError> {
+    let tokens = tokenize(filter)?;
+    let mut clauses = Vec::new();
+    let mut iter = tokens.into_iter();
+
+    loop {
+        let attr = iter
+            .next()
+            .ok_or_else(|| invalid_filter("Expected an attribute"))?;
+        let attr = match normalize_path(&attr).as_str() {
+            "id" => FilterAttr::Id,
+            "username" => FilterAttr::UserName,
+            "externalid" => FilterAttr::ExternalId,
+            "emails" | "emails.value" => FilterAttr::Email,
+            "active" => FilterAttr::Active,
+            _ => {
+                return Err(invalid_filter(format!(
+                    "Unsupported filter attribute '{}'",
+                    attr
+                )))
+            }
+        };
+        let op = match iter
+            .next()
+            .ok_or_else(|| invalid_filter("Expected an operator"))?
+            .to_lowercase()
+            .as_str()
+        {
+            "eq" => FilterOp::Eq,
+            "ne" => FilterOp::Ne,
+            "co" => FilterOp::Co,
+            "sw" => FilterOp::Sw,
+            "ew" => FilterOp::Ew,
+            "pr" => FilterOp::Pr,
+            other => {
+                return Err(invalid_filter(format!(
+                    "Unsupported filter operator '{}'",
+                    other
+                )))
+            }
+        };
+        let value = if op == FilterOp::Pr {
+            None
+        } else {
+            Some(
+                iter.next()
+                    .ok_or_else(|| invalid_filter("Expected a value"))?,
+            )
+        };
+        clauses.push(FilterClause { attr, op, value });
+
+        match iter.next() {
+            None => break,
+            Some(word) if word.eq_ignore_ascii_case("and") => continue,
+            Some(word) => {
+                return Err(invalid_filter(format!(
+                    "Unsupported filter keyword '{}'",
+                    word
+                )))
+            }
+        }
+    }
+
+    Ok(clauses)
+}
+
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn like_pattern(op: FilterOp, value: &str) -> String {
+    let value = escape_like(value);
+    match op {
+        FilterOp::Co => format!("%{}%", value),
+        FilterOp::Sw => format!("{}%", value),
+        FilterOp::Ew => format!("%{}", value),
+        _ => value,
+    }
+}
+
+/// Builds a users query from a SCIM filter. String comparisons are
+/// case-insensitive, as `userName` and `emails` are not `caseExact`. Deleted
+/// users are left out.
+pub fn filtered_users_query(
+    filter: Option<&str>,
+) -> Result<users::BoxedQuery<'static, Pg>, ScimError> {
+    let mut query = users::table.filter(users::deleted_at.is_null()).into_boxed();
+    let Some(filter) = filter.map(str::trim).filter(|f| !f.is_empty()) else {
+        return Ok(query);
+    };
+
+    for clause in parse_filter(filter)? {
+        let value = clause.value.unwrap_or_default();
+        query = match (clause.attr, clause.op) {
+            (FilterAttr::Id, FilterOp::Eq | FilterOp::Ne) => {
+                let id =
+                    Uuid::parse_str(&value).map_err(|_| invalid_filter("id must be a UUID"))?;
+                if clause.op == FilterOp::Eq {
+                    query.filter(users::id.eq(id))
+                } else {
+                    query.filter(users::id.ne(id))
+                }
+            }
+            (FilterAttr::Active, FilterOp::Eq | FilterOp::Ne) => {
+                let active = parse_bool(&Value::String(value))
+                    .map_err(|_| invalid_filter("active must be true or false"))?;
+                query.filter(users::active.eq(active == (clause.op == FilterOp::Eq)))
+            }
+            (
+                FilterAttr::Id | FilterAttr::UserName | FilterAttr::Email | FilterAttr::Active,
+                FilterOp::Pr,
+            ) => query,
+            (FilterAttr::ExternalId, FilterOp::Pr) => {
+                query.filter(users::external_id.is_not_null())
+            }
+            (FilterAttr::Id | FilterAttr::Active, _) => {
+                return Err(invalid_filter(
+                    "Only eq, ne and pr are supported for this attribute",
+                ));
+            }
+            (FilterAttr::UserName, FilterOp::Ne) => {
+                query.filter(users::username.not_ilike(like_pattern(clause.op, &value)))
+            }
+            (FilterAttr::UserName, op) => {
+                query.filter(users::username.ilike(like_pattern(op, &value)))
+            }
+            (FilterAttr::Email, FilterOp::Ne) => {
+                query.filter(users::email.not_ilike(like_pattern(clause.op, &value)))
+            }
+            (FilterAttr::Email, op) => query.filter(users::email.ilike(like_pattern(op, &value))),
+            // externalId is caseExact
+            (FilterAttr::ExternalId, FilterOp::Eq) => query.filter(users::external_id.eq(value)),
+            (FilterAttr::ExternalId, FilterOp::Ne) => query.filter(
+                users::external_id
+                    .is_null()
+                    .or(users::external_id.ne(value)),
+            ),
+            (FilterAttr::ExternalId, op) => {
+                query.filter(users::external_id.like(like_pattern(op, &value)))
+            }
+        };
+    }
+
+    Ok(query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> ScimUserState {
+        ScimUserState {
+            user_name: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            external_id: Some("ext-1".to_string()),
+            active: true,
+        }
+    }
+
+    fn patch(operations: Value) -> ScimPatchRequest {
+        serde_json::from_value(json!({ "Operations": operations })).unwrap()
+    }
+
+    #[test]
+    fn tokenizes_quoted_values_with_escapes() {
+        assert_eq!(
+            tokenize(r#"userName eq "a \"b\" c""#).unwrap(),
+            ["userName", "eq", r#"a "b" c"#]
+        );
+        assert_eq!(
+            tokenize(r#"userName eq "open"#).unwrap_err().scim_type,
+            Some("invalidFilter")
+        );
+    }
+
+    #[test]
+    fn parses_clauses_joined_with_and() {
+        let clauses = parse_filter(
+            r#"userName Eq "alice" and emails[type eq "work"].value co "ex" and active pr"#,
+        )
+        .unwrap();
+        let parsed: Vec<_> = clauses
+            .iter()
+            .map(|clause| (clause.attr, clause.op, clause.value.as_deref()))
+            .collect();
+        assert_eq!(
+            parsed,
+            [
+                (FilterAttr::UserName, FilterOp::Eq, Some("alice")),
+                (FilterAttr::Email, FilterOp::Co, Some("ex")),
+                (FilterAttr::Active, FilterOp::Pr, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_schema_qualified_attributes() {
+        let filter = format!(r#"{}:userName eq "alice""#, USER_SCHEMA);
+        assert_eq!(parse_filter(&filter).unwrap()[0].attr, FilterAttr::UserName);
+    }
+
+    #[test]
+    fn rejects_unsupported_filters() {
+        for filter in [
+            "",
+            "userName",
+            "userName eq",
+            r#"nickName eq "x""#,
+            r#"userName gt "x""#,
+            r#"userName eq "a" or userName eq "b""#,
+            r#"emails[type eq "work" co "x""#,
+        ] {
+            let error = parse_filter(filter).unwrap_err();
+            assert_eq!(error.scim_type, Some("invalidFilter"), "{}", filter);
+        }
+    }
+
+    #[test]
+    fn escapes_like_patterns() {
+        assert_eq!(like_pattern(FilterOp::Co, "50%_off"), r"%50\%\_off%");
+        assert_eq!(like_pattern(FilterOp::Sw, r"a\b"), r"a\\b%");
+        assert_eq!(like_pattern(FilterOp::Ew, "x"), "%x");
+        assert_eq!(like_pattern(FilterOp::Eq, "x"), "x");
+    }
+
+    #[test]
+    fn normalizes_paths() {
+        assert_eq!(
+            normalize_path(r#"emails[type eq "work"].value"#),
+            "emails.value"
+        );
+        assert_eq!(
+            normalize_path(&format!("{}:userName", USER_SCHEMA)),
+            "username"
+        );
+    }
+
+    #[test]
+    fn applies_patch_operations() {
+        let mut user = state();
+        user.apply_patch(&patch(json!([
+            { "op": "Replace", "path": "active", "value": "False" },
+            { "op": "add", "value": { "userName": "alice2", "displayName": "ignored" } },
+            { "op": "replace", "path": "emails[type eq \"work\"].value", "value": "a2@example.com" },
+            { "op": "remove", "path": "externalId" }
+        ])))
+        .unwrap();
+        assert!(!user.active);
+        assert_eq!(user.user_name, "alice2");
+        assert_eq!(user.email, "a2@example.com");
+        assert_eq!(user.external_id, None);
+    }
+
+    #[test]
+    fn rejects_invalid_patches() {
+        let cases = [
+            (
+                json!([{ "op": "remove", "path": "userName" }]),
+                Some("mutability"),
+            ),
+            (json!([{ "op": "remove" }]), Some("noTarget")),
+            (
+                json!([{ "op": "replace", "path": "active", "value": 1 }]),
+                Some("invalidValue"),
+            ),
+            (
+                json!([{ "op": "move", "path": "active" }]),
+                Some("invalidValue"),
+            ),
+            (
+                json!([{ "op": "replace", "path": "userName", "value": "ab" }]),
+                Some("invalidValue"),
+            ),
+        ];
+        for (operations, scim_type) in cases {
+            let error = state().apply_patch(&patch(operations.clone())).unwrap_err();
+            assert_eq!(error.scim_type, scim_type, "{}", operations);
+        }
+    }
+
+    #[test]
+    fn prefers_the_primary_email() {
+        let emails: Vec<ScimEmail> = serde_json::from_value(json!([
+            { "value": "first@example.com" },
+            { "value": "primary@example.com", "primary": true }
+        ]))
+        .unwrap();
+        assert_eq!(
+            primary_email(&emails).as_deref(),
+            Some("primary@example.com")
+        );
+        assert_eq!(primary_email(&[]), None);
+    }
+}
@@ -0,0 +1,543 @@
+//! Subtask hierarchy: parent validation, subtree queries and the handling of
+//! children when their parent is completed or deleted.
+
+use std::collections::HashMap;
+
+use diesel::dsl::now;
+use diesel::prelude::*;
+use diesel::sql_types;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    access::{self, AccessError},
+    models::{GrantRole, SubtaskProgress, Task, TaskResponse},
+    schema::tasks,
+    task_events::{self, Changes},
+    task_positions, task_responses,
+    workflows::{self, WorkflowError},
+};
+
+/// Policy loaded from the environment at startup.
+pub static POLICY: Lazy<SubtaskPolicy> =
+    Lazy::new(|| SubtaskPolicy::from_env().expect("Invalid subtask policy configuration"));
+
+/// What completing a task does to its open subtasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnParentComplete {
+    /// Complete the whole subtree along with the parent
+    Complete,
+    /// Refuse while any subtask is open
+    Block,
+    /// Leave subtasks as they are
+    Ignore,
+}
+
+/// What deleting a task does to its subtasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnParentDelete {
+    /// Delete the whole subtree
+    Cascade,
+    /// Move direct children up to the deleted task's parent
+    Promote,
+    /// Refuse while the task has subtasks
+    Block,
+}
+
+#[derive(Debug)]
+pub struct SubtaskPolicy {
+    /// Levels of nesting allowed below a top-level task
+    pub max_depth: i32,
+    pub on_complete: OnParentComplete,
+    pub on_delete: OnParentDelete,
+}
+
+impl SubtaskPolicy {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let max_depth = match std::env::var("SUBTASK_MAX_DEPTH") {
+            Ok(value) => value
+                .parse::<i32>()
+                .ok()
+                .filter(|depth| *depth >= 0)
+                .ok_or_else(|| anyhow::anyhow!("SUBTASK_MAX_DEPTH must be a non-negative integer"))?,
+            Err(_) => 3,
+        };
+
+        let on_complete = match std::env::var("SUBTASK_ON_PARENT_COMPLETE")
+            .unwrap_or_else(|_| "complete".to_string())
+            .to_lowercase()
+            .as_str()
+        {
+            "complete" => OnParentComplete::Complete,
+            "block" => OnParentComplete::Block,
+            "ignore" => OnParentComplete::Ignore,
+            other => anyhow::bail!("Unknown SUBTASK_ON_PARENT_COMPLETE '{}'", other),
+        };
+
+        let on_delete = match std::env::var("SUBTASK_ON_PARENT_DELETE")
+            .unwrap_or_else(|_| "cascade".to_string())
+            .to_lowercase()
+            .as_str()
+        {
+            "cascade" => OnParentDelete::Cascade,
+            "promote" => OnParentDelete::Promote,
+            "block" => OnParentDelete::Block,
+            other => anyhow::bail!("Unknown SUBTASK_ON_PARENT_DELETE '{}'", other),
+        };
+
+        Ok(Self {
+            max_depth,
+            on_complete,
+            on_delete,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SubtaskError {
+    /// The requested parent is missing, inside the task's own subtree or too
+    /// deep; maps to 400.
+    #[error("{0}")]
+    InvalidParent(String),
+    /// The configured policy forbids the change; maps to 409.
+    #[error("{0}")]
+    Conflict(String),
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+#[derive(QueryableByName)]
+struct SubtreeRow {
+    #[diesel(sql_type = sql_types::Uuid)]
+    id: Uuid,
+    #[diesel(sql_type = sql_types::Integer)]
+    depth: i32,
+}
+
+#[derive(QueryableByName)]
+struct DepthRow {
+    #[diesel(sql_type = sql_types::Integer)]
+    depth: i32,
+}
+
+#[derive(QueryableByName)]
+struct ProgressRow {
+    #[diesel(sql_type = sql_types::Uuid)]
+    root_id: Uuid,
+    #[diesel(sql_type = sql_types::BigInt)]
+    total: i64,
+    #[diesel(sql_type = sql_types::BigInt)]
+    completed: i64,
+}
+
+/// The task and all of its descendants not in the trash, with their depth
+/// below it.
+fn subtree(conn: &mut PgConnection, task_id: Uuid) -> QueryResult<Vec<SubtreeRow>> {
+    diesel::sql_query(
+        "WITH RECURSIVE subtree AS (
+             SELECT id, 0 AS depth FROM tasks WHERE id = $1
+             UNION ALL
+             SELECT t.id, s.depth + 1 FROM tasks t JOIN subtree s ON t.parent_id = s.id
+             WHERE t.deleted_at IS NULL
+         )
+         SELECT id, depth FROM subtree",
+    )
+    .bind::<sql_types::Uuid, _>(task_id)
+    .load(conn)
+}
+
+/// Number of ancestors above the task (0 for a top-level task).
+fn depth(conn: &mut PgConnection, task_id: Uuid) -> QueryResult<i32> {
+    let row: DepthRow = diesel::sql_query(
+        "WITH RECURSIVE chain AS (
+             SELECT id, parent_id, 0 AS depth FROM tasks WHERE id = $1
+             UNION ALL
+             SELECT t.id, t.parent_id, c.depth + 1 FROM tasks t JOIN chain c ON t.id = c.parent_id
+         )
+         SELECT MAX(depth) AS depth FROM chain",
+    )
+    .bind::<sql_types::Uuid, _>(task_id)
+    .get_result(conn)?;
+    Ok(row.depth)
+}
+
+fn descendant_ids(conn: &mut PgConnection, task_id: Uuid) -> QueryResult<Vec<Uuid>> {
+    Ok(subtree(conn, task_id)?
+        .into_iter()
+        .filter(|row| row.depth > 0)
+        .map(|row| row.id)
+        .collect())
+}
+
+/// The deleted descendants of a deleted task that went to the trash with it.
+pub fn trashed_with(conn: &mut PgConnection, task: &Task) -> QueryResult<Vec<Uuid>> {
+    let rows: Vec<SubtreeRow> = diesel::sql_query(
+        "WITH RECURSIVE subtree AS (
+             SELECT id, deleted_at, 0 AS depth FROM tasks WHERE id = $1
+             UNION ALL
+             SELECT t.id, t.deleted_at, s.depth + 1 FROM tasks t JOIN subtree s ON t.parent_id = s.id
+             WHERE t.deleted_at = s.deleted_at
+         )
+         SELECT id, depth FROM subtree WHERE depth > 0",
+    )
+    .bind::<sql_types::Uuid, _>(task.id)
+    .load(conn)?;
+    Ok(rows.into_iter().map(|row| row.id).collect())
+}
+
+/// Checks that `parent_id` can hold `task_id` (or a new task when `None`) and
+/// returns the parent.
+pub fn check_parent(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    task_id: Option<Uuid>,
+    parent_id: Uuid,
+) -> Result<Task, SubtaskError> {
+    let parent = match access::task(conn, user_id, parent_id, GrantRole::Editor) {
+        Ok((parent, _)) => parent,
+        Err(AccessError::NotFound) => {
+            return Err(SubtaskError::InvalidParent("Parent task not found".to_string()));
+        }
+        Err(AccessError::Forbidden(role)) => {
+            return Err(SubtaskError::InvalidParent(format!(
+                "Requires {} access to the parent task",
+                role
+            )));
+        }
+        Err(AccessError::Database(e)) => return Err(e.into()),
+    };
+
+    // Height of the subtree being attached; a new task has none
+    let height = match task_id {
+        Some(task_id) => {
+            let rows = subtree(conn, task_id)?;
+            if rows.iter().any(|row| row.id == parent_id) {
+                return Err(SubtaskError::InvalidParent(
+                    "A task cannot be nested under itself or its subtasks".to_string(),
+                ));
+            }
+            rows.iter().map(|row| row.depth).max().unwrap_or(0)
+        }
+        None => 0,
+    };
+
+    if depth(conn, parent_id)? + 1 + height > POLICY.max_depth {
+        return Err(SubtaskError::InvalidParent(format!(
+            "Subtasks can be nested at most {} levels deep",
+            POLICY.max_depth
+        )));
+    }
+    Ok(parent)
+}
+
+/// Applies `POLICY.on_complete` before `task_id` is marked completed.
+pub fn on_parent_complete(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    actor_id: Uuid,
+) -> Result<(), SubtaskError> {
+    if POLICY.on_complete == OnParentComplete::Ignore {
+        return Ok(());
+    }
+    let descendants = descendant_ids(conn, task_id)?;
+    let open = tasks::table
+        .filter(tasks::id.eq_any(&descendants))
+        .filter(tasks::completed.eq(false));
+
+    match POLICY.on_complete {
+        // Each subtask moves to a done status of its project as a PATCH with
+        // `completed` would, so the move must be allowed by the workflow
+        OnParentComplete::Complete => {
+            let open_tasks: Vec<Task> = open.load(conn)?;
+            for task in open_tasks {
+                let status = workflows::target_status(conn, &task, task.project_id, None, Some(true))
+                    .map_err(|e| match e {
+                        WorkflowError::Database(e) => SubtaskError::Database(e),
+                        e => SubtaskError::Conflict(format!(
+                            "Subtask '{}' cannot be completed: {}",
+                            task.title, e
+                        )),
+                    })?;
+                let Some(status) = status else {
+                    continue;
+                };
+                let completed: Task = diesel::update(tasks::table.find(task.id))
+                    .set((tasks::status_id.eq(status.id), tasks::updated_at.eq(now)))
+                    .get_result(conn)?;
+                task_events::updated(conn, task.id, Changes::between(&task, &completed), actor_id)?;
+            }
+        }
+        OnParentComplete::Block => {
+            let open_count: i64 = open.count().get_result(conn)?;
+            if open_count > 0 {
+                return Err(SubtaskError::Conflict(
+                    "Complete the task's subtasks first".to_string(),
+                ));
+            }
+        }
+        OnParentComplete::Ignore => {}
+    }
+    Ok(())
+}
+
+/// Applies `POLICY.on_delete` before `task` is moved to the trash.
+pub fn on_parent_delete(
+    conn: &mut PgConnection,
+    task: &Task,
+    actor_id: Uuid,
+) -> Result<(), SubtaskError> {
+    let children = tasks::table
+        .filter(tasks::parent_id.eq(task.id))
+        .filter(tasks::deleted_at.is_null());
+    match POLICY.on_delete {
+        // The subtree goes to the trash with the task and is restored with it
+        OnParentDelete::Cascade => {
+            let descendants: Vec<Task> = diesel::update(
+                tasks::table.filter(tasks::id.eq_any(descendant_ids(conn, task.id)?)),
+            )
+            .set(tasks::deleted_at.eq(now))
+            .get_results(conn)?;
+            for descendant in &descendants {
+                task_events::deleted(conn, descendant, actor_id)?;
+            }
+        }
+        // The children join the end of the task's own list, in their order
+        OnParentDelete::Promote => {
+            let promoted_ids: Vec<Uuid> = children
+                .order((tasks::position.asc(), tasks::id.asc()))
+                .select(tasks::id)
+                .load(conn)?;
+            for id in promoted_ids {
+                let position = task_positions::append(conn, task.project_id, task.parent_id)?;
+                diesel::update(tasks::table.find(id))
+                    .set((
+                        tasks::parent_id.eq(task.parent_id),
+                        tasks::position.eq(position),
+                    ))
+                    .execute(conn)?;
+                let mut changes = Changes::default();
+                changes.set("parent_id", task.id, task.parent_id);
+                task_events::updated(conn, id, changes, actor_id)?;
+            }
+        }
+        OnParentDelete::Block => {
+            let child_count: i64 = children.count().get_result(conn)?;
+            if child_count > 0 {
+                return Err(SubtaskError::Conflict(
+                    "Delete or move the task's subtasks first".to_string(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Moves a task's descendants into `project_id`; subtasks always share their
+/// parent's project.
+pub fn move_descendants(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    project_id: Uuid,
+    actor_id: Uuid,
+) -> QueryResult<()> {
+    let descendants = descendant_ids(conn, task_id)?;
+    let moved: Vec<(Uuid, Uuid)> = tasks::table
+        .filter(tasks::id.eq_any(&descendants))
+        .filter(tasks::project_id.ne(project_id))
+        .select((tasks::id, tasks::project_id))
+        .load(conn)?;
+    diesel::update(tasks::table.filter(tasks::id.eq_any(&descendants)))
+        .set(tasks::project_id.eq(project_id))
+        .execute(conn)?;
+    for (id, previous_project_id) in moved {
+        let mut changes = Changes::default();
+        changes.set("project_id", previous_project_id, project_id);
+        task_events::updated(conn, id, changes, actor_id)?;
+    }
+    Ok(())
+}
+
+/// Completed and total descendants of each task, in one query for the batch.
+pub fn progress_for(
+    conn: &mut PgConnection,
+    task_ids: &[Uuid],
+) -> QueryResult<HashMap<Uuid, SubtaskProgress>> {
+    let rows: Vec<ProgressRow> = diesel::sql_query(
+        "WITH RECURSIVE descendants AS (
+             SELECT parent_id AS root_id, id, completed FROM tasks
+             WHERE parent_id = ANY($1) AND deleted_at IS NULL
+             UNION ALL
+             SELECT d.root_id, t.id, t.completed FROM tasks t JOIN descendants d ON t.parent_id = d.id
+             WHERE t.deleted_at IS NULL
+         )
+         SELECT root_id, COUNT(*) AS total, COUNT(*) FILTER (WHERE completed) AS completed
+         FROM descendants
+         GROUP BY root_id",
+    )
+    .bind::<sql_types::Array<sql_types::Uuid>, _>(task_ids)
+    .load(conn)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            (
+                row.root_id,
+                SubtaskProgress {
+                    completed: row.completed,
+                    total: row.total,
+                },
+            )
+        })
+        .collect())
+}
+
+/// A task with its nested subtasks.
+#[derive(Debug, Clone, Serialize)]
+pub struct SubtaskNode {
+    #[serde(flatten)]
+    pub task: TaskResponse,
+    pub subtasks: Vec<SubtaskNode>,
+}
+
+/// The subtasks of `task_id` visible to `user_id` as a tree, oldest first at
+/// each level. A hidden subtask hides its own subtasks too.
+pub fn load_tree(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    task_id: Uuid,
+) -> QueryResult<Vec<SubtaskNode>> {
+    let descendants = descendant_ids(conn, task_id)?;
+    let tasks: Vec<Task> = tasks::table
+        .filter(tasks::id.eq_any(&descendants))
+        .filter(access::visible_tasks(user_id))
+        .order((tasks::created_at.asc(), tasks::id.asc()))
+        .load(conn)?;
+
+    let mut children: HashMap<Uuid, Vec<TaskResponse>> = HashMap::new();
+    for response in task_responses::build(conn, tasks)? {
+        if let Some(parent_id) = response.parent_id {
+            children.entry(parent_id).or_default().push(response);
+        }
+    }
+
+    fn attach(parent_id: Uuid, children: &mut HashMap<Uuid, Vec<TaskResponse>>) -> Vec<SubtaskNode> {
+        children
+            .remove(&parent_id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|task| {
+                let subtasks = attach(task.id, children);
+                SubtaskNode { task, subtasks }
+            })
+            .collect()
+    }
+
+    Ok(attach(task_id, &mut children))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bearer, pool, send, task, user};
+    use actix_web::{http::StatusCode, test::TestRequest};
+    use serde_json::{json, Value};
+
+    async fn patch(
+        pool: &crate::DbPool,
+        user_id: Uuid,
+        task: &Value,
+        body: Value,
+    ) -> (StatusCode, Value) {
+        send(
+            pool,
+            TestRequest::patch()
+                .uri(&format!("/api/tasks/{}", task["id"].as_str().unwrap()))
+                .insert_header(bearer(user_id))
+                .set_json(body),
+        )
+        .await
+    }
+
+    async fn history(pool: &crate::DbPool, user_id: Uuid, task: &Value) -> Vec<Value> {
+        let (_, body) = send(
+            pool,
+            TestRequest::get()
+                .uri(&format!(
+                    "/api/tasks/{}/history",
+                    task["id"].as_str().unwrap()
+                ))
+                .insert_header(bearer(user_id)),
+        )
+        .await;
+        body["events"].as_array().unwrap().clone()
+    }
+
+    #[actix_web::test]
+    async fn completing_a_parent_moves_subtasks_to_done() {
+        let Some(pool) = pool() else { return };
+        let user = user(&pool);
+        let parent = task(&pool, user.id, json!({"title": "Parent"})).await;
+        let child = task(
+            &pool,
+            user.id,
+            json!({"title": "Child", "parent_id": parent["id"]}),
+        )
+        .await;
+
+        let (status, _) = patch(&pool, user.id, &parent, json!({"completed": true})).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let events = history(&pool, user.id, &child).await;
+        assert_eq!(events[0]["event_type"], "completed");
+        assert_eq!(
+            events[0]["changes"]["status_id"]["from"],
+            child["status_id"]
+        );
+        let (_, body) = send(
+            &pool,
+            TestRequest::get()
+                .uri(&format!("/api/tasks/{}", child["id"].as_str().unwrap()))
+                .insert_header(bearer(user.id)),
+        )
+        .await;
+        assert_eq!(body["task"]["completed"], json!(true));
+        assert_eq!(events[0]["changes"]["status_id"]["to"], body["task"]["status_id"]);
+    }
+
+    #[actix_web::test]
+    async fn completing_a_parent_follows_the_subtasks_workflow() {
+        let Some(pool) = pool() else { return };
+        let user = user(&pool);
+        let parent = task(&pool, user.id, json!({"title": "Parent"})).await;
+        let child = task(
+            &pool,
+            user.id,
+            json!({"title": "Child", "parent_id": parent["id"]}),
+        )
+        .await;
+        // Tasks must pass review to be done
+        let (status, workflow) = send(
+            &pool,
+            TestRequest::put()
+                .uri(&format!("/api/projects/{}/workflow", parent["project_id"].as_str().unwrap()))
+                .insert_header(bearer(user.id))
+                .set_json(json!({
+                    "statuses": [{"name": "todo"}, {"name": "review"}, {"name": "done", "done": true}],
+                    "transitions": [{"from": "todo", "to": "review"}, {"from": "review", "to": "done"}],
+                })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let review = workflow["statuses"][1]["id"].clone();
+        let (status, _) = patch(&pool, user.id, &parent, json!({"status_id": review})).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = patch(&pool, user.id, &parent, json!({"completed": true})).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}", body);
+        assert_eq!(
+            history(&pool, user.id, &child).await[0]["event_type"],
+            "created"
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! Per-user tags and their association with tasks.
+
+use diesel::dsl::count;
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use uuid::Uuid;
+
+use crate::{
+    models::{NewTag, Tag, Task, TaskTag},
+    schema::{tags, task_tags, tasks},
+};
+
+diesel::define_sql_function!(fn lower(x: Text) -> Text);
+
+/// Trims names and drops case-insensitive duplicates, keeping the first spelling.
+pub fn normalize_names<S: AsRef<str>>(names: &[S]) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    names
+        .iter()
+        .map(|name| name.as_ref().trim())
+        .filter(|name| !name.is_empty() && seen.insert(name.to_lowercase()))
+        .map(str::to_string)
+        .collect()
+}
+
+/// The user's tags with the given names, creating any that don't exist yet.
+pub fn resolve(conn: &mut PgConnection, user_id: Uuid, names: &[String]) -> QueryResult<Vec<Tag>> {
+    let names = normalize_names(names);
+    if names.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let new_tags: Vec<NewTag> = names
+        .iter()
+        .map(|name| NewTag {
+            user_id,
+            name: name.clone(),
+            color: None,
+        })
+        .collect();
+    // Existing names hit the (user_id, lower(name)) unique index and are skipped
+    diesel::insert_into(tags::table)
+        .values(&new_tags)
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+
+    let lowered: Vec<String> = names.iter().map(|name| name.to_lowercase()).collect();
+    tags::table
+        .filter(tags::user_id.eq(user_id))
+        .filter(lower(tags::name).eq_any(lowered))
+        .order(tags::name.asc())
+        .load(conn)
+}
+
+/// Sorted names of `tags`, for task history.
+pub fn names(tags: &[Tag]) -> Vec<String> {
+    let mut names: Vec<String> = tags.iter().map(|tag| tag.name.clone()).collect();
+    names.sort();
+    names
+}
+
+/// Replaces the tags attached to a task.
+pub fn set_task_tags(conn: &mut PgConnection, task_id: Uuid, tags: &[Tag]) -> QueryResult<()> {
+    diesel::delete(task_tags::table.filter(task_tags::task_id.eq(task_id))).execute(conn)?;
+    let rows: Vec<TaskTag> = tags
+        .iter()
+        .map(|tag| TaskTag {
+            task_id,
+            tag_id: tag.id,
+        })
+        .collect();
+    diesel::insert_into(task_tags::table)
+        .values(&rows)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Tags of each task, in the order of `tasks`, loaded with a single query.
+pub fn load_for_tasks(conn: &mut PgConnection, tasks: &[Task]) -> QueryResult<Vec<Vec<Tag>>> {
+    let rows: Vec<(TaskTag, Tag)> = TaskTag::belonging_to(tasks)
+        .inner_join(tags::table)
+        .select((TaskTag::as_select(), Tag::as_select()))
+        .order(tags::name.asc())
+        .load(conn)?;
+    Ok(rows
+        .grouped_by(tasks)
+        .into_iter()
+        .map(|group| group.into_iter().map(|(_, tag)| tag).collect())
+        .collect())
+}
+
+/// The user's tags, each with the number of tasks using it.
+pub fn with_task_counts(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<Vec<(Tag, i64)>> {
+    // Tasks in the trash keep their tags but aren't counted
+    tags::table
+        .left_join(task_tags::table)
+        .left_join(
+            tasks::table.on(tasks::id
+                .eq(task_tags::task_id)
+                .and(tasks::deleted_at.is_null())),
+        )
+        .filter(tags::user_id.eq(user_id))
+        .group_by(tags::id)
+        .select((Tag::as_select(), count(tasks::id.nullable())))
+        .order(tags::name.asc())
+        .load(conn)
+}
+
+/// Number of tasks using a single tag.
+pub fn task_count(conn: &mut PgConnection, tag_id: Uuid) -> QueryResult<i64> {
+    task_tags::table
+        .inner_join(tasks::table)
+        .filter(task_tags::tag_id.eq(tag_id))
+        .filter(tasks::deleted_at.is_null())
+        .count()
+        .get_result(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bearer, pool, send, task, user};
+    use actix_web::{http::StatusCode, test::TestRequest};
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn counts_leave_out_trashed_tasks() {
+        let Some(pool) = pool() else { return };
+        let user = user(&pool);
+        task(&pool, user.id, json!({"title": "Kept", "tags": ["work"]})).await;
+        let trashed = task(
+            &pool,
+            user.id,
+            json!({"title": "Trashed", "tags": ["work"]}),
+        )
+        .await;
+        let (status, _) = send(
+            &pool,
+            TestRequest::delete()
+                .uri(&format!("/api/tasks/{}", trashed["id"].as_str().unwrap()))
+                .insert_header(bearer(user.id)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let conn = &mut pool.get().unwrap();
+        let counts = with_task_counts(conn, user.id).unwrap();
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts[0].1, 1);
+        assert_eq!(task_count(conn, counts[0].0.id).unwrap(), 1);
+    }
+}
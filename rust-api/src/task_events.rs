@@ -0,0 +1,175 @@
+//! Per-task history: who changed a task, and each changed field's value
+//! before and after. Events are written in the transaction of the change they
+//! describe, so a rolled-back change leaves no trace.
+
+use diesel::prelude::*;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+use crate::{
+    models::{NewTaskEvent, Task, TaskEvent, TaskEventResponse, TaskHistoryQuery, User},
+    schema::{task_events, users},
+    task_query::{DEFAULT_LIMIT, MAX_LIMIT},
+};
+
+pub const EVENT_CREATED: &str = "created";
+pub const EVENT_UPDATED: &str = "updated";
+pub const EVENT_COMPLETED: &str = "completed";
+pub const EVENT_REOPENED: &str = "reopened";
+pub const EVENT_TRANSITIONED: &str = "transitioned";
+pub const EVENT_DELETED: &str = "deleted";
+pub const EVENT_RESTORED: &str = "restored";
+
+/// Changed fields of a task, each as `{"from": ..., "to": ...}`.
+#[derive(Debug, Default)]
+pub struct Changes(Map<String, Value>);
+
+impl Changes {
+    /// Every tracked field that differs between two versions of a task.
+    pub fn between(before: &Task, after: &Task) -> Self {
+        let mut changes = Self::default();
+        for ((name, from), (_, to)) in fields(before).into_iter().zip(fields(after)) {
+            changes.set(name, from, to);
+        }
+        changes
+    }
+
+    /// Adds `name` unless `from` and `to` are equal.
+    pub fn set(&mut self, name: &str, from: impl Serialize, to: impl Serialize) {
+        let (from, to) = (json!(from), json!(to));
+        if from != to {
+            self.0
+                .insert(name.to_string(), json!({ "from": from, "to": to }));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // Completing or reopening is the headline of an update that does either,
+    // then moving to another status
+    fn event_type(&self) -> &'static str {
+        match self.0.get("completed").map(|completed| &completed["to"]) {
+            Some(Value::Bool(true)) => EVENT_COMPLETED,
+            Some(Value::Bool(false)) => EVENT_REOPENED,
+            _ if self.0.contains_key("status_id") => EVENT_TRANSITIONED,
+            _ => EVENT_UPDATED,
+        }
+    }
+}
+
+// The fields history is kept for, in a fixed order
+fn fields(task: &Task) -> [(&'static str, Value); 10] {
+    [
+        ("title", json!(task.title)),
+        ("description", json!(task.description)),
+        ("completed", json!(task.completed)),
+        ("status_id", json!(task.status_id)),
+        ("due_at", json!(task.due_at)),
+        ("due_date", json!(task.due_date)),
+        ("priority", json!(task.priority)),
+        ("project_id", json!(task.project_id)),
+        ("parent_id", json!(task.parent_id)),
+        ("assignee_id", json!(task.assignee_id)),
+    ]
+}
+
+fn insert(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    actor_id: Uuid,
+    event_type: &str,
+    changes: Changes,
+) -> QueryResult<()> {
+    diesel::insert_into(task_events::table)
+        .values(&NewTaskEvent {
+            task_id,
+            actor_id: Some(actor_id),
+            event_type: event_type.to_string(),
+            changes: Value::Object(changes.0),
+        })
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Records a new task with the fields and tags it starts with.
+pub fn created(
+    conn: &mut PgConnection,
+    task: &Task,
+    tag_names: &[String],
+    actor_id: Uuid,
+) -> QueryResult<()> {
+    let mut changes = Changes::default();
+    for (name, value) in fields(task) {
+        changes.set(name, Value::Null, value);
+    }
+    if !tag_names.is_empty() {
+        changes.set("tags", Value::Null, tag_names);
+    }
+    insert(conn, task.id, actor_id, EVENT_CREATED, changes)
+}
+
+/// Records a change to a task; nothing is written when no field changed.
+pub fn updated(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    changes: Changes,
+    actor_id: Uuid,
+) -> QueryResult<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let event_type = changes.event_type();
+    insert(conn, task_id, actor_id, event_type, changes)
+}
+
+/// Records a task moving to the trash with the field values it had.
+pub fn deleted(conn: &mut PgConnection, task: &Task, actor_id: Uuid) -> QueryResult<()> {
+    let mut changes = Changes::default();
+    for (name, value) in fields(task) {
+        changes.set(name, value, Value::Null);
+    }
+    insert(conn, task.id, actor_id, EVENT_DELETED, changes)
+}
+
+/// Records a task coming back from the trash.
+pub fn restored(conn: &mut PgConnection, task_id: Uuid, actor_id: Uuid) -> QueryResult<()> {
+    insert(conn, task_id, actor_id, EVENT_RESTORED, Changes::default())
+}
+
+pub struct HistoryPage {
+    pub events: Vec<TaskEventResponse>,
+    pub total: i64,
+    pub next_offset: Option<i64>,
+}
+
+/// The task's events, newest first.
+pub fn list(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    params: &TaskHistoryQuery,
+) -> QueryResult<HistoryPage> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let history = task_events::table.filter(task_events::task_id.eq(task_id));
+    let total: i64 = history.count().get_result(conn)?;
+    let rows: Vec<(TaskEvent, Option<User>)> = history
+        .left_join(users::table)
+        .order((task_events::created_at.desc(), task_events::id.desc()))
+        .limit(limit)
+        .offset(offset)
+        .load(conn)?;
+
+    let next_offset = Some(offset + limit).filter(|next| *next < total);
+    Ok(HistoryPage {
+        events: rows
+            .into_iter()
+            .map(|(event, actor)| TaskEventResponse::new(event, actor))
+            .collect(),
+        total,
+        next_offset,
+    })
+}
@@ -0,0 +1,363 @@
+//! Manual ordering of tasks. A list (a project's top-level tasks, or the
+//! subtasks of one parent) is ordered by `position`: base-62 keys compared
+//! byte by byte, so a key can be made between any two neighbours and a move
+//! rewrites only the moved task. When keys grow too long, or neighbours share
+//! a key, the whole list is given evenly spaced keys again.
+
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::sql_types::{Bool, Nullable};
+use uuid::Uuid;
+
+use crate::{models::Task, schema::tasks};
+
+const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const BASE: usize = DIGITS.len();
+
+type ListPredicate = Box<dyn BoxableExpression<tasks::table, Pg, SqlType = Nullable<Bool>>>;
+
+/// Keys longer than this make their list be rebalanced.
+pub const MAX_POSITION_LEN: usize = 16;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PositionError {
+    /// A neighbour is missing or not in the task's list; maps to 400.
+    #[error("{0}")]
+    Invalid(String),
+    /// The neighbours are no longer next to each other; maps to 409.
+    #[error("{0}")]
+    Conflict(String),
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+fn value(digit: u8) -> usize {
+    DIGITS.iter().position(|d| *d == digit).unwrap_or(0)
+}
+
+// A key strictly between `low` and `high` (no upper bound when `None`), or
+// `None` when there is none, e.g. because `low` is not below `high`
+fn midpoint(low: &[u8], high: Option<&[u8]>) -> Option<Vec<u8>> {
+    if let Some(high) = high {
+        // Shared prefix, reading digits missing from `low` as zero
+        let shared = high
+            .iter()
+            .enumerate()
+            .take_while(|(i, digit)| low.get(*i).copied().unwrap_or(DIGITS[0]) == **digit)
+            .count();
+        if shared > 0 {
+            let mut key = high[..shared].to_vec();
+            key.extend(midpoint(
+                low.get(shared..).unwrap_or_default(),
+                Some(&high[shared..]),
+            )?);
+            return Some(key);
+        }
+    }
+
+    let low_digit = low.first().map_or(0, |digit| value(*digit));
+    let high_digit = match high {
+        Some(high) => high.first().map_or(0, |digit| value(*digit)),
+        None => BASE,
+    };
+    if high_digit <= low_digit {
+        return None;
+    }
+    if high_digit - low_digit > 1 {
+        return Some(vec![DIGITS[(low_digit + high_digit) / 2]]);
+    }
+    // Adjacent first digits: cut `high` short, or extend `low`
+    match high {
+        Some(high) if high.len() > 1 => Some(high[..1].to_vec()),
+        _ => {
+            let mut key = vec![DIGITS[low_digit]];
+            key.extend(midpoint(low.get(1..).unwrap_or_default(), None)?);
+            Some(key)
+        }
+    }
+}
+
+/// A key after `after` and before `before`; either may be `None` for the
+/// start or end of the list.
+pub fn between(after: Option<&str>, before: Option<&str>) -> Option<String> {
+    let key = midpoint(
+        after.unwrap_or_default().as_bytes(),
+        before.map(str::as_bytes),
+    )?;
+    String::from_utf8(key).ok()
+}
+
+/// `count` keys in ascending order, evenly spread with room to insert between
+/// each pair.
+pub fn spaced(count: usize) -> Vec<String> {
+    let slots = count as u128 + 1;
+    let mut width = 1;
+    let mut range = BASE as u128;
+    while range < slots * BASE as u128 {
+        width += 1;
+        range *= BASE as u128;
+    }
+    let step = range / slots;
+
+    (1..slots)
+        .map(|slot| {
+            let mut n = slot * step;
+            let mut key = vec![DIGITS[0]; width];
+            for digit in key.iter_mut().rev() {
+                *digit = DIGITS[(n % BASE as u128) as usize];
+                n /= BASE as u128;
+            }
+            // Trailing zeros add nothing to the order and would leave no key below
+            while key.last() == Some(&DIGITS[0]) {
+                key.pop();
+            }
+            String::from_utf8(key).unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Matches the live tasks of the list containing a task in `project_id`
+/// under `parent_id`.
+pub fn in_list(project_id: Uuid, parent_id: Option<Uuid>) -> ListPredicate {
+    match parent_id {
+        Some(parent_id) => Box::new(
+            tasks::deleted_at
+                .is_null()
+                .and(tasks::parent_id.eq(parent_id)),
+        ),
+        None => Box::new(
+            tasks::deleted_at
+                .is_null()
+                .and(tasks::project_id.eq(project_id))
+                .and(tasks::parent_id.is_null())
+                .nullable(),
+        ),
+    }
+}
+
+// Gives every task in the list a fresh key, in the order of `ids`
+fn respace(conn: &mut PgConnection, ids: &[Uuid]) -> QueryResult<()> {
+    for (id, position) in ids.iter().zip(spaced(ids.len())) {
+        diesel::update(tasks::table.find(id))
+            .set(tasks::position.eq(position))
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+// The list's ids in order, locked so concurrent moves take turns
+fn lock_list(
+    conn: &mut PgConnection,
+    project_id: Uuid,
+    parent_id: Option<Uuid>,
+) -> QueryResult<Vec<(Uuid, String)>> {
+    tasks::table
+        .filter(in_list(project_id, parent_id))
+        .select((tasks::id, tasks::position))
+        .order((tasks::position.asc(), tasks::id.asc()))
+        .for_update()
+        .load(conn)
+}
+
+/// The key for a task added at the end of a list. A list whose last key has
+/// grown too long is rebalanced first.
+pub fn append(
+    conn: &mut PgConnection,
+    project_id: Uuid,
+    parent_id: Option<Uuid>,
+) -> QueryResult<String> {
+    let last: Option<String> = tasks::table
+        .filter(in_list(project_id, parent_id))
+        .select(tasks::position)
+        .order(tasks::position.desc())
+        .first(conn)
+        .optional()?;
+    if let Some(position) = between(last.as_deref(), None).filter(|p| p.len() <= MAX_POSITION_LEN) {
+        return Ok(position);
+    }
+
+    let ids: Vec<Uuid> = lock_list(conn, project_id, parent_id)?
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    respace(conn, &ids)?;
+    let last = spaced(ids.len()).pop();
+    Ok(between(last.as_deref(), None).unwrap_or_default())
+}
+
+/// Moves a task after `after_id` and/or before `before_id` in its own list.
+pub fn move_task(
+    conn: &mut PgConnection,
+    task: &Task,
+    after_id: Option<Uuid>,
+    before_id: Option<Uuid>,
+) -> Result<Task, PositionError> {
+    if after_id == Some(task.id) || before_id == Some(task.id) {
+        return Err(PositionError::Invalid(
+            "A task cannot be moved next to itself".to_string(),
+        ));
+    }
+
+    let mut entries = lock_list(conn, task.project_id, task.parent_id)?;
+    entries.retain(|(id, _)| *id != task.id);
+    let index_of = |id: Uuid, name: &str| {
+        entries
+            .iter()
+            .position(|(entry_id, _)| *entry_id == id)
+            .ok_or_else(|| {
+                PositionError::Invalid(format!("{} must be a task in the same list", name))
+            })
+    };
+    let after = after_id.map(|id| index_of(id, "after_id")).transpose()?;
+    let before = before_id.map(|id| index_of(id, "before_id")).transpose()?;
+
+    // Where the task goes among the other tasks of the list
+    let index = match (after, before) {
+        (Some(after), Some(before)) if after + 1 != before => {
+            return Err(PositionError::Conflict(
+                "after_id and before_id are not next to each other".to_string(),
+            ));
+        }
+        (_, Some(before)) => before,
+        (Some(after), None) => after + 1,
+        (None, None) => {
+            return Err(PositionError::Invalid(
+                "Give after_id, before_id or both".to_string(),
+            ));
+        }
+    };
+
+    let low = index.checked_sub(1).map(|i| entries[i].1.as_str());
+    let high = entries.get(index).map(|(_, position)| position.as_str());
+    match between(low, high).filter(|p| p.len() <= MAX_POSITION_LEN) {
+        Some(position) => {
+            diesel::update(tasks::table.find(task.id))
+                .set(tasks::position.eq(position))
+                .execute(conn)?;
+        }
+        // Neighbours share a key or keys have grown too long
+        None => {
+            let mut ids: Vec<Uuid> = entries.iter().map(|(id, _)| *id).collect();
+            ids.insert(index, task.id);
+            respace(conn, &ids)?;
+        }
+    }
+    Ok(tasks::table.find(task.id).first(conn)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(low: &str, high: Option<&str>) -> Option<String> {
+        between(Some(low).filter(|low| !low.is_empty()), high)
+    }
+
+    fn assert_between(low: &str, high: Option<&str>) -> String {
+        let key =
+            key(low, high).unwrap_or_else(|| panic!("no key between {:?} and {:?}", low, high));
+        assert!(key.as_str() > low, "{:?} should sort after {:?}", key, low);
+        if let Some(high) = high {
+            assert!(
+                key.as_str() < high,
+                "{:?} should sort before {:?}",
+                key,
+                high
+            );
+        }
+        assert!(!key.ends_with('0'), "{:?} has a trailing zero", key);
+        key
+    }
+
+    #[test]
+    fn between_empty_list() {
+        assert_eq!(between(None, None).as_deref(), Some("V"));
+    }
+
+    #[test]
+    fn between_distant_digits_takes_the_middle() {
+        assert_eq!(assert_between("A", Some("C")), "B");
+        assert_between("", Some("z"));
+        assert_between("y", None);
+    }
+
+    #[test]
+    fn between_adjacent_digits_extends_the_key() {
+        assert_eq!(assert_between("A", Some("B")), "AV");
+        assert_between("z", None);
+        assert_between("zz", None);
+        assert_between("", Some("1"));
+        assert_between("", Some("01"));
+    }
+
+    #[test]
+    fn between_keys_with_shared_prefix() {
+        assert_between("AB", Some("AC"));
+        assert_between("AB", Some("ABz"));
+        assert_between("ABC", Some("AC"));
+        assert_between("A", Some("A1"));
+        assert_eq!(assert_between("Az", Some("B1")), "B");
+    }
+
+    #[test]
+    fn between_keeps_splitting() {
+        let mut high = "B".to_string();
+        for _ in 0..20 {
+            high = assert_between("A", Some(&high));
+        }
+        let mut low = "A".to_string();
+        for _ in 0..20 {
+            low = assert_between(&low, Some("B"));
+        }
+    }
+
+    #[test]
+    fn between_refuses_unordered_neighbours() {
+        assert_eq!(key("B", Some("B")), None);
+        assert_eq!(key("C", Some("B")), None);
+        assert_eq!(key("AB", Some("AB")), None);
+        assert_eq!(key("A1", Some("A")), None);
+        assert_eq!(key("", Some("")), None);
+        assert_eq!(key("", Some("0")), None);
+    }
+
+    #[test]
+    fn spaced_keys_are_ordered_without_trailing_zeros() {
+        for count in [0, 1, 2, 10, 61, 62, 63, 500, 4000] {
+            let keys = spaced(count);
+            assert_eq!(keys.len(), count);
+            assert!(
+                keys.windows(2).all(|pair| pair[0] < pair[1]),
+                "count {}",
+                count
+            );
+            assert!(keys
+                .iter()
+                .all(|key| !key.is_empty() && !key.ends_with('0')));
+        }
+    }
+
+    #[test]
+    fn spaced_keys_leave_room_around_each_key() {
+        let keys = spaced(100);
+        assert_between("", Some(&keys[0]));
+        for pair in keys.windows(2) {
+            assert_between(&pair[0], Some(&pair[1]));
+        }
+        assert_between(&keys[99], None);
+    }
+
+    #[test]
+    fn order_is_kept_after_a_respace() {
+        // A list whose keys grew long, respaced in its current order
+        let mut long = vec!["A".to_string()];
+        while long.last().unwrap().len() <= MAX_POSITION_LEN {
+            let next = between(long.last().map(String::as_str), Some("B")).unwrap();
+            long.push(next);
+        }
+        assert!(long.windows(2).all(|pair| pair[0] < pair[1]));
+        let respaced = spaced(long.len());
+        assert!(respaced.iter().all(|key| key.len() <= MAX_POSITION_LEN));
+        assert!(respaced.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
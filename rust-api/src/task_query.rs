@@ -0,0 +1,500 @@
+//! Filtering, sorting and keyset pagination for task listings.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use diesel::dsl::count;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::sql_types::Bool;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    access,
+    models::{AssigneeFilter, TagMatch, Task, TaskListQuery, TaskPriority},
+    schema::{tags, task_tags, tasks},
+    tags::{lower, normalize_names},
+};
+
+pub const DEFAULT_LIMIT: i64 = 50;
+pub const MAX_LIMIT: i64 = 200;
+pub const DEFAULT_SORT: &str = "created_at";
+
+type TaskPredicate = Box<dyn BoxableExpression<tasks::table, Pg, SqlType = Bool>>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TaskQueryError {
+    /// A query parameter (sort, cursor, ...) was malformed; maps to 400.
+    #[error("{0}")]
+    InvalidParameter(String),
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    CreatedAt,
+    UpdatedAt,
+    Title,
+    Completed,
+    Priority,
+    Position,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Comparison {
+    Before,
+    Equal,
+    After,
+}
+
+impl SortField {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "created_at" => Some(Self::CreatedAt),
+            "updated_at" => Some(Self::UpdatedAt),
+            "title" => Some(Self::Title),
+            "completed" => Some(Self::Completed),
+            "priority" => Some(Self::Priority),
+            "position" => Some(Self::Position),
+            _ => None,
+        }
+    }
+
+    fn cursor_value(&self, task: &Task) -> Value {
+        match self {
+            Self::CreatedAt => serde_json::to_value(task.created_at),
+            Self::UpdatedAt => serde_json::to_value(task.updated_at),
+            Self::Title => serde_json::to_value(&task.title),
+            Self::Completed => serde_json::to_value(task.completed),
+            Self::Priority => serde_json::to_value(task.priority),
+            Self::Position => serde_json::to_value(&task.position),
+        }
+        .unwrap_or(Value::Null)
+    }
+
+    // Compares the column with a value taken from a cursor
+    fn compare(&self, value: &Value, comparison: Comparison) -> Option<TaskPredicate> {
+        fn timestamp(value: &Value) -> Option<DateTime<Utc>> {
+            serde_json::from_value(value.clone()).ok()
+        }
+
+        macro_rules! compare_column {
+            ($column:expr, $value:expr) => {
+                match comparison {
+                    Comparison::Before => Box::new($column.lt($value)) as TaskPredicate,
+                    Comparison::Equal => Box::new($column.eq($value)),
+                    Comparison::After => Box::new($column.gt($value)),
+                }
+            };
+        }
+
+        Some(match self {
+            Self::CreatedAt => compare_column!(tasks::created_at, timestamp(value)?),
+            Self::UpdatedAt => compare_column!(tasks::updated_at, timestamp(value)?),
+            Self::Title => compare_column!(tasks::title, value.as_str()?.to_string()),
+            Self::Completed => compare_column!(tasks::completed, value.as_bool()?),
+            Self::Priority => compare_column!(
+                tasks::priority,
+                serde_json::from_value::<TaskPriority>(value.clone()).ok()?
+            ),
+            Self::Position => compare_column!(tasks::position, value.as_str()?.to_string()),
+        })
+    }
+}
+
+/// Parses a sort specification such as `-updated_at,title`; a leading `-`
+/// sorts descending. The task id is always appended as a tiebreaker.
+pub fn parse_sort(sort: &str) -> Result<Vec<SortKey>, String> {
+    let mut keys: Vec<SortKey> = Vec::new();
+    for part in sort.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (name, descending) = match part.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (part.strip_prefix('+').unwrap_or(part), false),
+        };
+        let field = SortField::parse(name).ok_or_else(|| format!("Invalid sort field '{}'", name))?;
+        if keys.iter().any(|k| k.field == field) {
+            return Err(format!("Sort field '{}' is repeated", name));
+        }
+        keys.push(SortKey { field, descending });
+    }
+    if keys.is_empty() {
+        keys.push(SortKey {
+            field: SortField::CreatedAt,
+            descending: false,
+        });
+    }
+    Ok(keys)
+}
+
+/// Position of the last row of a page, handed to clients as an opaque string.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cursor {
+    // Sort specification the cursor was issued for
+    s: String,
+    v: Vec<Value>,
+    id: Uuid,
+}
+
+impl Cursor {
+    pub fn for_task(sort: &str, keys: &[SortKey], task: &Task) -> Self {
+        Self {
+            s: sort.to_string(),
+            v: keys.iter().map(|k| k.field.cursor_value(task)).collect(),
+            id: task.id,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(encoded: &str, sort: &str, keys: &[SortKey]) -> Result<Self, String> {
+        let cursor: Cursor = URL_SAFE_NO_PAD
+            .decode(encoded)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or_else(|| "Invalid cursor".to_string())?;
+        if cursor.s != sort || cursor.v.len() != keys.len() {
+            return Err("Cursor does not match the requested sort".to_string());
+        }
+        Ok(cursor)
+    }
+
+    /// Rows strictly after this cursor in the given order:
+    /// `(k1 > v1) OR (k1 = v1 AND k2 > v2) OR ... OR (k1 = v1 AND ... AND id > id0)`
+    /// with `>` flipped to `<` for descending keys.
+    fn predicate(&self, keys: &[SortKey]) -> Result<TaskPredicate, String> {
+        let invalid = || "Invalid cursor".to_string();
+        let mut clauses: Vec<TaskPredicate> = Vec::new();
+        let mut equal_prefix: Vec<(SortField, &Value)> = Vec::new();
+
+        let prefix_with = |prefix: &[(SortField, &Value)], last: TaskPredicate| -> Option<TaskPredicate> {
+            let mut clause = last;
+            for (field, value) in prefix {
+                clause = Box::new(field.compare(value, Comparison::Equal)?.and(clause));
+            }
+            Some(clause)
+        };
+
+        for (key, value) in keys.iter().zip(&self.v) {
+            let comparison = if key.descending {
+                Comparison::Before
+            } else {
+                Comparison::After
+            };
+            let step = key.field.compare(value, comparison).ok_or_else(invalid)?;
+            clauses.push(prefix_with(&equal_prefix, step).ok_or_else(invalid)?);
+            equal_prefix.push((key.field, value));
+        }
+        clauses.push(prefix_with(&equal_prefix, Box::new(tasks::id.gt(self.id))).ok_or_else(invalid)?);
+
+        let mut clauses = clauses.into_iter();
+        let first = clauses.next().ok_or_else(invalid)?;
+        Ok(clauses.fold(first, |acc, clause| Box::new(acc.or(clause))))
+    }
+}
+
+pub(crate) fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Tasks visible to `user_id` matching the query's filters, unsorted and unpaginated.
+pub fn filtered(user_id: Uuid, params: &TaskListQuery) -> tasks::BoxedQuery<'static, Pg> {
+    let mut query = tasks::table
+        .filter(access::visible_tasks(user_id))
+        .into_boxed();
+
+    if let Some(completed) = params.completed {
+        query = query.filter(tasks::completed.eq(completed));
+    }
+    if let Some(project_id) = params.project_id {
+        query = query.filter(tasks::project_id.eq(project_id));
+    }
+    if let Some(assignee) = params.assigned_to {
+        let assignee_id = match assignee {
+            AssigneeFilter::Me => user_id,
+            AssigneeFilter::User(id) => id,
+        };
+        query = query.filter(tasks::assignee_id.eq(assignee_id));
+    }
+    if let Some(parent_id) = params.parent_id {
+        query = query.filter(tasks::parent_id.eq(parent_id));
+    }
+    if params.top_level == Some(true) {
+        query = query.filter(tasks::parent_id.is_null());
+    }
+    if let Some(after) = params.created_after {
+        query = query.filter(tasks::created_at.ge(after));
+    }
+    if let Some(before) = params.created_before {
+        query = query.filter(tasks::created_at.lt(before));
+    }
+    if let Some(after) = params.updated_after {
+        query = query.filter(tasks::updated_at.ge(after));
+    }
+    if let Some(before) = params.updated_before {
+        query = query.filter(tasks::updated_at.lt(before));
+    }
+    if let Some(title) = params.title_contains.as_deref().filter(|t| !t.is_empty()) {
+        query = query.filter(tasks::title.ilike(format!("%{}%", escape_like(title))));
+    }
+    if let Some(tag) = params.tag.as_deref() {
+        let names: Vec<String> = normalize_names(&tag.split(',').collect::<Vec<_>>())
+            .iter()
+            .map(|name| name.to_lowercase())
+            .collect();
+        if !names.is_empty() {
+            let wanted = names.len() as i64;
+            // Collaborators tag shared tasks with their own tags, which
+            // aren't the requesting user's to filter by
+            let tagged = task_tags::table
+                .inner_join(tags::table)
+                .filter(tags::user_id.eq(user_id))
+                .filter(lower(tags::name).eq_any(names));
+            query = match params.tag_mode.unwrap_or_default() {
+                TagMatch::Any => query.filter(tasks::id.eq_any(tagged.select(task_tags::task_id))),
+                TagMatch::All => query.filter(
+                    tasks::id.eq_any(
+                        tagged
+                            .group_by(task_tags::task_id)
+                            .having(count(task_tags::tag_id).eq(wanted))
+                            .select(task_tags::task_id),
+                    ),
+                ),
+            };
+        }
+    }
+
+    query
+}
+
+/// A page of tasks plus everything needed to build the response.
+pub struct TaskPage {
+    pub tasks: Vec<Task>,
+    pub next_cursor: Option<String>,
+    pub total: Option<i64>,
+}
+
+pub fn load_page(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    params: &TaskListQuery,
+) -> Result<TaskPage, TaskQueryError> {
+    let sort = params.sort.clone().unwrap_or_else(|| DEFAULT_SORT.to_string());
+    let keys = parse_sort(&sort).map_err(TaskQueryError::InvalidParameter)?;
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let mut query = filtered(user_id, params);
+    if let Some(encoded) = params.cursor.as_deref().filter(|c| !c.is_empty()) {
+        let predicate = Cursor::decode(encoded, &sort, &keys)
+            .and_then(|cursor| cursor.predicate(&keys))
+            .map_err(TaskQueryError::InvalidParameter)?;
+        query = query.filter(predicate);
+    }
+
+    for key in &keys {
+        query = match (key.field, key.descending) {
+            (SortField::CreatedAt, false) => query.then_order_by(tasks::created_at.asc()),
+            (SortField::CreatedAt, true) => query.then_order_by(tasks::created_at.desc()),
+            (SortField::UpdatedAt, false) => query.then_order_by(tasks::updated_at.asc()),
+            (SortField::UpdatedAt, true) => query.then_order_by(tasks::updated_at.desc()),
+            (SortField::Title, false) => query.then_order_by(tasks::title.asc()),
+            (SortField::Title, true) => query.then_order_by(tasks::title.desc()),
+            (SortField::Completed, false) => query.then_order_by(tasks::completed.asc()),
+            (SortField::Completed, true) => query.then_order_by(tasks::completed.desc()),
+            (SortField::Priority, false) => query.then_order_by(tasks::priority.asc()),
+            (SortField::Priority, true) => query.then_order_by(tasks::priority.desc()),
+            (SortField::Position, false) => query.then_order_by(tasks::position.asc()),
+            (SortField::Position, true) => query.then_order_by(tasks::position.desc()),
+        };
+    }
+
+    // Fetch one extra row to learn whether another page follows
+    let mut tasks: Vec<Task> = query
+        .then_order_by(tasks::id.asc())
+        .limit(limit + 1)
+        .load(conn)?;
+
+    let next_cursor = if tasks.len() as i64 > limit {
+        tasks.truncate(limit as usize);
+        tasks
+            .last()
+            .map(|last| Cursor::for_task(&sort, &keys, last).encode())
+    } else {
+        None
+    };
+
+    let total = if params.include_total.unwrap_or(false) {
+        Some(filtered(user_id, params).count().get_result(conn)?)
+    } else {
+        None
+    };
+
+    Ok(TaskPage {
+        tasks,
+        next_cursor,
+        total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access::{self, Resource},
+        models::GrantRole,
+        projects,
+        test_support::{bearer, pool, send, task, user},
+    };
+    use actix_web::{http::StatusCode, test::TestRequest};
+    use serde_json::json;
+
+    fn key(field: SortField, descending: bool) -> SortKey {
+        SortKey { field, descending }
+    }
+
+    fn cursor(sort: &str, v: Vec<Value>) -> Cursor {
+        Cursor {
+            s: sort.to_string(),
+            v,
+            id: Uuid::nil(),
+        }
+    }
+
+    #[test]
+    fn parses_sort_specifications() {
+        assert_eq!(
+            parse_sort("-updated_at, +title,priority").unwrap(),
+            [
+                key(SortField::UpdatedAt, true),
+                key(SortField::Title, false),
+                key(SortField::Priority, false),
+            ]
+        );
+        assert_eq!(
+            parse_sort(" , ").unwrap(),
+            [key(SortField::CreatedAt, false)]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_and_repeated_sort_fields() {
+        assert_eq!(
+            parse_sort("due_at").unwrap_err(),
+            "Invalid sort field 'due_at'"
+        );
+        assert_eq!(
+            parse_sort("title,-title").unwrap_err(),
+            "Sort field 'title' is repeated"
+        );
+    }
+
+    #[test]
+    fn round_trips_cursors() {
+        let keys = parse_sort("-priority,title").unwrap();
+        let encoded = cursor("-priority,title", vec![json!("high"), json!("Write")]).encode();
+        assert!(!encoded.contains(['+', '/', '=']));
+
+        let decoded = Cursor::decode(&encoded, "-priority,title", &keys).unwrap();
+        assert_eq!(decoded.v, [json!("high"), json!("Write")]);
+        assert_eq!(decoded.id, Uuid::nil());
+        assert!(decoded.predicate(&keys).is_ok());
+    }
+
+    #[test]
+    fn rejects_cursors_for_another_sort() {
+        let keys = parse_sort("title").unwrap();
+        let encoded = cursor("-title", vec![json!("Write")]).encode();
+        assert_eq!(
+            Cursor::decode(&encoded, "title", &keys).unwrap_err(),
+            "Cursor does not match the requested sort"
+        );
+        let short = cursor("title", Vec::new()).encode();
+        assert!(Cursor::decode(&short, "title", &keys).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_cursors() {
+        let keys = parse_sort("title").unwrap();
+        for encoded in ["not base64!", "", &URL_SAFE_NO_PAD.encode(b"{\"s\":1}")] {
+            assert_eq!(
+                Cursor::decode(encoded, "title", &keys).unwrap_err(),
+                "Invalid cursor"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_cursor_values_of_the_wrong_type() {
+        let cases = [
+            ("created_at", json!("yesterday")),
+            ("title", json!(42)),
+            ("completed", json!("yes")),
+            ("priority", json!("critical")),
+            ("position", json!(null)),
+        ];
+        for (sort, value) in cases {
+            let keys = parse_sort(sort).unwrap();
+            let result = cursor(sort, vec![value]).predicate(&keys);
+            assert!(result.is_err(), "{}", sort);
+        }
+    }
+
+    #[test]
+    fn escapes_like_wildcards() {
+        assert_eq!(escape_like(r"100%_\"), r"100\%\_\\");
+    }
+
+    #[actix_web::test]
+    async fn tag_filter_ignores_collaborators_tags() {
+        let Some(pool) = pool() else { return };
+        let (owner, collaborator) = (user(&pool), user(&pool));
+        let inbox = {
+            let conn = &mut pool.get().unwrap();
+            let inbox = projects::inbox(conn, owner.id).unwrap();
+            access::grant(
+                conn,
+                Resource::Project(inbox.id),
+                collaborator.id,
+                GrantRole::Editor,
+                owner.id,
+            )
+            .unwrap();
+            inbox
+        };
+        task(
+            &pool,
+            collaborator.id,
+            json!({"title": "Shared", "tags": ["work"], "project_id": inbox.id}),
+        )
+        .await;
+        task(&pool, owner.id, json!({"title": "Own", "tags": ["work"]})).await;
+
+        let (status, body) = send(
+            &pool,
+            TestRequest::get()
+                .uri("/api/tasks/?tag=work")
+                .insert_header(bearer(owner.id)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let titles: Vec<_> = body["tasks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["title"].clone())
+            .collect();
+        assert_eq!(titles, vec![json!("Own")]);
+    }
+}
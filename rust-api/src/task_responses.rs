@@ -0,0 +1,37 @@
+//! Builds `TaskResponse`s, loading related rows with one query per relation
+//! for the whole batch rather than one per task.
+
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    models::{Task, TaskResponse},
+    comments, dependencies, subtasks, tags,
+};
+
+pub fn build(conn: &mut PgConnection, tasks: Vec<Task>) -> QueryResult<Vec<TaskResponse>> {
+    let tags = tags::load_for_tasks(conn, &tasks)?;
+    let ids: Vec<Uuid> = tasks.iter().map(|task| task.id).collect();
+    let progress = subtasks::progress_for(conn, &ids)?;
+    let blocked = dependencies::blocked_ids(conn, &ids)?;
+    let comment_counts = comments::counts_for(conn, &ids)?;
+    Ok(tasks
+        .into_iter()
+        .zip(tags)
+        .map(|(task, tags)| {
+            let task_progress = progress.get(&task.id).copied().unwrap_or_default();
+            let task_blocked = blocked.contains(&task.id);
+            let comment_count = comment_counts.get(&task.id).copied().unwrap_or(0);
+            TaskResponse::new(task, tags, task_progress, task_blocked, comment_count)
+        })
+        .collect())
+}
+
+pub fn build_one(conn: &mut PgConnection, task: Task) -> QueryResult<TaskResponse> {
+    build(conn, vec![task]).map(|responses| {
+        responses
+            .into_iter()
+            .next()
+            .expect("one response per task")
+    })
+}
@@ -0,0 +1,99 @@
+//! Day-based task views (today, upcoming, overdue) evaluated in the user's
+//! own time zone rather than the server's.
+
+use chrono::{DateTime, Days, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use log::warn;
+use uuid::Uuid;
+
+use crate::{
+    access,
+    models::Task,
+    schema::{tasks, users},
+};
+
+pub const DEFAULT_UPCOMING_DAYS: u64 = 7;
+pub const MAX_UPCOMING_DAYS: u64 = 90;
+
+#[derive(Debug, Clone, Copy)]
+pub enum DueView {
+    Today,
+    /// The `days` days after today
+    Upcoming { days: u64 },
+    Overdue,
+}
+
+/// The user's time zone, falling back to UTC if the stored name is unknown.
+pub fn user_timezone(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<Tz> {
+    let name: String = users::table
+        .filter(users::id.eq(user_id))
+        .select(users::timezone)
+        .first(conn)?;
+    Ok(name.parse().unwrap_or_else(|_| {
+        warn!("User {} has unknown time zone '{}', using UTC", user_id, name);
+        Tz::UTC
+    }))
+}
+
+/// First instant of `date` in `tz`. Days that begin inside a DST gap start at
+/// the first local time that exists.
+pub(crate) fn start_of_day(tz: Tz, date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_time(NaiveTime::MIN);
+    (0..96)
+        .map(|step| midnight + Duration::minutes(15 * step))
+        .find_map(|local| tz.from_local_datetime(&local).earliest())
+        .map(|start| start.with_timezone(&Utc))
+        .unwrap_or_else(|| midnight.and_utc())
+}
+
+pub struct DueTasks {
+    pub today: NaiveDate,
+    pub tasks: Vec<Task>,
+}
+
+/// Open tasks visible to `user_id` falling in `view`, earliest deadline first.
+/// All-day tasks count as due at the start of their day.
+pub fn load(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    tz: Tz,
+    view: DueView,
+    now: DateTime<Utc>,
+) -> QueryResult<DueTasks> {
+    let today = now.with_timezone(&tz).date_naive();
+    let query = tasks::table
+        .filter(access::visible_tasks(user_id))
+        .filter(tasks::completed.eq(false));
+
+    let mut tasks: Vec<Task> = match view {
+        DueView::Overdue => query
+            .filter(tasks::due_date.lt(today).or(tasks::due_at.lt(now)))
+            .load(conn)?,
+        DueView::Today | DueView::Upcoming { .. } => {
+            let (from, to) = match view {
+                DueView::Upcoming { days } => (today + Days::new(1), today + Days::new(1 + days)),
+                _ => (today, today + Days::new(1)),
+            };
+            query
+                .filter(
+                    tasks::due_date
+                        .ge(from)
+                        .and(tasks::due_date.lt(to))
+                        .or(tasks::due_at
+                            .ge(start_of_day(tz, from))
+                            .and(tasks::due_at.lt(start_of_day(tz, to)))),
+                )
+                .load(conn)?
+        }
+    };
+
+    tasks.sort_by_cached_key(|task| {
+        let due = task
+            .due_at
+            .or_else(|| task.due_date.map(|date| start_of_day(tz, date)));
+        (due, std::cmp::Reverse(task.priority), task.id)
+    });
+
+    Ok(DueTasks { today, tasks })
+}
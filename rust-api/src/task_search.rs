@@ -0,0 +1,296 @@
+//! Full-text search over task titles and descriptions, falling back to
+//! trigram similarity for short or misspelled queries.
+
+use std::collections::HashMap;
+
+use diesel::prelude::*;
+use diesel::sql_types;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    access,
+    models::{Task, TaskResponse, TaskSearchQuery},
+    schema::tasks,
+    task_responses,
+    task_query::{escape_like, TaskQueryError, DEFAULT_LIMIT, MAX_LIMIT},
+};
+
+pub const MAX_QUERY_LENGTH: usize = 256;
+// Shorter queries carry too little signal for stemming and go straight to trigrams
+pub const MIN_FULLTEXT_LENGTH: usize = 3;
+
+// Postgres marks matches with these control characters, which are stripped
+// from the text beforehand; `to_html` escapes the text and turns them into
+// <mark> tags
+const MARK_START: char = '\u{2}';
+const MARK_END: char = '\u{3}';
+const STRIP_MARKS_SQL: &str = "chr(2) || chr(3)";
+const TITLE_HEADLINE_OPTIONS: &str = "StartSel=\u{2}, StopSel=\u{3}, HighlightAll=true";
+const SNIPPET_HEADLINE_OPTIONS: &str =
+    "StartSel=\u{2}, StopSel=\u{3}, MaxWords=35, MinWords=15, MaxFragments=2, FragmentDelimiter=\" ... \"";
+const FUZZY_SNIPPET_LENGTH: i32 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchMode {
+    Fulltext,
+    Fuzzy,
+}
+
+#[derive(QueryableByName)]
+struct SearchHit {
+    #[diesel(sql_type = sql_types::Uuid)]
+    id: Uuid,
+    #[diesel(sql_type = sql_types::Float4)]
+    rank: f32,
+    #[diesel(sql_type = sql_types::Text)]
+    title_highlight: String,
+    #[diesel(sql_type = sql_types::Nullable<sql_types::Text>)]
+    snippet: Option<String>,
+    #[diesel(sql_type = sql_types::BigInt)]
+    total_count: i64,
+}
+
+#[derive(QueryableByName)]
+struct Exists {
+    #[diesel(sql_type = sql_types::Bool)]
+    found: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    pub task: TaskResponse,
+    pub rank: f32,
+    /// HTML-escaped, with matches wrapped in <mark>
+    pub title_highlight: String,
+    /// HTML-escaped, with matches wrapped in <mark>
+    pub snippet: Option<String>,
+}
+
+pub struct SearchPage {
+    pub mode: SearchMode,
+    pub results: Vec<SearchResult>,
+    pub total: i64,
+    pub next_offset: Option<i64>,
+}
+
+/// Escapes text for HTML, turning the match markers into <mark> tags.
+fn to_html(text: &str) -> String {
+    let mut html = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            MARK_START => html.push_str("<mark>"),
+            MARK_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+// Decided from the whole result set rather than the current page, so every
+// page of one query is served by the same strategy
+fn choose_mode(conn: &mut PgConnection, user_id: Uuid, q: &str) -> QueryResult<SearchMode> {
+    if q.chars().count() < MIN_FULLTEXT_LENGTH {
+        return Ok(SearchMode::Fuzzy);
+    }
+    let exists: Exists = diesel::sql_query(format!(
+        "SELECT EXISTS (
+             SELECT 1 FROM tasks t
+             WHERE {visible} AND t.search_vector @@ websearch_to_tsquery('english', $2)
+         ) AS found",
+        visible = access::VISIBLE_TASKS_SQL,
+    ))
+    .bind::<sql_types::Uuid, _>(user_id)
+    .bind::<sql_types::Text, _>(q)
+    .get_result(conn)?;
+    Ok(if exists.found {
+        SearchMode::Fulltext
+    } else {
+        SearchMode::Fuzzy
+    })
+}
+
+fn fulltext_hits(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    q: &str,
+    limit: i64,
+    offset: i64,
+) -> QueryResult<Vec<SearchHit>> {
+    diesel::sql_query(format!(
+        "SELECT t.id,
+                ts_rank_cd(t.search_vector, q.query) AS rank,
+                ts_headline('english', translate(t.title, {marks}, ''), q.query, '{title}')
+                    AS title_highlight,
+                CASE WHEN t.description IS NULL THEN NULL
+                     ELSE ts_headline(
+                         'english', translate(t.description, {marks}, ''), q.query, '{snippet}'
+                     )
+                END AS snippet,
+                COUNT(*) OVER () AS total_count
+         FROM tasks t, websearch_to_tsquery('english', $2) AS q(query)
+         WHERE {visible} AND t.search_vector @@ q.query
+         ORDER BY rank DESC, t.id
+         LIMIT $3 OFFSET $4",
+        visible = access::VISIBLE_TASKS_SQL,
+        marks = STRIP_MARKS_SQL,
+        title = TITLE_HEADLINE_OPTIONS,
+        snippet = SNIPPET_HEADLINE_OPTIONS,
+    ))
+    .bind::<sql_types::Uuid, _>(user_id)
+    .bind::<sql_types::Text, _>(q)
+    .bind::<sql_types::BigInt, _>(limit)
+    .bind::<sql_types::BigInt, _>(offset)
+    .load(conn)
+}
+
+fn fuzzy_hits(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    q: &str,
+    limit: i64,
+    offset: i64,
+) -> QueryResult<Vec<SearchHit>> {
+    // `%` and `<%` use pg_trgm's similarity thresholds; the ILIKE arm keeps
+    // one- and two-character queries useful
+    diesel::sql_query(format!(
+        "SELECT t.id,
+                GREATEST(similarity(t.title, $2), word_similarity($2, t.title)) AS rank,
+                translate(t.title, {marks}, '') AS title_highlight,
+                translate(left(t.description, $6), {marks}, '') AS snippet,
+                COUNT(*) OVER () AS total_count
+         FROM tasks t
+         WHERE {visible}
+           AND (t.title % $2 OR $2 <% t.title OR t.title ILIKE $3)
+         ORDER BY rank DESC, t.id
+         LIMIT $4 OFFSET $5",
+        visible = access::VISIBLE_TASKS_SQL,
+        marks = STRIP_MARKS_SQL,
+    ))
+    .bind::<sql_types::Uuid, _>(user_id)
+    .bind::<sql_types::Text, _>(q)
+    .bind::<sql_types::Text, _>(format!("%{}%", escape_like(q)))
+    .bind::<sql_types::BigInt, _>(limit)
+    .bind::<sql_types::BigInt, _>(offset)
+    .bind::<sql_types::Integer, _>(FUZZY_SNIPPET_LENGTH)
+    .load(conn)
+}
+
+pub fn search(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    params: &TaskSearchQuery,
+) -> Result<SearchPage, TaskQueryError> {
+    let q = params.q.trim();
+    if q.is_empty() {
+        return Err(TaskQueryError::InvalidParameter(
+            "Search query must not be empty".to_string(),
+        ));
+    }
+    if q.len() > MAX_QUERY_LENGTH {
+        return Err(TaskQueryError::InvalidParameter(format!(
+            "Search query must be at most {} bytes",
+            MAX_QUERY_LENGTH
+        )));
+    }
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let mode = choose_mode(conn, user_id, q)?;
+    let hits = match mode {
+        SearchMode::Fulltext => fulltext_hits(conn, user_id, q, limit, offset)?,
+        SearchMode::Fuzzy => fuzzy_hits(conn, user_id, q, limit, offset)?,
+    };
+
+    // An offset past the end yields no rows and so no window count
+    let total = hits.first().map(|hit| hit.total_count).unwrap_or(0);
+    let next_offset = Some(offset + hits.len() as i64).filter(|next| *next < total);
+
+    let ids: Vec<Uuid> = hits.iter().map(|hit| hit.id).collect();
+    let mut tasks_by_id: HashMap<Uuid, Task> = tasks::table
+        .filter(tasks::id.eq_any(&ids))
+        .load::<Task>(conn)?
+        .into_iter()
+        .map(|task| (task.id, task))
+        .collect();
+
+    let (hits, tasks): (Vec<SearchHit>, Vec<Task>) = hits
+        .into_iter()
+        .filter_map(|hit| tasks_by_id.remove(&hit.id).map(|task| (hit, task)))
+        .unzip();
+    let results = hits
+        .into_iter()
+        .zip(task_responses::build(conn, tasks)?)
+        .map(|(hit, task)| SearchResult {
+            task,
+            rank: hit.rank,
+            title_highlight: to_html(&hit.title_highlight),
+            snippet: hit.snippet.as_deref().map(to_html),
+        })
+        .collect();
+
+    Ok(SearchPage {
+        mode,
+        results,
+        total,
+        next_offset,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{pool, task, user};
+    use serde_json::json;
+
+    #[test]
+    fn escapes_text_and_marks_matches() {
+        assert_eq!(
+            to_html("<img src=x onerror=\"alert('1')\"> & \u{2}cat\u{3}"),
+            "&lt;img src=x onerror=&quot;alert(&#39;1&#39;)&quot;&gt; &amp; <mark>cat</mark>"
+        );
+    }
+
+    #[test]
+    fn escapes_markup_inside_matches() {
+        assert_eq!(
+            to_html("\u{2}<b>\u{3} & 'x'"),
+            "<mark>&lt;b&gt;</mark> &amp; &#39;x&#39;"
+        );
+    }
+
+    #[actix_web::test]
+    async fn uses_fulltext_only_when_it_finds_something() {
+        let Some(pool) = pool() else { return };
+        let (owner, stranger) = (user(&pool), user(&pool));
+        task(&pool, owner.id, json!({"title": "Deploy the cluster"})).await;
+        task(&pool, stranger.id, json!({"title": "Water the geraniums"})).await;
+        let conn = &mut pool.get().unwrap();
+
+        assert_eq!(
+            choose_mode(conn, owner.id, "deploying clusters").unwrap(),
+            SearchMode::Fulltext
+        );
+        // Too short for stemming
+        assert_eq!(
+            choose_mode(conn, owner.id, "cl").unwrap(),
+            SearchMode::Fuzzy
+        );
+        // Misspelled
+        assert_eq!(
+            choose_mode(conn, owner.id, "clustr").unwrap(),
+            SearchMode::Fuzzy
+        );
+        // Only matches a task the user can't see
+        assert_eq!(
+            choose_mode(conn, owner.id, "geraniums").unwrap(),
+            SearchMode::Fuzzy
+        );
+    }
+}
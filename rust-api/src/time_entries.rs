@@ -0,0 +1,469 @@
+//! Time tracked on tasks: the timer, entries logged by hand, and the per-task,
+//! per-day and CSV views built from them. A user runs at most one timer at a
+//! time, and running timers count up to the current time.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Days, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use uuid::Uuid;
+
+use crate::{
+    models::{
+        CreateTimeEntryRequest, DailyTimeTotal, NewTimeEntry, TaskTimeSummary, TimeEntry,
+        TimeEntryListQuery, TimeEntryResponse, TimeRangeQuery, User, UserSummary, UserTimeTotal,
+    },
+    schema::{projects, tasks, time_entries, users},
+    task_query::{DEFAULT_LIMIT, MAX_LIMIT},
+    task_schedule::start_of_day,
+};
+
+/// Longest range of days the daily totals and the report cover.
+pub const MAX_RANGE_DAYS: u64 = 366;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TimeEntryError {
+    /// The entry does not exist or belongs to someone else; maps to 404.
+    #[error("Time entry not found")]
+    NotFound,
+    /// Times or a range that make no sense; maps to 400.
+    #[error("{0}")]
+    Invalid(String),
+    /// A timer is already running, none is, or entries overlap; maps to 409.
+    #[error("{0}")]
+    Conflict(String),
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+}
+
+// Locks the user's row so that their timer and entries change one request at
+// a time
+fn lock_user(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<()> {
+    users::table
+        .find(user_id)
+        .select(users::id)
+        .for_update()
+        .first::<Uuid>(conn)?;
+    Ok(())
+}
+
+/// The user's running timer, if any.
+pub fn running(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<Option<TimeEntry>> {
+    time_entries::table
+        .filter(time_entries::user_id.eq(user_id))
+        .filter(time_entries::stopped_at.is_null())
+        .first(conn)
+        .optional()
+}
+
+/// Starts the user's timer on a task the caller may edit.
+pub fn start(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    task_id: Uuid,
+    note: Option<String>,
+) -> Result<TimeEntry, TimeEntryError> {
+    lock_user(conn, user_id)?;
+    if let Some(entry) = running(conn, user_id)? {
+        return Err(TimeEntryError::Conflict(if entry.task_id == task_id {
+            "A timer is already running on this task".to_string()
+        } else {
+            "A timer is already running on another task; stop it first".to_string()
+        }));
+    }
+
+    let new_entry = NewTimeEntry {
+        task_id,
+        user_id,
+        started_at: Utc::now(),
+        stopped_at: None,
+        note,
+    };
+    diesel::insert_into(time_entries::table)
+        .values(&new_entry)
+        .get_result(conn)
+        .map_err(|e| match e {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                TimeEntryError::Conflict("A timer is already running".to_string())
+            }
+            e => TimeEntryError::Database(e),
+        })
+}
+
+/// Stops the user's timer on the task. Needs no access to the task, so a
+/// timer can still be stopped after the task was unshared.
+pub fn stop(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    task_id: Uuid,
+) -> Result<TimeEntry, TimeEntryError> {
+    lock_user(conn, user_id)?;
+    let entry = running(conn, user_id)?
+        .filter(|entry| entry.task_id == task_id)
+        .ok_or_else(|| TimeEntryError::Conflict("No timer is running on this task".to_string()))?;
+
+    // Stopping in the same instant, or after the clock stepped back, still
+    // leaves an entry that ends after it starts
+    let stopped_at = Utc::now().max(entry.started_at + Duration::microseconds(1));
+    Ok(diesel::update(time_entries::table.find(entry.id))
+        .set(time_entries::stopped_at.eq(stopped_at))
+        .get_result(conn)?)
+}
+
+/// Logs a finished stretch of work by hand. It may not overlap the user's
+/// other entries, including a running timer.
+pub fn create(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    request: &CreateTimeEntryRequest,
+) -> Result<TimeEntry, TimeEntryError> {
+    if request.stopped_at > Utc::now() {
+        return Err(TimeEntryError::Invalid(
+            "stopped_at cannot be in the future".to_string(),
+        ));
+    }
+
+    lock_user(conn, user_id)?;
+    let overlapping: i64 = time_entries::table
+        .filter(time_entries::user_id.eq(user_id))
+        .filter(time_entries::started_at.lt(request.stopped_at))
+        .filter(
+            time_entries::stopped_at
+                .is_null()
+                .or(time_entries::stopped_at.gt(request.started_at)),
+        )
+        .count()
+        .get_result(conn)?;
+    if overlapping > 0 {
+        return Err(TimeEntryError::Conflict(
+            "The entry overlaps another of your time entries".to_string(),
+        ));
+    }
+
+    let new_entry = NewTimeEntry {
+        task_id: request.task_id,
+        user_id,
+        started_at: request.started_at,
+        stopped_at: Some(request.stopped_at),
+        note: request.note.clone(),
+    };
+    Ok(diesel::insert_into(time_entries::table)
+        .values(&new_entry)
+        .get_result(conn)?)
+}
+
+/// Deletes one of the user's own entries, running or not.
+pub fn delete(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    entry_id: Uuid,
+) -> Result<(), TimeEntryError> {
+    let deleted = diesel::delete(
+        time_entries::table
+            .filter(time_entries::id.eq(entry_id))
+            .filter(time_entries::user_id.eq(user_id)),
+    )
+    .execute(conn)?;
+    match deleted {
+        0 => Err(TimeEntryError::NotFound),
+        _ => Ok(()),
+    }
+}
+
+// The day after `date`, which the last representable date doesn't have
+fn day_after(date: NaiveDate) -> Result<NaiveDate, TimeEntryError> {
+    date.checked_add_days(Days::new(1))
+        .ok_or_else(|| TimeEntryError::Invalid(format!("{} is out of range", date)))
+}
+
+// The instants a range of days in `tz` starts and ends at
+fn range_bounds(
+    tz: Tz,
+    range: &TimeRangeQuery,
+) -> Result<(DateTime<Utc>, DateTime<Utc>), TimeEntryError> {
+    if range.to < range.from {
+        return Err(TimeEntryError::Invalid(
+            "to cannot be before from".to_string(),
+        ));
+    }
+    let too_long = range
+        .from
+        .checked_add_days(Days::new(MAX_RANGE_DAYS))
+        .is_some_and(|limit| range.to >= limit);
+    if too_long {
+        return Err(TimeEntryError::Invalid(format!(
+            "A range covers at most {} days",
+            MAX_RANGE_DAYS
+        )));
+    }
+    Ok((
+        start_of_day(tz, range.from),
+        start_of_day(tz, day_after(range.to)?),
+    ))
+}
+
+pub struct TimeEntryPage {
+    pub entries: Vec<TimeEntryResponse>,
+    pub total: i64,
+    pub next_offset: Option<i64>,
+}
+
+/// The user's own entries, latest first, optionally for one task or started
+/// within days `from` to `to` in `tz`.
+pub fn list(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    tz: Tz,
+    params: &TimeEntryListQuery,
+) -> Result<TimeEntryPage, TimeEntryError> {
+    if matches!((params.from, params.to), (Some(from), Some(to)) if to < from) {
+        return Err(TimeEntryError::Invalid(
+            "to cannot be before from".to_string(),
+        ));
+    }
+    let end = params.to.map(day_after).transpose()?;
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let filtered = || {
+        let mut query = time_entries::table
+            .filter(time_entries::user_id.eq(user_id))
+            .into_boxed::<Pg>();
+        if let Some(task_id) = params.task_id {
+            query = query.filter(time_entries::task_id.eq(task_id));
+        }
+        if let Some(from) = params.from {
+            query = query.filter(time_entries::started_at.ge(start_of_day(tz, from)));
+        }
+        if let Some(end) = end {
+            query = query.filter(time_entries::started_at.lt(start_of_day(tz, end)));
+        }
+        query
+    };
+
+    let total: i64 = filtered().count().get_result(conn)?;
+    let entries: Vec<TimeEntry> = filtered()
+        .order((time_entries::started_at.desc(), time_entries::id.desc()))
+        .limit(limit)
+        .offset(offset)
+        .load(conn)?;
+
+    let now = Utc::now();
+    let next_offset = (offset + (entries.len() as i64) < total).then_some(offset + limit);
+    Ok(TimeEntryPage {
+        entries: entries
+            .into_iter()
+            .map(|entry| TimeEntryResponse::new(entry, now))
+            .collect(),
+        total,
+        next_offset,
+    })
+}
+
+/// Time logged on the task by everyone, with a total per user, most first.
+pub fn task_summary(conn: &mut PgConnection, task_id: Uuid) -> QueryResult<TaskTimeSummary> {
+    let rows: Vec<(TimeEntry, User)> = time_entries::table
+        .inner_join(users::table)
+        .filter(time_entries::task_id.eq(task_id))
+        .select((TimeEntry::as_select(), User::as_select()))
+        .load(conn)?;
+
+    let now = Utc::now();
+    let mut summary = TaskTimeSummary {
+        task_id,
+        total_seconds: 0,
+        entries: rows.len() as i64,
+        running: 0,
+        users: Vec::new(),
+    };
+    let mut per_user: HashMap<Uuid, UserTimeTotal> = HashMap::new();
+    for (entry, user) in rows {
+        if entry.stopped_at.is_none() {
+            summary.running += 1;
+        }
+        let seconds = TimeEntryResponse::new(entry, now).duration_seconds;
+        summary.total_seconds += seconds;
+        per_user
+            .entry(user.id)
+            .or_insert_with(|| UserTimeTotal {
+                user: UserSummary::from(user),
+                total_seconds: 0,
+            })
+            .total_seconds += seconds;
+    }
+    summary.users = per_user.into_values().collect();
+    summary.users.sort_by(|a, b| {
+        b.total_seconds
+            .cmp(&a.total_seconds)
+            .then_with(|| a.user.username.cmp(&b.user.username))
+    });
+    Ok(summary)
+}
+
+/// The user's logged time on every day of the range in `tz`. Entries that
+/// cross midnight are split between the days they cover.
+pub fn daily(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    tz: Tz,
+    range: &TimeRangeQuery,
+) -> Result<Vec<DailyTimeTotal>, TimeEntryError> {
+    let (start, end) = range_bounds(tz, range)?;
+    let entries: Vec<TimeEntry> = time_entries::table
+        .filter(time_entries::user_id.eq(user_id))
+        .filter(time_entries::started_at.lt(end))
+        .filter(
+            time_entries::stopped_at
+                .is_null()
+                .or(time_entries::stopped_at.gt(start)),
+        )
+        .load(conn)?;
+
+    let mut totals: BTreeMap<NaiveDate, i64> = range
+        .from
+        .iter_days()
+        .take_while(|date| *date <= range.to)
+        .map(|date| (date, 0))
+        .collect();
+    let now = Utc::now();
+    for entry in entries {
+        let mut from = entry.started_at.max(start);
+        let until = entry.stopped_at.unwrap_or(now).min(end);
+        while from < until {
+            let date = from.with_timezone(&tz).date_naive();
+            let next_day = start_of_day(tz, day_after(date)?).min(until);
+            if let Some(total) = totals.get_mut(&date) {
+                *total += (next_day - from).num_seconds();
+            }
+            from = next_day;
+        }
+    }
+
+    Ok(totals
+        .into_iter()
+        .map(|(date, total_seconds)| DailyTimeTotal {
+            date,
+            total_seconds,
+        })
+        .collect())
+}
+
+// Spreadsheets run cells starting with these as formulas
+fn csv_text(value: &str) -> String {
+    if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// CSV of the user's entries started within the range in `tz`, oldest first.
+pub fn report(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    tz: Tz,
+    range: &TimeRangeQuery,
+) -> Result<Vec<u8>, TimeEntryError> {
+    let (start, end) = range_bounds(tz, range)?;
+    let rows: Vec<(TimeEntry, String, String)> = time_entries::table
+        .inner_join(tasks::table.inner_join(projects::table))
+        .filter(time_entries::user_id.eq(user_id))
+        .filter(time_entries::started_at.ge(start))
+        .filter(time_entries::started_at.lt(end))
+        .order((time_entries::started_at.asc(), time_entries::id.asc()))
+        .select((TimeEntry::as_select(), projects::name, tasks::title))
+        .load(conn)?;
+
+    let now = Utc::now();
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "date",
+        "started_at",
+        "stopped_at",
+        "duration_seconds",
+        "hours",
+        "project",
+        "task_id",
+        "task",
+        "note",
+    ])?;
+    for (entry, project, title) in rows {
+        let entry = TimeEntryResponse::new(entry, now);
+        writer.write_record([
+            entry.started_at.with_timezone(&tz).date_naive().to_string(),
+            entry.started_at.to_rfc3339(),
+            entry
+                .stopped_at
+                .map(|stopped_at| stopped_at.to_rfc3339())
+                .unwrap_or_default(),
+            entry.duration_seconds.to_string(),
+            format!("{:.2}", entry.duration_seconds as f64 / 3600.0),
+            csv_text(&project),
+            entry.task_id.to_string(),
+            csv_text(&title),
+            csv_text(entry.note.as_deref().unwrap_or_default()),
+        ])?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| TimeEntryError::Csv(e.into_error().into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(from: NaiveDate, to: NaiveDate) -> TimeRangeQuery {
+        TimeRangeQuery { from, to }
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn range_bounds_cover_whole_days_in_the_zone() {
+        let (start, end) = range_bounds(
+            chrono_tz::Europe::Berlin,
+            &range(date(2024, 3, 1), date(2024, 3, 31)),
+        )
+        .unwrap();
+        assert_eq!(start.to_rfc3339(), "2024-02-29T23:00:00+00:00");
+        // The range ends after the switch to summer time
+        assert_eq!(end.to_rfc3339(), "2024-03-31T22:00:00+00:00");
+    }
+
+    #[test]
+    fn range_bounds_reject_reversed_and_long_ranges() {
+        let tz = chrono_tz::UTC;
+        assert!(matches!(
+            range_bounds(tz, &range(date(2024, 2, 1), date(2024, 1, 31))),
+            Err(TimeEntryError::Invalid(_))
+        ));
+        assert!(range_bounds(tz, &range(date(2024, 1, 1), date(2024, 12, 31))).is_ok());
+        assert!(matches!(
+            range_bounds(tz, &range(date(2024, 1, 1), date(2025, 1, 1))),
+            Err(TimeEntryError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn range_bounds_reject_the_last_date_instead_of_panicking() {
+        let tz = chrono_tz::UTC;
+        assert!(matches!(
+            range_bounds(tz, &range(NaiveDate::MAX, NaiveDate::MAX)),
+            Err(TimeEntryError::Invalid(_))
+        ));
+        let from = NaiveDate::MAX - Days::new(10);
+        assert!(matches!(
+            range_bounds(tz, &range(from, NaiveDate::MAX)),
+            Err(TimeEntryError::Invalid(_))
+        ));
+        assert!(range_bounds(tz, &range(from, from)).is_ok());
+    }
+}
@@ -0,0 +1,518 @@
+//! The trash: deleted tasks and users keep their rows, left out everywhere
+//! else, until they are restored or purged once the retention period has
+//! passed. Everything deleted in one transaction shares its `deleted_at`,
+//! which is how a restore finds what was deleted along with a task or user.
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::dsl::now;
+use diesel::prelude::*;
+use diesel::sql_types;
+use log::{error, info};
+use once_cell::sync::Lazy;
+use uuid::Uuid;
+
+use crate::{
+    access, attachments,
+    models::{
+        GrantRole, Project, Task, TrashQuery, TrashedTaskResponse, TrashedUserResponse, User,
+    },
+    projects as user_projects,
+    schema::{projects, tasks, users},
+    subtasks::{self, SubtaskError},
+    task_events::{self, Changes},
+    task_positions,
+    task_query::{DEFAULT_LIMIT, MAX_LIMIT},
+    task_responses, DbPool,
+};
+
+/// Policy loaded from the environment at startup.
+pub static POLICY: Lazy<TrashPolicy> =
+    Lazy::new(|| TrashPolicy::from_env().expect("Invalid trash policy configuration"));
+
+#[derive(Debug)]
+pub struct TrashPolicy {
+    /// How long deleted tasks and users are kept before they are purged
+    pub retention: Duration,
+    /// How often the purge job runs
+    pub purge_interval: std::time::Duration,
+}
+
+impl TrashPolicy {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let days = match std::env::var("TRASH_RETENTION_DAYS") {
+            Ok(value) => value
+                .parse::<i64>()
+                .ok()
+                .filter(|days| *days >= 0)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("TRASH_RETENTION_DAYS must be a non-negative integer")
+                })?,
+            Err(_) => 30,
+        };
+
+        let minutes = match std::env::var("TRASH_PURGE_INTERVAL_MINUTES") {
+            Ok(value) => value
+                .parse::<u64>()
+                .ok()
+                .filter(|minutes| *minutes > 0)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("TRASH_PURGE_INTERVAL_MINUTES must be a positive integer")
+                })?,
+            Err(_) => 60,
+        };
+
+        Ok(Self {
+            retention: Duration::days(days),
+            purge_interval: std::time::Duration::from_secs(minutes * 60),
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TrashError {
+    /// Not in the trash, or not the user's to restore; maps to 404.
+    #[error("not found")]
+    NotFound,
+    /// The item can't come back on its own yet; maps to 409.
+    #[error("{0}")]
+    Conflict(String),
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+#[derive(QueryableByName)]
+struct IdRow {
+    #[diesel(sql_type = sql_types::Uuid)]
+    id: Uuid,
+}
+
+fn purge_at(deleted_at: DateTime<Utc>) -> DateTime<Utc> {
+    deleted_at + POLICY.retention
+}
+
+/// Moves a task to the trash, handling its subtasks as configured. The task
+/// leaves its series, so a restored occurrence comes back as a one-off task.
+pub fn delete_task(
+    conn: &mut PgConnection,
+    task: &Task,
+    actor_id: Uuid,
+) -> Result<(), SubtaskError> {
+    subtasks::on_parent_delete(conn, task, actor_id)?;
+    diesel::update(tasks::table.find(task.id))
+        .set((
+            tasks::deleted_at.eq(now),
+            tasks::series_id.eq(None::<Uuid>),
+            tasks::occurrence_at.eq(None::<DateTime<Utc>>),
+        ))
+        .execute(conn)?;
+    task_events::deleted(conn, task, actor_id)?;
+    Ok(())
+}
+
+/// Moves the tasks of a project that is about to be deleted to the trash,
+/// together with those already there. They move to the project owner's inbox,
+/// where a restore brings them back, and leave their series, which goes with
+/// the project.
+pub fn delete_project(
+    conn: &mut PgConnection,
+    project: &Project,
+    actor_id: Uuid,
+) -> QueryResult<()> {
+    let inbox = user_projects::inbox(conn, project.user_id)?;
+    let in_project = || tasks::table.filter(tasks::project_id.eq(project.id));
+
+    let trashed: Vec<Task> = diesel::update(in_project().filter(tasks::deleted_at.is_null()))
+        .set((
+            tasks::deleted_at.eq(now),
+            tasks::series_id.eq(None::<Uuid>),
+            tasks::occurrence_at.eq(None::<DateTime<Utc>>),
+        ))
+        .get_results(conn)?;
+    for task in &trashed {
+        task_events::deleted(conn, task, actor_id)?;
+    }
+
+    let moved: Vec<Uuid> = diesel::update(in_project())
+        .set((
+            tasks::project_id.eq(inbox.id),
+            tasks::series_id.eq(None::<Uuid>),
+            tasks::occurrence_at.eq(None::<DateTime<Utc>>),
+        ))
+        .returning(tasks::id)
+        .get_results(conn)?;
+    for id in moved {
+        let mut changes = Changes::default();
+        changes.set("project_id", project.id, inbox.id);
+        task_events::updated(conn, id, changes, actor_id)?;
+    }
+    Ok(())
+}
+
+/// Loads a deleted task the user owns.
+pub fn find_task(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    task_id: Uuid,
+) -> Result<Task, TrashError> {
+    let task: Task = tasks::table
+        .find(task_id)
+        .filter(tasks::deleted_at.is_not_null())
+        .first(conn)
+        .optional()?
+        .ok_or(TrashError::NotFound)?;
+    match access::task_role(conn, user_id, &task)? {
+        Some(GrantRole::Owner) => Ok(task),
+        _ => Err(TrashError::NotFound),
+    }
+}
+
+/// Restores a deleted task with the subtasks deleted along with it. A subtask
+/// only comes back under a parent that isn't in the trash, and joins its
+/// parent's project. The task goes to the end of its list; the subtasks
+/// restored with it keep their order under it.
+pub fn restore_task(
+    conn: &mut PgConnection,
+    task: &Task,
+    actor_id: Uuid,
+) -> Result<Task, TrashError> {
+    let owner_deleted = users::table
+        .find(task.user_id)
+        .select(users::deleted_at)
+        .first::<Option<DateTime<Utc>>>(conn)?
+        .is_some();
+    if owner_deleted {
+        return Err(TrashError::Conflict(
+            "The task's owner has been deleted; restore the user instead".to_string(),
+        ));
+    }
+
+    let project_id = match task.parent_id {
+        Some(parent_id) => tasks::table
+            .find(parent_id)
+            .filter(tasks::deleted_at.is_null())
+            .select(tasks::project_id)
+            .first(conn)
+            .optional()?
+            .ok_or_else(|| TrashError::Conflict("Restore the parent task first".to_string()))?,
+        None => task.project_id,
+    };
+
+    let position = task_positions::append(conn, project_id, task.parent_id)?;
+    let mut ids = subtasks::trashed_with(conn, task)?;
+    ids.push(task.id);
+    let restored_ids: Vec<Uuid> = diesel::update(tasks::table.filter(tasks::id.eq_any(&ids)))
+        .set((
+            tasks::deleted_at.eq(None::<DateTime<Utc>>),
+            tasks::project_id.eq(project_id),
+        ))
+        .returning(tasks::id)
+        .get_results(conn)?;
+    for restored_id in restored_ids {
+        task_events::restored(conn, restored_id, actor_id)?;
+    }
+    Ok(diesel::update(tasks::table.find(task.id))
+        .set(tasks::position.eq(position))
+        .get_result(conn)?)
+}
+
+pub struct TrashPage<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub next_offset: Option<i64>,
+}
+
+/// Deleted tasks the user owns, most recently deleted first. Subtasks that
+/// went to the trash with their parent are left out; they come back with it.
+pub fn list_tasks(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    params: &TrashQuery,
+) -> QueryResult<TrashPage<TrashedTaskResponse>> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let trash = || {
+        let own_projects = projects::table
+            .filter(projects::user_id.eq(user_id))
+            .select(projects::id);
+        let parents = diesel::alias!(tasks as parents);
+        let live_parents = parents
+            .filter(parents.field(tasks::deleted_at).is_null())
+            .select(parents.field(tasks::id).nullable());
+        tasks::table
+            .filter(tasks::deleted_at.is_not_null())
+            .filter(
+                tasks::user_id
+                    .eq(user_id)
+                    .or(tasks::project_id.eq_any(own_projects)),
+            )
+            .filter(
+                tasks::parent_id
+                    .is_null()
+                    .or(tasks::parent_id.eq_any(live_parents)),
+            )
+    };
+    let total: i64 = trash().count().get_result(conn)?;
+    let trashed: Vec<Task> = trash()
+        .order((tasks::deleted_at.desc(), tasks::id.asc()))
+        .limit(limit)
+        .offset(offset)
+        .load(conn)?;
+
+    let deleted_at: Vec<DateTime<Utc>> = trashed
+        .iter()
+        .map(|task| task.deleted_at.unwrap_or_default())
+        .collect();
+    let items = task_responses::build(conn, trashed)?
+        .into_iter()
+        .zip(deleted_at)
+        .map(|(task, deleted_at)| TrashedTaskResponse {
+            task,
+            deleted_at,
+            purge_at: purge_at(deleted_at),
+        })
+        .collect();
+
+    let next_offset = Some(offset + limit).filter(|next| *next < total);
+    Ok(TrashPage {
+        items,
+        total,
+        next_offset,
+    })
+}
+
+// The user's tasks, the tasks in their projects and the subtasks of all of
+// these, among the tasks whose `deleted_at` is the given one (`None` for
+// tasks not in the trash)
+fn user_task_ids(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    deleted_at: Option<DateTime<Utc>>,
+) -> QueryResult<Vec<Uuid>> {
+    let rows: Vec<IdRow> = diesel::sql_query(
+        "WITH RECURSIVE owned AS (
+             SELECT id FROM tasks
+             WHERE deleted_at IS NOT DISTINCT FROM $2
+               AND (user_id = $1 OR project_id IN (SELECT id FROM projects WHERE user_id = $1))
+             UNION
+             SELECT t.id FROM tasks t JOIN owned o ON t.parent_id = o.id
+             WHERE t.deleted_at IS NOT DISTINCT FROM $2
+         )
+         SELECT id FROM owned",
+    )
+    .bind::<sql_types::Uuid, _>(user_id)
+    .bind::<sql_types::Nullable<sql_types::Timestamptz>, _>(deleted_at)
+    .load(conn)?;
+    Ok(rows.into_iter().map(|row| row.id).collect())
+}
+
+/// Moves a user to the trash with their tasks, the tasks in their projects and
+/// the subtasks of all of these.
+pub fn delete_user(conn: &mut PgConnection, user_id: Uuid) -> QueryResult<()> {
+    let deleted_at: Option<DateTime<Utc>> = diesel::update(users::table.find(user_id))
+        .set(users::deleted_at.eq(now))
+        .returning(users::deleted_at)
+        .get_result(conn)?;
+
+    let ids = user_task_ids(conn, user_id, None)?;
+    let trashed: Vec<Task> = diesel::update(tasks::table.filter(tasks::id.eq_any(&ids)))
+        .set(tasks::deleted_at.eq(deleted_at))
+        .get_results(conn)?;
+    for task in &trashed {
+        task_events::deleted(conn, task, user_id)?;
+    }
+    Ok(())
+}
+
+/// Loads a deleted user.
+pub fn find_user(conn: &mut PgConnection, user_id: Uuid) -> Result<User, TrashError> {
+    users::table
+        .find(user_id)
+        .filter(users::deleted_at.is_not_null())
+        .first(conn)
+        .optional()?
+        .ok_or(TrashError::NotFound)
+}
+
+/// Restores a deleted user with the tasks deleted along with them. Each
+/// restored task whose parent stays where it was goes to the end of its list,
+/// in the order the tasks had.
+pub fn restore_user(conn: &mut PgConnection, user: &User, actor_id: Uuid) -> QueryResult<User> {
+    let ids = user_task_ids(conn, user.id, user.deleted_at)?;
+    let mut restored: Vec<Task> = diesel::update(tasks::table.filter(tasks::id.eq_any(&ids)))
+        .set(tasks::deleted_at.eq(None::<DateTime<Utc>>))
+        .get_results(conn)?;
+    restored.sort_by(|a, b| (&a.position, a.id).cmp(&(&b.position, b.id)));
+    for task in &restored {
+        if task.parent_id.is_none_or(|parent_id| !ids.contains(&parent_id)) {
+            let position = task_positions::append(conn, task.project_id, task.parent_id)?;
+            diesel::update(tasks::table.find(task.id))
+                .set(tasks::position.eq(position))
+                .execute(conn)?;
+        }
+        task_events::restored(conn, task.id, actor_id)?;
+    }
+    diesel::update(users::table.find(user.id))
+        .set(users::deleted_at.eq(None::<DateTime<Utc>>))
+        .get_result(conn)
+}
+
+/// Deleted users, most recently deleted first.
+pub fn list_users(
+    conn: &mut PgConnection,
+    params: &TrashQuery,
+) -> QueryResult<TrashPage<TrashedUserResponse>> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let trash = users::table.filter(users::deleted_at.is_not_null());
+    let total: i64 = trash.count().get_result(conn)?;
+    let trashed: Vec<User> = trash
+        .order((users::deleted_at.desc(), users::id.asc()))
+        .limit(limit)
+        .offset(offset)
+        .load(conn)?;
+
+    let items = trashed
+        .into_iter()
+        .map(|user| {
+            let deleted_at = user.deleted_at.unwrap_or_default();
+            TrashedUserResponse {
+                user: user.into(),
+                deleted_at,
+                purge_at: purge_at(deleted_at),
+            }
+        })
+        .collect();
+
+    let next_offset = Some(offset + limit).filter(|next| *next < total);
+    Ok(TrashPage {
+        items,
+        total,
+        next_offset,
+    })
+}
+
+/// Permanently removes the tasks and users deleted longer ago than the
+/// retention period; returns the number of rows removed. Foreign keys take
+/// everything that belonged to them along, except the tasks in a purged
+/// user's projects, which are removed here first.
+pub fn purge(conn: &mut PgConnection) -> QueryResult<usize> {
+    let cutoff = Utc::now() - POLICY.retention;
+    let purged_users = users::table
+        .filter(users::deleted_at.lt(cutoff))
+        .select(users::id);
+    let purged_projects = projects::table
+        .filter(projects::user_id.eq_any(purged_users))
+        .select(projects::id);
+    let tasks = diesel::delete(
+        tasks::table.filter(
+            tasks::deleted_at
+                .lt(cutoff)
+                .or(tasks::project_id.eq_any(purged_projects)),
+        ),
+    )
+    .execute(conn)?;
+    let users = diesel::delete(users::table.filter(users::deleted_at.lt(cutoff))).execute(conn)?;
+    Ok(tasks + users)
+}
+
+/// Runs `purge` every `POLICY.purge_interval` in the background, sweeping the
+/// blobs of purged attachments afterwards.
+pub fn spawn_purge_job(pool: DbPool) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(POLICY.purge_interval);
+        loop {
+            interval.tick().await;
+            let job_pool = pool.clone();
+            let result = actix_web::web::block(move || -> anyhow::Result<usize> {
+                let conn = &mut job_pool.get()?;
+                Ok(purge(conn)?)
+            })
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|purged| purged);
+            match result {
+                Ok(0) => {}
+                Ok(purged) => {
+                    info!("Purged {} rows from the trash", purged);
+                    attachments::spawn_sweep(pool.clone());
+                }
+                Err(e) => error!("Failed to purge the trash: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bearer, pool, send, task, user};
+    use actix_web::{http::StatusCode, test::TestRequest};
+    use serde_json::json;
+
+    fn id(task: &serde_json::Value) -> Uuid {
+        serde_json::from_value(task["id"].clone()).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn restoring_a_user_brings_back_only_their_tasks() {
+        let Some(pool) = pool() else { return };
+        let (owner, collaborator) = (user(&pool), user(&pool));
+        let inbox = {
+            let conn = &mut pool.get().unwrap();
+            let inbox = user_projects::inbox(conn, owner.id).unwrap();
+            access::grant(
+                conn,
+                access::Resource::Project(inbox.id),
+                collaborator.id,
+                GrantRole::Editor,
+                owner.id,
+            )
+            .unwrap();
+            inbox
+        };
+        let contributed = task(
+            &pool,
+            collaborator.id,
+            json!({"title": "Contributed", "project_id": inbox.id}),
+        )
+        .await;
+        let child = task(
+            &pool,
+            owner.id,
+            json!({"title": "Child", "parent_id": contributed["id"]}),
+        )
+        .await;
+        // The test runs in one transaction, so this task is trashed with the
+        // same `deleted_at` as the user
+        let unrelated = task(&pool, owner.id, json!({"title": "Unrelated"})).await;
+        let (status, _) = send(
+            &pool,
+            TestRequest::delete()
+                .uri(&format!("/api/tasks/{}", id(&unrelated)))
+                .insert_header(bearer(owner.id)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        delete_user(&mut pool.get().unwrap(), collaborator.id).unwrap();
+        let later = task(&pool, owner.id, json!({"title": "Later"})).await;
+
+        let conn = &mut pool.get().unwrap();
+        let deleted = find_user(conn, collaborator.id).unwrap();
+        restore_user(conn, &deleted, owner.id).unwrap();
+
+        let mut load = |task| tasks::table.find(id(task)).first::<Task>(conn).unwrap();
+        let (contributed, child, unrelated, later) = (
+            load(&contributed),
+            load(&child),
+            load(&unrelated),
+            load(&later),
+        );
+        assert!(contributed.deleted_at.is_none() && child.deleted_at.is_none());
+        assert!(unrelated.deleted_at.is_some());
+        // Back at the end of the inbox, after the task added meanwhile
+        assert!(contributed.position > later.position);
+    }
+}
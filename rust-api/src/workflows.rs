@@ -0,0 +1,425 @@
+//! Per-project workflows: the statuses a task moves through, in board order,
+//! and the transitions allowed between them. A task is completed while its
+//! status is done. The database keeps the two in step (see the
+//! `sync_task_status` trigger), so code that only writes `completed` moves
+//! the task to the first status of that kind.
+
+use std::collections::{HashMap, HashSet};
+
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    access,
+    models::{
+        BoardColumn, BoardQuery, NewProjectStatus, ProjectStatus, ProjectStatusResponse,
+        StatusTransition, Task, WorkflowRequest,
+    },
+    schema::{project_status_transitions, project_statuses, tasks},
+    task_events::{self, Changes},
+    task_query::{DEFAULT_LIMIT, MAX_LIMIT},
+    task_responses,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum WorkflowError {
+    /// The request names a status that doesn't exist or can't be used;
+    /// maps to 400.
+    #[error("{0}")]
+    Invalid(String),
+    /// The workflow doesn't allow the change; maps to 409.
+    #[error("{0}")]
+    Conflict(String),
+    #[error(transparent)]
+    Database(#[from] diesel::result::Error),
+}
+
+/// The project's statuses in board order, each with where it may lead.
+pub fn statuses(
+    conn: &mut PgConnection,
+    project_id: Uuid,
+) -> QueryResult<Vec<ProjectStatusResponse>> {
+    let statuses: Vec<ProjectStatus> = project_statuses::table
+        .filter(project_statuses::project_id.eq(project_id))
+        .order((project_statuses::position.asc(), project_statuses::id.asc()))
+        .load(conn)?;
+    let ids: Vec<Uuid> = statuses.iter().map(|status| status.id).collect();
+    let transitions: Vec<StatusTransition> = project_status_transitions::table
+        .filter(project_status_transitions::from_status_id.eq_any(&ids))
+        .load(conn)?;
+
+    let mut targets: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+    for transition in transitions {
+        targets
+            .entry(transition.from_status_id)
+            .or_default()
+            .push(transition.to_status_id);
+    }
+    let board_order: HashMap<Uuid, usize> = ids
+        .iter()
+        .enumerate()
+        .map(|(index, id)| (*id, index))
+        .collect();
+
+    Ok(statuses
+        .into_iter()
+        .map(|status| {
+            let mut transitions_to = targets.remove(&status.id).unwrap_or_default();
+            transitions_to.sort_by_key(|id| board_order.get(id).copied());
+            ProjectStatusResponse {
+                id: status.id,
+                name: status.name,
+                done: status.done,
+                position: status.position,
+                transitions_to,
+            }
+        })
+        .collect())
+}
+
+/// A status of `project_id`; statuses of other projects are invalid.
+pub fn find_status(
+    conn: &mut PgConnection,
+    project_id: Uuid,
+    status_id: Uuid,
+) -> Result<ProjectStatus, WorkflowError> {
+    project_statuses::table
+        .find(status_id)
+        .filter(project_statuses::project_id.eq(project_id))
+        .first(conn)
+        .optional()?
+        .ok_or_else(|| {
+            WorkflowError::Invalid("Status is not part of the task's project".to_string())
+        })
+}
+
+fn first_status(
+    conn: &mut PgConnection,
+    project_id: Uuid,
+    done: bool,
+) -> QueryResult<ProjectStatus> {
+    project_statuses::table
+        .filter(project_statuses::project_id.eq(project_id))
+        .filter(project_statuses::done.eq(done))
+        .order((project_statuses::position.asc(), project_statuses::id.asc()))
+        .first(conn)
+}
+
+/// The status an update moves `task` to, if any: `status_id` when given,
+/// otherwise the first status of the kind `completed` asks for, both in
+/// `project_id`, where the task will be. A move within the task's project
+/// must be one of its transitions; a move to another project is not.
+pub fn target_status(
+    conn: &mut PgConnection,
+    task: &Task,
+    project_id: Uuid,
+    status_id: Option<Uuid>,
+    completed: Option<bool>,
+) -> Result<Option<ProjectStatus>, WorkflowError> {
+    let target = match (status_id, completed) {
+        (Some(status_id), _) => find_status(conn, project_id, status_id)?,
+        (None, Some(completed)) if completed != task.completed => {
+            first_status(conn, project_id, completed)?
+        }
+        _ => return Ok(None),
+    };
+    if target.id == task.status_id {
+        return Ok(None);
+    }
+
+    if project_id == task.project_id {
+        let allowed: i64 = project_status_transitions::table
+            .filter(project_status_transitions::from_status_id.eq(task.status_id))
+            .filter(project_status_transitions::to_status_id.eq(target.id))
+            .count()
+            .get_result(conn)?;
+        if allowed == 0 {
+            let current: String = project_statuses::table
+                .find(task.status_id)
+                .select(project_statuses::name)
+                .first(conn)?;
+            return Err(WorkflowError::Conflict(format!(
+                "Tasks cannot move from '{}' to '{}'",
+                current, target.name
+            )));
+        }
+    }
+    Ok(Some(target))
+}
+
+/// Replaces the project's workflow. Statuses are matched to the current ones
+/// by name; a status that is dropped must not hold any tasks. Tasks the
+/// change moves are recorded in their history as changed by `actor_id`.
+pub fn replace(
+    conn: &mut PgConnection,
+    project_id: Uuid,
+    workflow: &WorkflowRequest,
+    actor_id: Uuid,
+) -> Result<Vec<ProjectStatusResponse>, WorkflowError> {
+    let mut index_by_name: HashMap<String, usize> = HashMap::new();
+    for (index, status) in workflow.statuses.iter().enumerate() {
+        let name = status.name.trim();
+        if name.is_empty() {
+            return Err(WorkflowError::Invalid(
+                "Status names cannot be blank".to_string(),
+            ));
+        }
+        if index_by_name.insert(name.to_lowercase(), index).is_some() {
+            return Err(WorkflowError::Invalid(format!(
+                "Status '{}' is listed twice",
+                name
+            )));
+        }
+    }
+    let has_done = workflow.statuses.iter().any(|status| status.done);
+    let has_open = workflow.statuses.iter().any(|status| !status.done);
+    if !(has_done && has_open) {
+        return Err(WorkflowError::Invalid(
+            "A workflow needs at least one open and one done status".to_string(),
+        ));
+    }
+
+    let mut edges: HashSet<(usize, usize)> = HashSet::new();
+    for transition in &workflow.transitions {
+        let index_of = |name: &str| {
+            index_by_name
+                .get(&name.trim().to_lowercase())
+                .copied()
+                .ok_or_else(|| {
+                    WorkflowError::Invalid(format!("Unknown status '{}' in transitions", name))
+                })
+        };
+        let edge = (index_of(&transition.from)?, index_of(&transition.to)?);
+        if edge.0 == edge.1 {
+            return Err(WorkflowError::Invalid(format!(
+                "Status '{}' cannot transition to itself",
+                transition.from
+            )));
+        }
+        edges.insert(edge);
+    }
+
+    let existing: Vec<ProjectStatus> = project_statuses::table
+        .filter(project_statuses::project_id.eq(project_id))
+        .load(conn)?;
+    let (kept, dropped): (Vec<ProjectStatus>, Vec<ProjectStatus>) = existing
+        .into_iter()
+        .partition(|status| index_by_name.contains_key(&status.name.to_lowercase()));
+
+    for status in &dropped {
+        let live: i64 = tasks::table
+            .filter(tasks::status_id.eq(status.id))
+            .filter(tasks::deleted_at.is_null())
+            .count()
+            .get_result(conn)?;
+        if live > 0 {
+            return Err(WorkflowError::Conflict(format!(
+                "Move the tasks in '{}' to another status first",
+                status.name
+            )));
+        }
+    }
+
+    let mut kept: HashMap<String, ProjectStatus> = kept
+        .into_iter()
+        .map(|status| (status.name.to_lowercase(), status))
+        .collect();
+    let mut ids = Vec::with_capacity(workflow.statuses.len());
+    for (position, status) in workflow.statuses.iter().enumerate() {
+        let name = status.name.trim();
+        let position = position as i32;
+        let id = match kept.remove(&name.to_lowercase()) {
+            Some(current) => {
+                diesel::update(project_statuses::table.find(current.id))
+                    .set((
+                        project_statuses::name.eq(name),
+                        project_statuses::done.eq(status.done),
+                        project_statuses::position.eq(position),
+                    ))
+                    .execute(conn)?;
+                // Tasks in a status that changed kind are completed or reopened with it
+                if current.done != status.done {
+                    let changed: Vec<Uuid> =
+                        diesel::update(tasks::table.filter(tasks::status_id.eq(current.id)))
+                            .set(tasks::completed.eq(status.done))
+                            .returning(tasks::id)
+                            .get_results(conn)?;
+                    for task_id in changed {
+                        let mut changes = Changes::default();
+                        changes.set("completed", current.done, status.done);
+                        task_events::updated(conn, task_id, changes, actor_id)?;
+                    }
+                }
+                current.id
+            }
+            None => diesel::insert_into(project_statuses::table)
+                .values(&NewProjectStatus {
+                    project_id,
+                    name: name.to_string(),
+                    done: status.done,
+                    position,
+                })
+                .returning(project_statuses::id)
+                .get_result(conn)?,
+        };
+        ids.push(id);
+    }
+
+    // Tasks in the trash keep a place in the workflow for when they are
+    // restored: the first new status of the same kind, which always exists
+    for status in &dropped {
+        let Some(replacement) = workflow
+            .statuses
+            .iter()
+            .position(|new_status| new_status.done == status.done)
+            .map(|index| ids[index])
+        else {
+            continue;
+        };
+        let moved: Vec<Uuid> = diesel::update(tasks::table.filter(tasks::status_id.eq(status.id)))
+            .set(tasks::status_id.eq(replacement))
+            .returning(tasks::id)
+            .get_results(conn)?;
+        for task_id in moved {
+            let mut changes = Changes::default();
+            changes.set("status_id", status.id, replacement);
+            task_events::updated(conn, task_id, changes, actor_id)?;
+        }
+    }
+    let dropped_ids: Vec<Uuid> = dropped.iter().map(|status| status.id).collect();
+    diesel::delete(project_statuses::table.filter(project_statuses::id.eq_any(&dropped_ids)))
+        .execute(conn)?;
+
+    diesel::delete(
+        project_status_transitions::table
+            .filter(project_status_transitions::from_status_id.eq_any(&ids)),
+    )
+    .execute(conn)?;
+    let transitions: Vec<StatusTransition> = edges
+        .into_iter()
+        .map(|(from, to)| StatusTransition {
+            from_status_id: ids[from],
+            to_status_id: ids[to],
+        })
+        .collect();
+    diesel::insert_into(project_status_transitions::table)
+        .values(&transitions)
+        .execute(conn)?;
+
+    Ok(statuses(conn, project_id)?)
+}
+
+/// The project's tasks visible to the user, a column per status in board
+/// order, each in manual order.
+pub fn board(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    project_id: Uuid,
+    params: &BoardQuery,
+) -> QueryResult<Vec<BoardColumn>> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let include_subtasks = params.include_subtasks.unwrap_or(false);
+    let column = |status_id: Uuid| {
+        let query = tasks::table
+            .filter(access::visible_tasks(user_id))
+            .filter(tasks::status_id.eq(status_id))
+            .into_boxed();
+        if include_subtasks {
+            query
+        } else {
+            query.filter(tasks::parent_id.is_null())
+        }
+    };
+
+    let mut columns = Vec::new();
+    for status in statuses(conn, project_id)? {
+        let total: i64 = column(status.id).count().get_result(conn)?;
+        let tasks: Vec<Task> = column(status.id)
+            .order((tasks::position.asc(), tasks::id.asc()))
+            .limit(limit)
+            .load(conn)?;
+        columns.push(BoardColumn {
+            status,
+            tasks: task_responses::build(conn, tasks)?,
+            total,
+        });
+    }
+    Ok(columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        schema::task_events as events,
+        test_support::{bearer, pool, send, task, user},
+    };
+    use actix_web::{http::StatusCode, test::TestRequest};
+    use serde_json::{json, Value};
+
+    // The events other than the task's creation, as (type, changes, actor)
+    fn history(conn: &mut PgConnection, task: &Value) -> Vec<(String, Value, Option<Uuid>)> {
+        let task_id: Uuid = serde_json::from_value(task["id"].clone()).unwrap();
+        events::table
+            .filter(events::task_id.eq(task_id))
+            .filter(events::event_type.ne(task_events::EVENT_CREATED))
+            .select((events::event_type, events::changes, events::actor_id))
+            .load(conn)
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn replacing_a_workflow_records_the_tasks_it_moves() {
+        let Some(pool) = pool() else { return };
+        let user = user(&pool);
+        let open = task(&pool, user.id, json!({"title": "Open"})).await;
+        let trashed = task(&pool, user.id, json!({"title": "Trashed"})).await;
+        let project_id = open["project_id"].as_str().unwrap().to_string();
+        let send_as_user =
+            |request: TestRequest| send(&pool, request.insert_header(bearer(user.id)));
+
+        let (_, workflow) =
+            send_as_user(TestRequest::get().uri(&format!("/api/projects/{}/workflow", project_id)))
+                .await;
+        let in_progress = workflow["statuses"][1]["id"].clone();
+        assert_eq!(workflow["statuses"][1]["name"], "in_progress");
+        let trashed_uri = format!("/api/tasks/{}", trashed["id"].as_str().unwrap());
+        let (status, _) = send_as_user(
+            TestRequest::patch()
+                .uri(&trashed_uri)
+                .set_json(json!({"status_id": in_progress})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_as_user(TestRequest::delete().uri(&trashed_uri)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // `todo` becomes a done status, `in_progress` and `review` are dropped
+        let (status, workflow) = send_as_user(
+            TestRequest::put()
+                .uri(&format!("/api/projects/{}/workflow", project_id))
+                .set_json(json!({
+                    "statuses": [{"name": "backlog"}, {"name": "todo", "done": true}, {"name": "done", "done": true}],
+                })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let backlog = workflow["statuses"][0]["id"].clone();
+
+        let conn = &mut pool.get().unwrap();
+        assert_eq!(
+            history(conn, &open),
+            vec![(
+                task_events::EVENT_COMPLETED.to_string(),
+                json!({"completed": {"from": false, "to": true}}),
+                Some(user.id)
+            )]
+        );
+        // Alongside its earlier move to `in_progress` and to the trash
+        assert!(history(conn, &trashed).contains(&(
+            task_events::EVENT_TRANSITIONED.to_string(),
+            json!({"status_id": {"from": in_progress, "to": backlog}}),
+            Some(user.id)
+        )));
+    }
+}